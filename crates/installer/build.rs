@@ -25,6 +25,6 @@ fn main() {
             },
             "../../LICENSE",
         ],
-        ExecutionLevel::RequireAdministrator,
+        ExecutionLevel::HighestAvailable,
     );
 }
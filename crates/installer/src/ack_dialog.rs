@@ -1,5 +1,5 @@
-use std::{io::Error, ptr::null_mut};
-use wedge_lib::{win32::*, *};
+use std::ptr::null_mut;
+use wedge_lib::{error::WedgeError, win32::*, *};
 use winapi::{
     shared::{
         minwindef::{FALSE, LPARAM, LRESULT, UINT, WPARAM},
@@ -41,7 +41,7 @@ const ID_SYSLINK: i32 = 1000;
 static mut USER_ACK_RESPONSE: bool = false;
 
 /// Displays user acknowledgement dialog and returns user response
-pub fn display() -> Result<bool, Error> {
+pub fn display() -> Result<bool, WedgeError> {
     unsafe {
         // Load common control classes
         init_common_controls()?;
@@ -58,7 +58,7 @@ pub fn display() -> Result<bool, Error> {
             0 as _,
         ) < 0
         {
-            Err(Error::last_os_error())
+            Err(WedgeError::last_os_error("open the install prompt"))
         } else {
             // Safely return user response
             Ok(USER_ACK_RESPONSE)
@@ -1,6 +1,8 @@
-use std::{io::Error, ptr::null_mut};
+use std::ptr::null_mut;
 use wedge_lib::{
-    install::{install, STEP_COUNT, STEP_INTERVAL},
+    error::WedgeError,
+    install::{install_runner, Scope, STEP_INTERVAL, SUCCESS_MESSAGE},
+    plan::Runner,
     win32::*,
     *,
 };
@@ -29,9 +31,20 @@ const INSTALL_DIALOG: LPWSTR = MAKEINTRESOURCE!(101);
 const ID_PROGRESS: i32 = 1001;
 const ID_RICH_EDIT: i32 = 1002;
 
+// Timer stepping through the install plan
+const STEP_TIMER: usize = 1;
+
+// Runner shared between dialog messages
+static mut RUNNER: Option<Runner<'static>> = None;
+
+// Who the dialog installs for
+static mut SCOPE: Scope = Scope::Machine;
+
 /// Displays install progress dialog and returns success
-pub fn display() -> Result<(), Error> {
+pub fn display(scope: Scope) -> Result<(), WedgeError> {
     unsafe {
+        SCOPE = scope;
+
         // Load common control classes
         init_common_controls()?;
 
@@ -44,7 +57,7 @@ pub fn display() -> Result<(), Error> {
             0 as _,
         ) < 0
         {
-            Err(Error::last_os_error())
+            Err(WedgeError::last_os_error("open the install dialog"))
         } else {
             // Safely return user response
             Ok(())
@@ -87,16 +100,27 @@ unsafe extern "system" fn dialog_proc(
             // Get our progress bar
             let hprogress = GetDlgItem(hwnd, ID_PROGRESS);
 
-            // Set the range and increment of the progress bar
-            // Use increment of 10 to allow smooth animation
-            SendMessageW(hprogress, PBM_SETRANGE, 0, STEP_COUNT << 16);
-            SendMessageW(hprogress, PBM_SETSTEP, 1, 0);
-
-            // First step
-            SendMessageW(hprogress, PBM_STEPIT, 0, 0);
-
-            // Start installer steps
-            SetTimer(hwnd, 1, STEP_INTERVAL, None);
+            // Plan the install
+            match install_runner(SCOPE) {
+                Ok(runner) => {
+                    // Set the range and increment of the progress bar
+                    SendMessageW(
+                        hprogress,
+                        PBM_SETRANGE,
+                        0,
+                        (runner.step_count() as isize) << 16,
+                    );
+                    SendMessageW(hprogress, PBM_SETSTEP, 1, 0);
+                    RUNNER = Some(runner);
+
+                    // Start installer steps
+                    SetTimer(hwnd, STEP_TIMER, STEP_INTERVAL, None);
+                }
+                Err(e) => {
+                    SendMessageW(hprogress, PBM_SETSTATE, PBST_ERROR as _, 0);
+                    log(hwnd, &e.to_string());
+                }
+            }
             true as _
         }
 
@@ -107,47 +131,43 @@ unsafe extern "system" fn dialog_proc(
             // Kill timer otherwise we'll start repeating steps
             KillTimer(hwnd, wparam);
 
+            let runner = match RUNNER.as_mut() {
+                Some(runner) => runner,
+                None => return true as _,
+            };
+
             // Run installer step and log error or success message
-            let mut log = match install(wparam) {
-                Ok(msg) => {
-                    // Start timer to execute next step, as long as there is a next step
-                    if wparam + 1 < STEP_COUNT as usize {
-                        SetTimer(hwnd, wparam + 1, STEP_INTERVAL, None);
-                    }
+            match runner.step() {
+                Ok(Some(msg)) => {
+                    // Start timer to execute next step
+                    SetTimer(hwnd, STEP_TIMER, STEP_INTERVAL, None);
 
                     // Move progress bar forward a step
                     SendMessageW(hprogress, PBM_STEPIT, 0, 0);
 
                     // Log success message to user
-                    format!(
-                        "Step {}/{}\r\n\t{}",
-                        wparam,
-                        STEP_COUNT - 1,
-                        msg.replace("\n", "\r\n\t")
-                    )
+                    log(
+                        hwnd,
+                        &format!(
+                            "Step {}/{}\r\n\t{}",
+                            runner.steps_done(),
+                            runner.step_count(),
+                            msg
+                        ),
+                    );
                 }
+                Ok(None) => log(hwnd, &SUCCESS_MESSAGE.replace("\n", "\r\n")),
                 Err(e) => {
                     // Set progress bar to error state
                     SendMessageW(hprogress, PBM_SETSTATE, PBST_ERROR as _, 0);
 
-                    // Log error
-                    format!("Error on step {}/{} {}", wparam, STEP_COUNT - 1, e)
+                    // Log error, then undo the steps already done
+                    log(hwnd, &e.to_string());
+                    for line in runner.rollback() {
+                        log(hwnd, &line);
+                    }
                 }
-            };
-            log.push_str("\r\n");
-
-            // Log message to user
-            let htext = GetDlgItem(hwnd, ID_RICH_EDIT);
-            SendMessageW(htext, EM_SETSEL as _, 0, isize::max_value());
-            SendMessageW(
-                htext,
-                EM_SETSEL as _,
-                usize::max_value(),
-                isize::max_value(),
-            );
-            SendMessageW(htext, EM_REPLACESEL as _, 0, TEXT!(&log) as _);
-            SendMessageW(htext, EM_SCROLL as _, SB_PAGEDOWN as _, 0);
-            HideCaret(htext);
+            }
             true as _
         }
 
@@ -159,3 +179,19 @@ unsafe extern "system" fn dialog_proc(
         _ => false as _,
     }
 }
+
+/// Appends a line to the log shown to the user
+unsafe fn log(hwnd: HWND, line: &str) {
+    let line = format!("{}\r\n", line);
+    let htext = GetDlgItem(hwnd, ID_RICH_EDIT);
+    SendMessageW(htext, EM_SETSEL as _, 0, isize::max_value());
+    SendMessageW(
+        htext,
+        EM_SETSEL as _,
+        usize::max_value(),
+        isize::max_value(),
+    );
+    SendMessageW(htext, EM_REPLACESEL as _, 0, TEXT!(&line) as _);
+    SendMessageW(htext, EM_SCROLL as _, SB_PAGEDOWN as _, 0);
+    HideCaret(htext);
+}
@@ -4,22 +4,46 @@
 mod ack_dialog;
 mod install_dialog;
 use std::{env, process::exit};
-use wedge_lib::install::{install, STEP_COUNT};
+use wedge_lib::{
+    install::{install_runner, Scope, SUCCESS_MESSAGE},
+    win32::{attach_console, is_elevated},
+};
 
 #[cfg(windows)]
 fn main() {
+    // Install for just the current user when asked to or when not an administrator
+    let scope = if env::args().any(|a| a == r"/user" || a == r"-user") || !is_elevated() {
+        Scope::User
+    } else {
+        Scope::Machine
+    };
+
+    // List what installing would change without changing anything
+    if env::args().any(|a| a == r"/dryrun" || a == r"-dryrun") {
+        attach_console();
+        match install_runner(scope) {
+            Ok(runner) => {
+                for line in runner.preview() {
+                    println!("{}", line);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+    }
     // Run a silent install
-    if env::args().any(|a| a == r"/quiet" || a == r"-quiet") {
-        for i in 0..STEP_COUNT {
-            match install(i as usize) {
-                Ok(msg) => println!(
-                    "Step {}/{}\n    {}",
-                    i,
-                    STEP_COUNT - 1,
-                    msg.replace("\n", "\n    ")
-                ),
-                Err(e) => panic!("Error on step {}/{} {}", i, STEP_COUNT - 1, e),
-            };
+    else if env::args().any(|a| a == r"/quiet" || a == r"-quiet") {
+        attach_console();
+        let result =
+            install_runner(scope).and_then(|mut runner| runner.run(|line| println!("{}", line)));
+        match result {
+            Ok(()) => println!("{}", SUCCESS_MESSAGE),
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(1);
+            }
         }
     }
     // Run attended install
@@ -27,7 +51,7 @@ fn main() {
         // User must acknowledge prompt
         if ack_dialog::display().unwrap() {
             // Install dialog will handle install
-            install_dialog::display().unwrap();
+            install_dialog::display(scope).unwrap();
         }
     }
     exit(0);
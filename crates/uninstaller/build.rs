@@ -6,6 +6,6 @@ fn main() {
         "wedge.uninstaller",
         vec![],
         vec![],
-        ExecutionLevel::RequireAdministrator,
+        ExecutionLevel::HighestAvailable,
     );
 }
@@ -3,12 +3,12 @@
 
 use std::{
     env::args,
-    io::Error,
     mem::size_of,
     ptr::{null, null_mut},
 };
 use wedge_lib::{
-    install::{uninstall, UNINSTALLER_NAME},
+    error::WedgeError,
+    install::{uninstall, uninstall_preview, Scope, UNINSTALLER_NAME},
     win32::*,
     *,
 };
@@ -26,8 +26,23 @@ use winapi::{
 /// Entry
 #[cfg(windows)]
 fn main() {
+    // List what uninstalling would change without changing anything
+    if args().any(|arg| arg == "/dryrun" || arg == "-dryrun") {
+        attach_console();
+        match uninstall_preview(scope()) {
+            Ok(lines) => {
+                for line in lines {
+                    println!("{}", line);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
     // Run uninstall and notify user of any error
-    if let Err(e) = uninstall_proc() {
+    else if let Err(e) = uninstall_proc() {
         unsafe {
             MessageBoxExW(
                 null_mut(),
@@ -40,13 +55,22 @@ fn main() {
     }
 }
 
+/// Per-user installs register the uninstaller with "/user"
+fn scope() -> Scope {
+    if args().any(|arg| arg == "/user" || arg == "-user") {
+        Scope::User
+    } else {
+        Scope::Machine
+    }
+}
+
 /// Runs the uninstallation procedure
-fn uninstall_proc() -> Result<(), Error> {
+fn uninstall_proc() -> Result<(), WedgeError> {
     // If program is executed with "run-uninstall" command line arg that means it
     // was already copied to a temp location where it can safely delete the original
     // files. Proceed with uninstallation. Ignore as many errors as possible.
     if args().any(|arg| arg == "run-uninstall") {
-        uninstall()?;
+        uninstall(scope())?;
     }
     // Ask user if they want to proceed with uninstallation and if they do copy the uninstaller
     // executable to a temp location and execute with "run-uninstall" command line arg
@@ -71,7 +95,9 @@ fn uninstall_proc() -> Result<(), Error> {
 
                 // Make a copy of this uninstaller in the %temp% file overwriting any copies
                 if CopyFileW(TEXT!(&source.to_str().unwrap()), TEXT!(target_str), FALSE) == 0 {
-                    return Err(Error::last_os_error());
+                    return Err(WedgeError::last_os_error(
+                        "copy the uninstaller to the temp folder",
+                    ));
                 }
 
                 // Movefile to empty so the uninstaller will eventually be deleted
@@ -104,10 +130,15 @@ fn uninstall_proc() -> Result<(), Error> {
                     hStdError: null_mut(),
                 };
 
-                // Invoke the temp uninstaller with command line args "run-uninstall"
+                // Invoke the temp uninstaller with command line args "run-uninstall", passing on
+                // which install to remove
+                let command_line = match scope() {
+                    Scope::Machine => "run-uninstall",
+                    Scope::User => "run-uninstall /user",
+                };
                 if CreateProcessW(
                     TEXT!(target_str),
-                    TEXT!("run-uninstall"),
+                    TEXT!(command_line),
                     null_mut(),
                     null_mut(),
                     FALSE,
@@ -118,7 +149,7 @@ fn uninstall_proc() -> Result<(), Error> {
                     &mut pi,
                 ) == 0
                 {
-                    return Err(Error::last_os_error());
+                    return Err(WedgeError::last_os_error("start the uninstaller"));
                 }
             }
         }
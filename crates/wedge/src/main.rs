@@ -1,52 +1,369 @@
 // Don't use console on release builds
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod uri;
-use std::{env, path::PathBuf, process::Command};
+use std::{env, fs, path::Path, process::exit};
 use wedge_lib::{
-    browser::{get_default_browser, Browser},
-    install::{MSEDGE_PATH, MSEDGE_PROXY_PATH},
-    win32::shell_execute,
+    browser::SystemBrowserProbe,
+    cli::{
+        help, parse_command, status_text, Command, ConfigCommand, EXIT_FAILURE, EXIT_OK,
+        EXIT_USAGE, VERSION,
+    },
+    cmdline::CommandLine,
+    config::{get_setting, read_config_file, set_setting, Config, ConfigPaths},
+    deflect::{decide, Decision, Env},
+    doctor::doctor,
+    edge::{discover, known_folders, EdgeInstall},
+    explain::{explain, explain_argv},
+    fallback::{
+        append_log, link_fallbacks, log_path, open, pass_through_fallbacks, try_in_order, GUARD_VAR,
+    },
+    install::{InstallPaths, Scope},
+    pause::{current_pause, pause, resume, SystemClock},
+    policy::write_templates,
+    registry::SystemRegistry,
+    repair::{installed_proxy, repair},
+    system::WindowsSystem,
+    win32::{
+        attach_console, error_box, get_command_line, get_self_location, info_box, is_elevated,
+        shell_execute_elevated,
+    },
 };
 
-/// Entry
+/// Loads settings, logging any files or policies that had to be skipped
 #[cfg(windows)]
-fn main() {
-    let is_running_as_debugger = env::args().nth(1).unwrap_or_default() == MSEDGE_PATH;
+fn load_config() -> Config {
+    let mut warnings = vec![];
+    let mut config = Config::load_all(&WindowsSystem, &ConfigPaths::locate(), &mut |line: &str| {
+        warnings.push(String::from(line))
+    });
+    if config.logging {
+        for warning in &warnings {
+            append_log(warning);
+        }
+    }
+    config.paused = current_pause(&SystemRegistry, &SystemClock).is_some();
+    config
+}
 
-    if is_running_as_debugger {
-        let edge_args: Vec<String> = env::args().into_iter().skip(2).collect();
+/// Per-user installs and channels installed after wedge have no proxy, nor an IFEO filter it is
+/// needed to get around
+#[cfg(windows)]
+fn use_proxy_if_present(env: &mut Env) {
+    if !Path::new(&env.msedge_proxy_path).exists() {
+        env.msedge_proxy_path = env.msedge_path.clone();
+    }
+}
+
+/// Handles a launch of edge, or the `microsoft-edge:` url wedge was given as the protocol
+/// handler, from the command line wedge was launched with minus its own path. Fails when
+/// nothing could be opened.
+#[cfg(windows)]
+fn deflect(command_line: &CommandLine, mut env: Env) -> Result<(), String> {
+    let config = load_config();
+    let mut log = |line: &str| {
+        if config.logging {
+            append_log(line);
+        }
+    };
 
-        let default_browser = get_default_browser().unwrap_or(Browser::Unknown);
+    // Edge updates can take away the proxy the machine install made, so put it back when we're
+    // allowed to
+    let proxy_taken = !Path::new(&env.msedge_proxy_path).exists()
+        && InstallPaths::for_scope(Scope::Machine)
+            .map(|paths| {
+                installed_proxy(
+                    &WindowsSystem,
+                    &paths.manifest_path(),
+                    &env.msedge_proxy_path,
+                )
+            })
+            .unwrap_or(false);
+    if proxy_taken && is_elevated() {
+        if let Err(e) = repair(Scope::Machine) {
+            log(&format!(
+                "Couldn't put back {}: {}",
+                env.msedge_proxy_path, e
+            ));
+        }
+    }
+
+    use_proxy_if_present(&mut env);
+
+    // A wedge started by one of our own fallbacks doesn't take them again, so it can't loop
+    let guarded = env::var_os(GUARD_VAR).is_some();
+
+    let result = match decide(command_line, &env, &config, &SystemBrowserProbe) {
+        // Launch browsers directly where we can so the url can't bounce back to edge
+        Decision::OpenUrl(links) => links.iter().fold(Ok(()), |result, link| {
+            let fallbacks = link_fallbacks(&SystemRegistry, &env, link, guarded, &mut log);
+            result.and(try_in_order(&fallbacks, open, &mut log))
+        }),
+        // Call msedge with the same args it would have originally been called with
+        Decision::PassThrough { program, args } => try_in_order(
+            &pass_through_fallbacks(&env, &program, &args, guarded),
+            open,
+            &mut log,
+        ),
+        Decision::Block => Ok(()),
+    };
+    if let Err(ref e) = result {
+        log(&format!("Giving up: {}", e));
+    }
+    result
+}
+
+/// Shows the status summary, in a message box when there's no console to print it to
+#[cfg(windows)]
+fn run_status() -> ! {
+    let has_console = attach_console();
+    let report = match doctor(None) {
+        Ok(report) => report,
+        Err(e) => fail(format!("Can't check the install: {}", e)),
+    };
+    let text = status_text(&report, &load_config(), &log_path().to_string_lossy());
+    if has_console {
+        println!("{}", text);
+    } else {
+        info_box("Wedge", &text);
+    }
+    exit(report.exit_code());
+}
+
+/// Runs `wedge open <url>`, opening the url as if edge had been asked to
+#[cfg(windows)]
+fn run_open(url: &str, edges: &[EdgeInstall]) -> ! {
+    attach_console();
+    let env = Env::invoked(&[], edges);
+    let command_line = CommandLine::from_args(&[env.msedge_path.clone(), String::from(url)]);
+    match deflect(&command_line, env) {
+        Ok(()) => exit(EXIT_OK),
+        Err(e) => fail(format!("Couldn't open {}: {}", url, e)),
+    }
+}
+
+/// Runs `wedge pause`, handing launches back to edge for `duration` seconds or until resumed
+#[cfg(windows)]
+fn run_pause(duration: Option<u64>) -> ! {
+    attach_console();
+    match pause(&SystemRegistry, &SystemClock, duration) {
+        Ok(pause) => {
+            println!("{}", pause.describe(&SystemClock));
+            exit(EXIT_OK);
+        }
+        Err(e) => fail(format!("Can't pause deflection: {}", e)),
+    }
+}
+
+/// Runs `wedge resume`, ending any pause
+#[cfg(windows)]
+fn run_resume() -> ! {
+    attach_console();
+    match resume(&SystemRegistry, &SystemClock) {
+        Ok(true) => println!("Deflection resumed"),
+        Ok(false) => println!("Deflection wasn't paused"),
+        Err(e) => fail(format!("Can't resume deflection: {}", e)),
+    }
+    exit(EXIT_OK);
+}
+
+/// Runs `wedge explain`, printing how wedge would handle being launched with `args`
+#[cfg(windows)]
+fn run_explain(json: bool, args: &[String], edges: &[EdgeInstall]) -> ! {
+    attach_console();
+    let argv = explain_argv(args, &Env::invoked(&[], edges));
+    let mut env = Env::invoked(&argv, edges);
+    use_proxy_if_present(&mut env);
+    let explanation = explain(&argv, &env, &load_config(), &SystemBrowserProbe);
+    if json {
+        println!("{}", explanation.to_json());
+    } else {
+        println!("{}", explanation.to_text());
+    }
+    exit(EXIT_OK);
+}
+
+/// Runs `wedge doctor`, printing the report and exiting with its exit code
+#[cfg(windows)]
+fn run_doctor(json: bool, user: bool) -> ! {
+    attach_console();
+    match doctor(if user { Some(Scope::User) } else { None }) {
+        Ok(report) => {
+            if json {
+                println!("{}", report.to_json());
+            } else {
+                println!("{}", report.to_text());
+            }
+            exit(report.exit_code());
+        }
+        Err(e) => fail(format!("Wedge doctor failed: {}", e)),
+    }
+}
 
-        let deflected_url = if default_browser == Browser::Edge {
-            None
-        } else {
-            edge_args.iter().find_map(|a| uri::parse_ms_edge_url(&a))
-        };
+/// Runs `wedge repair`, putting back the parts of the install that drifted. Repairing an
+/// install for every user asks for administrator rights first.
+#[cfg(windows)]
+fn run_repair(user: bool) -> ! {
+    attach_console();
+    let scope = if user { Scope::User } else { Scope::Machine };
+    if scope == Scope::Machine && !is_elevated() {
+        if let Ok(path) = get_self_location() {
+            if shell_execute_elevated(&path.to_string_lossy(), "repair").is_ok() {
+                exit(EXIT_OK);
+            }
+        }
+    }
+    match repair(scope) {
+        Ok(log) => {
+            if log.is_empty() {
+                println!("Nothing needed repairing");
+            }
+            for line in log {
+                println!("{}", line);
+            }
+            exit(EXIT_OK);
+        }
+        Err(e) => fail(format!("Wedge repair failed: {}", e)),
+    }
+}
 
-        match deflected_url {
-            Some(url) => {
-                // Open the url with the system's default browser
-                shell_execute(&url);
+/// Runs `wedge config`. Settings are read from the user's config file, falling back on the
+/// machine's, and written to the user's.
+#[cfg(windows)]
+fn run_config(command: ConfigCommand) -> ! {
+    attach_console();
+    let paths = ConfigPaths::locate();
+    let read = |path: &str| fs::read_to_string(path).ok();
+    let result = match command {
+        ConfigCommand::Validate => {
+            let mut valid = true;
+            for path in &[&paths.machine, &paths.user] {
+                match read_config_file(&WindowsSystem, path) {
+                    Ok(Some(_)) => println!("{}: valid", path),
+                    Ok(None) => println!("{}: not found", path),
+                    Err(e) => {
+                        println!("{}", e.in_file(path));
+                        valid = false;
+                    }
+                }
             }
-            None => {
-                let mut default_cwd = PathBuf::from(MSEDGE_PATH);
-                default_cwd.pop();
-
-                // Launch edge from the same cwd
-                let cwd = std::env::current_dir().unwrap_or(default_cwd.into());
-
-                // Get path to edge executable through alternate execution path that avoids ifeo
-                let edge_alt_path = MSEDGE_PROXY_PATH;
-
-                // Call msedge with the same args it would have originally been called with
-                Command::new(edge_alt_path)
-                    .args(edge_args)
-                    .current_dir(cwd)
-                    .spawn()
-                    .expect("failed to execute process");
+            if valid {
+                Ok(())
+            } else {
+                Err(String::from("Config is invalid"))
+            }
+        }
+        ConfigCommand::Get(key) => read(&paths.user)
+            .or_else(|| read(&paths.machine))
+            .map_or(Ok(None), |text| get_setting(&text, &key))
+            .map(|value| match value {
+                Some(value) => println!("{}", value),
+                None => println!("{} isn't set", key),
+            })
+            .map_err(|e| e.to_string()),
+        ConfigCommand::Set(key, value) => {
+            set_setting(read(&paths.user).as_ref().map(String::as_str), &key, &value)
+                .map_err(|e| format!("Can't set {}: {}", key, e))
+                .and_then(|text| {
+                    let dir = Path::new(&paths.user).parent().unwrap();
+                    fs::create_dir_all(dir)
+                        .and_then(|_| fs::write(&paths.user, text))
+                        .map_err(|e| e.to_string())
+                })
+        }
+    };
+    match result {
+        Ok(()) => exit(EXIT_OK),
+        Err(e) => fail(e),
+    }
+}
+
+/// Runs `wedge policy`, writing the group policy templates to `dir`
+#[cfg(windows)]
+fn run_policy(dir: &str) -> ! {
+    attach_console();
+    match write_templates(&WindowsSystem, dir) {
+        Ok(paths) => {
+            for path in paths {
+                println!("Wrote {}", path);
             }
+            exit(EXIT_OK);
         }
+        Err(e) => fail(format!("Can't write the policy templates: {}", e)),
+    }
+}
+
+/// Runs `wedge logs`, printing the log of failed launches
+#[cfg(windows)]
+fn run_logs() -> ! {
+    attach_console();
+    let path = log_path();
+    match fs::read_to_string(&path) {
+        Ok(log) => print!("{}", log),
+        Err(_) => println!("Nothing has been logged to {}", path.display()),
+    }
+    exit(EXIT_OK);
+}
+
+/// Prints why a command failed and exits
+#[cfg(windows)]
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    exit(EXIT_FAILURE);
+}
+
+/// Entry
+#[cfg(windows)]
+fn main() {
+    // Keep the command line as it was written, since edge takes what follows
+    // `--single-argument` verbatim
+    let command_line = CommandLine::parse(&get_command_line());
+    let argv = command_line.argv();
+    let edges = discover(&WindowsSystem, &known_folders());
+
+    let command = match parse_command(&argv, &edges) {
+        Ok(command) => command,
+        Err(e) => {
+            attach_console();
+            eprintln!("{}", e);
+            exit(EXIT_USAGE);
+        }
+    };
+
+    match command {
+        Command::Deflect => {}
+        Command::Status => run_status(),
+        Command::Open(url) => run_open(&url, &edges),
+        Command::Pause { duration } => run_pause(duration),
+        Command::Resume => run_resume(),
+        Command::Explain { json, args } => run_explain(json, &args, &edges),
+        Command::Doctor { json, user } => run_doctor(json, user),
+        Command::Repair { user } => run_repair(user),
+        Command::Config(command) => run_config(command),
+        Command::Policy(dir) => run_policy(&dir),
+        Command::Logs => run_logs(),
+        Command::Version => {
+            attach_console();
+            println!("wedge {}", VERSION);
+            exit(EXIT_OK);
+        }
+        Command::Help(command) => {
+            attach_console();
+            println!("{}", help(command));
+            exit(EXIT_OK);
+        }
+    }
+
+    // Never leave a click doing nothing without saying so
+    let env = Env::invoked(&argv, &edges);
+    if let Err(e) = deflect(&command_line.shift(), env) {
+        error_box(
+            "Wedge couldn't open the link",
+            &format!(
+                "{}\n\nIf Edge was recently updated, run `wedge repair` as administrator. \
+                 Details are logged to {}.",
+                e,
+                log_path().display()
+            ),
+        );
     }
 }
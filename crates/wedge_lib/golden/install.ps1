@@ -0,0 +1,69 @@
+# Registers wedge for all users, run elevated
+param(
+    [string]$InstallDir = "C:\Program Files (x86)\Wedge",
+    [string]$EdgePath = "C:\Program Files (x86)\Microsoft\Edge\Application\msedge.exe"
+)
+
+$ErrorActionPreference = 'Stop'
+
+function New-RegistryKey([string]$Path) {
+    if (-not (Test-Path -LiteralPath $Path)) {
+        New-Item -Path $Path -Force | Out-Null
+    }
+}
+
+function Set-RegistryValue([string]$Path, [string]$Name, $Value, [string]$Type) {
+    if ($Name -eq '') {
+        $Name = '(default)'
+    }
+    New-ItemProperty -LiteralPath $Path -Name $Name -Value $Value -PropertyType $Type -Force | Out-Null
+}
+
+$Ifeo = "Registry::HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows NT\CurrentVersion\Image File Execution Options\msedge.exe"
+$Debugger = "`"$InstallDir\wedge.exe`""
+# Reuse the IFEO filter already pointing at wedge, otherwise take the lowest free one
+$IfeoFilter = $null
+if (Test-Path -LiteralPath $Ifeo) {
+    foreach ($Filter in Get-ChildItem -LiteralPath $Ifeo) {
+        if ((Get-ItemProperty -LiteralPath $Filter.PSPath).Debugger -eq $Debugger) {
+            $IfeoFilter = $Filter.PSChildName
+        }
+    }
+}
+if ($null -eq $IfeoFilter) {
+    $IfeoFilter = 0
+    while (Test-Path -LiteralPath "$Ifeo\$IfeoFilter") {
+        $IfeoFilter++
+    }
+}
+
+New-RegistryKey "Registry::HKEY_LOCAL_MACHINE\Software\Microsoft\Windows\CurrentVersion\App Paths\wedge.exe"
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Microsoft\Windows\CurrentVersion\App Paths\wedge.exe" "" "$Debugger" String
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Microsoft\Windows\CurrentVersion\App Paths\wedge.exe" "Path" "`"$InstallDir`"" String
+New-RegistryKey "Registry::HKEY_LOCAL_MACHINE\Software\Classes\Wedge"
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Classes\Wedge" "" "URL: Microsoft Edge Protocol Deflector" String
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Classes\Wedge" "URL Protocol" "" String
+New-RegistryKey "Registry::HKEY_LOCAL_MACHINE\Software\Classes\Wedge\DefaultIcon"
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Classes\Wedge\DefaultIcon" "" "$Debugger" String
+New-RegistryKey "Registry::HKEY_LOCAL_MACHINE\Software\Classes\Wedge\shell\open\command"
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Classes\Wedge\shell\open\command" "" "$Debugger `"%1`"" String
+New-RegistryKey "Registry::HKEY_LOCAL_MACHINE\Software\Clients\Wedge\Capabilities"
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Clients\Wedge\Capabilities" "ApplicationName" "Wedge" String
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Clients\Wedge\Capabilities" "ApplicationDescription" "Open web links normally forced to open in Microsoft Edge in your default web browser." String
+New-RegistryKey "Registry::HKEY_LOCAL_MACHINE\Software\Clients\Wedge\Capabilities\URLAssociations"
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Clients\Wedge\Capabilities\URLAssociations" "microsoft-edge" "Wedge" String
+New-RegistryKey "Registry::HKEY_LOCAL_MACHINE\Software\RegisteredApplications"
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\RegisteredApplications" "Wedge" "Software\Clients\Wedge\Capabilities" String
+New-RegistryKey "Registry::HKEY_LOCAL_MACHINE\Software\Microsoft\Windows\CurrentVersion\Uninstall\Wedge"
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Microsoft\Windows\CurrentVersion\Uninstall\Wedge" "DisplayIcon" "$Debugger" String
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Microsoft\Windows\CurrentVersion\Uninstall\Wedge" "DisplayName" "Wedge" String
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Microsoft\Windows\CurrentVersion\Uninstall\Wedge" "DisplayVersion" "0.1.0" String
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Microsoft\Windows\CurrentVersion\Uninstall\Wedge" "InstallLocation" "`"$InstallDir`"" String
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Microsoft\Windows\CurrentVersion\Uninstall\Wedge" "ModifyPath" "$Debugger repair" String
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Microsoft\Windows\CurrentVersion\Uninstall\Wedge" "Publisher" "Marc Guiselin <https://github.com/MarcGuiselin>" String
+Set-RegistryValue "Registry::HKEY_LOCAL_MACHINE\Software\Microsoft\Windows\CurrentVersion\Uninstall\Wedge" "UninstallString" "`"$InstallDir\wedge uninstaller.exe`"" String
+New-RegistryKey "$Ifeo"
+Set-RegistryValue "$Ifeo" "UseFilter" 1 DWord
+New-RegistryKey "$Ifeo\$IfeoFilter"
+Set-RegistryValue "$Ifeo\$IfeoFilter" "Debugger" "$Debugger" String
+Set-RegistryValue "$Ifeo\$IfeoFilter" "FilterFullPath" "$EdgePath" String
@@ -1,28 +1,306 @@
-use std::io::Error;
-use winreg::{enums::HKEY_CURRENT_USER, RegKey};
+#[cfg(windows)]
+use crate::registry::SystemRegistry;
+use crate::{
+    error::WedgeError,
+    registry::{Hive, Key, Registry},
+};
 
-#[derive(PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Browser {
     InternetExplorer,
     Edge,
     Firefox,
     Chrome,
+    Chromium,
     Opera,
+    Brave,
+    Vivaldi,
+    LibreWolf,
+    Waterfox,
     Unknown,
 }
 
-pub fn get_default_browser() -> Result<Browser, Error> {
-    let user_choice_key = RegKey::predef(HKEY_CURRENT_USER).open_subkey(
-        r"Software\Microsoft\Windows\Shell\Associations\UrlAssociations\http\UserChoice",
-    )?;
-    let prog_id: String = user_choice_key.get_value("Progid")?;
-
-    Ok(match prog_id.as_str() {
-        "IE.HTTP" => Browser::InternetExplorer,
-        "FirefoxURL" => Browser::Firefox,
-        "ChromeHTML" => Browser::Chrome,
-        "OperaStable" => Browser::Opera,
-        "AppXq0fevzme2pys62n3e0fbqa7peapykr8v" => Browser::Edge,
-        _ => Browser::Unknown,
-    })
+/// Release channel of a browser
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Stable,
+    Beta,
+    Dev,
+    /// Canary or nightly builds
+    Canary,
+}
+
+/// Engine family a browser is built on, which decides the command line flags it takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Engine {
+    Chromium,
+    Gecko,
+    Other,
+}
+
+/// Facts about a browser wedge knows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BrowserInfo {
+    pub browser: Browser,
+    /// Name used in settings
+    pub name: &'static str,
+    pub vendor: &'static str,
+    pub engine: Engine,
+    /// Executable the browser registers under App Paths, so it can be launched by name
+    pub executable: Option<&'static str>,
+}
+
+/// A browser identified from its ProgId
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgIdMatch {
+    pub browser: Browser,
+    pub channel: Channel,
+}
+
+#[rustfmt::skip]
+const BROWSERS: [BrowserInfo; 10] = [
+    BrowserInfo { browser: Browser::InternetExplorer, name: "internet-explorer", vendor: "Microsoft", engine: Engine::Other, executable: Some("iexplore.exe") },
+    BrowserInfo { browser: Browser::Edge, name: "edge", vendor: "Microsoft", engine: Engine::Chromium, executable: Some("msedge.exe") },
+    BrowserInfo { browser: Browser::Firefox, name: "firefox", vendor: "Mozilla", engine: Engine::Gecko, executable: Some("firefox.exe") },
+    BrowserInfo { browser: Browser::Chrome, name: "chrome", vendor: "Google", engine: Engine::Chromium, executable: Some("chrome.exe") },
+    // Chromium's executable is also chrome.exe, so App Paths would find Chrome instead
+    BrowserInfo { browser: Browser::Chromium, name: "chromium", vendor: "The Chromium Authors", engine: Engine::Chromium, executable: None },
+    BrowserInfo { browser: Browser::Opera, name: "opera", vendor: "Opera", engine: Engine::Chromium, executable: Some("opera.exe") },
+    BrowserInfo { browser: Browser::Brave, name: "brave", vendor: "Brave Software", engine: Engine::Chromium, executable: Some("brave.exe") },
+    BrowserInfo { browser: Browser::Vivaldi, name: "vivaldi", vendor: "Vivaldi Technologies", engine: Engine::Chromium, executable: Some("vivaldi.exe") },
+    BrowserInfo { browser: Browser::LibreWolf, name: "librewolf", vendor: "LibreWolf", engine: Engine::Gecko, executable: Some("librewolf.exe") },
+    BrowserInfo { browser: Browser::Waterfox, name: "waterfox", vendor: "Waterfox", engine: Engine::Gecko, executable: Some("waterfox.exe") },
+];
+
+/// ProgIds browsers register for http links. Many append a hash of their install path
+/// after a `-` or `.`, like `FirefoxURL-308046B0AF4A39CB`, which is matched too.
+#[rustfmt::skip]
+const PROG_IDS: [(&str, Browser, Channel); 22] = [
+    ("IE.HTTP", Browser::InternetExplorer, Channel::Stable),
+    ("MSEdgeHTM", Browser::Edge, Channel::Stable),
+    ("MSEdgeBHTML", Browser::Edge, Channel::Beta),
+    ("MSEdgeDHTML", Browser::Edge, Channel::Dev),
+    ("MSEdgeCHTML", Browser::Edge, Channel::Canary),
+    // Legacy edge
+    ("AppXq0fevzme2pys62n3e0fbqa7peapykr8v", Browser::Edge, Channel::Stable),
+    ("FirefoxURL", Browser::Firefox, Channel::Stable),
+    ("ChromeHTML", Browser::Chrome, Channel::Stable),
+    ("ChromeBHTML", Browser::Chrome, Channel::Beta),
+    ("ChromeDHTML", Browser::Chrome, Channel::Dev),
+    ("ChromeSSHTM", Browser::Chrome, Channel::Canary),
+    ("ChromiumHTM", Browser::Chromium, Channel::Stable),
+    ("OperaStable", Browser::Opera, Channel::Stable),
+    ("OperaNext", Browser::Opera, Channel::Beta),
+    ("OperaDeveloper", Browser::Opera, Channel::Dev),
+    ("BraveHTML", Browser::Brave, Channel::Stable),
+    ("BraveBHTML", Browser::Brave, Channel::Beta),
+    ("BraveDHTML", Browser::Brave, Channel::Dev),
+    ("BraveSSHTM", Browser::Brave, Channel::Canary),
+    ("VivaldiHTM", Browser::Vivaldi, Channel::Stable),
+    ("LibreWolfHTM", Browser::LibreWolf, Channel::Stable),
+    ("WaterfoxURL", Browser::Waterfox, Channel::Stable),
+];
+
+impl BrowserInfo {
+    /// Every browser wedge knows
+    pub fn all() -> &'static [BrowserInfo] { &BROWSERS }
+}
+
+impl Browser {
+    /// Parses a browser from its name as used in settings
+    pub fn from_name(name: &str) -> Option<Browser> {
+        let name = name.trim().to_lowercase();
+        BROWSERS
+            .iter()
+            .find(|info| info.name == name)
+            .map(|info| info.browser)
+    }
+
+    /// Identifies a browser from the ProgId it registers for http links
+    pub fn from_prog_id(prog_id: &str) -> Browser {
+        identify_prog_id(prog_id).map_or(Browser::Unknown, |id| id.browser)
+    }
+
+    /// Everything known about the browser, or nothing when it's unknown
+    pub fn info(self) -> Option<&'static BrowserInfo> {
+        BROWSERS.iter().find(|info| info.browser == self)
+    }
+
+    pub fn engine(self) -> Engine { self.info().map_or(Engine::Other, |info| info.engine) }
+
+    /// Executable the browser registers under App Paths, so it can be launched by name
+    pub fn executable_name(self) -> Option<&'static str> {
+        self.info().and_then(|info| info.executable)
+    }
+}
+
+/// Identifies the browser and channel registering a ProgId
+pub fn identify_prog_id(prog_id: &str) -> Option<ProgIdMatch> {
+    PROG_IDS
+        .iter()
+        .find(|(known, _, _)| {
+            // Compare bytes, as a ProgId can have any character where a known one ends
+            let bytes = prog_id.as_bytes();
+            match (bytes.get(..known.len()), bytes.get(known.len())) {
+                (Some(prefix), separator) if prefix.eq_ignore_ascii_case(known.as_bytes()) => {
+                    match separator {
+                        None => true,
+                        Some(b'-') | Some(b'.') => bytes.len() > known.len() + 1,
+                        Some(_) => false,
+                    }
+                }
+                _ => false,
+            }
+        })
+        .map(|(_, browser, channel)| ProgIdMatch {
+            browser: *browser,
+            channel: *channel,
+        })
+}
+
+/// Source of information about the browsers on a system
+pub trait BrowserProbe {
+    /// The user's default web browser
+    fn default_browser(&self) -> Browser;
+}
+
+/// Probes browsers configured on this machine
+#[cfg(windows)]
+pub struct SystemBrowserProbe;
+
+#[cfg(windows)]
+impl BrowserProbe for SystemBrowserProbe {
+    fn default_browser(&self) -> Browser {
+        get_default_browser(&SystemRegistry).unwrap_or(Browser::Unknown)
+    }
+}
+
+/// Registry keys browsers register themselves under, per user then per machine
+const START_MENU_INTERNET_KEY: &str = r"Software\Clients\StartMenuInternet";
+
+pub fn get_default_browser(registry: &dyn Registry) -> Result<Browser, WedgeError> {
+    Ok(Browser::from_prog_id(&get_default_prog_id(registry)?))
+}
+
+/// ProgId of the user's default handler for http links
+pub fn get_default_prog_id(registry: &dyn Registry) -> Result<String, WedgeError> {
+    Key::predef(registry, Hive::CurrentUser)
+        .open_subkey(
+            r"Software\Microsoft\Windows\Shell\Associations\UrlAssociations\http\UserChoice",
+        )?
+        .get_string("Progid")
+}
+
+/// Finds the ProgId an installed browser handles http links with
+pub fn find_prog_id(registry: &dyn Registry, browser: Browser) -> Option<String> {
+    [Hive::CurrentUser, Hive::LocalMachine]
+        .iter()
+        .filter_map(|hive| {
+            Key::predef(registry, *hive)
+                .open_subkey(START_MENU_INTERNET_KEY)
+                .ok()
+        })
+        .flat_map(|clients| {
+            let names = clients.enum_keys().unwrap_or_default();
+            names.into_iter().filter_map(move |name| {
+                clients
+                    .open_subkey(&name)
+                    .and_then(|client| client.open_subkey(r"Capabilities\URLAssociations"))
+                    .and_then(|associations| associations.get_string("http"))
+                    .ok()
+            })
+        })
+        .find(|prog_id| Browser::from_prog_id(prog_id) == browser)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::MemoryRegistry;
+
+    #[test]
+    fn test_get_default_browser() {
+        let registry = MemoryRegistry::new();
+        assert!(get_default_browser(&registry).is_err());
+
+        let user_choice = Key::predef(&registry, Hive::CurrentUser)
+            .create_subkey(
+                r"Software\Microsoft\Windows\Shell\Associations\UrlAssociations\http\UserChoice",
+            )
+            .unwrap();
+        user_choice.set_value("Progid", "FirefoxURL").unwrap();
+        assert_eq!(Browser::Firefox, get_default_browser(&registry).unwrap());
+        user_choice.set_value("Progid", "SomethingElse").unwrap();
+        assert_eq!(Browser::Unknown, get_default_browser(&registry).unwrap());
+    }
+
+    #[test]
+    fn test_identify_prog_id() {
+        let id = |browser, channel| Some(ProgIdMatch { browser, channel });
+        assert_eq!(
+            id(Browser::Firefox, Channel::Stable),
+            identify_prog_id("FirefoxURL-308046B0AF4A39CB")
+        );
+        assert_eq!(
+            id(Browser::Waterfox, Channel::Stable),
+            identify_prog_id("WaterfoxURL-1A2B3C")
+        );
+        assert_eq!(
+            id(Browser::Vivaldi, Channel::Stable),
+            identify_prog_id("VivaldiHTM.ZX3TQXVGPAXKFDPVWLSGHTB5HM")
+        );
+        assert_eq!(
+            id(Browser::Brave, Channel::Stable),
+            identify_prog_id("BraveHTML")
+        );
+        assert_eq!(
+            id(Browser::Edge, Channel::Stable),
+            identify_prog_id("MSEdgeHTM")
+        );
+        assert_eq!(
+            id(Browser::Edge, Channel::Beta),
+            identify_prog_id("MSEdgeBHTML")
+        );
+        assert_eq!(
+            id(Browser::Edge, Channel::Dev),
+            identify_prog_id("MSEdgeDHTML")
+        );
+        assert_eq!(
+            id(Browser::Edge, Channel::Canary),
+            identify_prog_id("MSEdgeCHTML")
+        );
+        assert_eq!(
+            id(Browser::Chromium, Channel::Stable),
+            identify_prog_id("ChromiumHTM.PGEVUAAXOQIQSAPLCI4BRV3JLE")
+        );
+        assert_eq!(
+            id(Browser::LibreWolf, Channel::Stable),
+            identify_prog_id("LibreWolfHTM")
+        );
+
+        // Only hash suffixes are allowed after a known ProgId
+        assert_eq!(None, identify_prog_id("FirefoxURLs"));
+        assert_eq!(None, identify_prog_id("FirefoxURL-"));
+        assert_eq!(None, identify_prog_id("MSEdge"));
+        assert_eq!(None, identify_prog_id(""));
+        // Non-ASCII characters where a known ProgId ends
+        assert_eq!(None, identify_prog_id("FirefoxURé"));
+        assert_eq!(None, identify_prog_id("MSEdgeHTé"));
+        assert_eq!(None, identify_prog_id("BraveHTMLé"));
+    }
+
+    #[test]
+    fn test_browser_info() {
+        for info in &BROWSERS {
+            assert_eq!(Some(info.browser), Browser::from_name(info.name));
+            assert_eq!(Some(info), info.browser.info());
+        }
+        for (_, browser, _) in &PROG_IDS {
+            assert!(browser.info().is_some());
+        }
+        assert_eq!(Engine::Gecko, Browser::LibreWolf.engine());
+        assert_eq!(Engine::Chromium, Browser::Brave.engine());
+        assert_eq!(Engine::Other, Browser::Unknown.engine());
+        assert_eq!(None, Browser::Unknown.executable_name());
+    }
 }
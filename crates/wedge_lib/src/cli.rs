@@ -0,0 +1,606 @@
+use crate::{
+    config::Config,
+    doctor::Report,
+    edge::EdgeInstall,
+    install::Scope,
+    pause::{format_duration, parse_duration, MAX_DURATION},
+    rules::Target,
+};
+use std::fmt;
+
+/// Version of wedge
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Exit code of a command that succeeded
+pub const EXIT_OK: i32 = 0;
+/// Exit code of a command that failed, or found a problem with the install
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit code of `status` and `doctor` when wedge isn't installed
+pub const EXIT_NOT_INSTALLED: i32 = 2;
+/// Exit code when the command line can't be understood
+pub const EXIT_USAGE: i32 = 64;
+
+/// What wedge was started to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Handle a launch of edge, as its IFEO debugger or the `microsoft-edge:` protocol handler
+    Deflect,
+    Status,
+    Open(String),
+    /// Hand every launch back to edge, for a number of seconds or until resumed
+    Pause {
+        duration: Option<u64>,
+    },
+    Resume,
+    /// Trace how an invocation would be handled, given a `microsoft-edge:` url or edge's args
+    Explain {
+        json: bool,
+        args: Vec<String>,
+    },
+    Doctor {
+        json: bool,
+        user: bool,
+    },
+    Repair {
+        user: bool,
+    },
+    Config(ConfigCommand),
+    /// Write the group policy templates to a folder
+    Policy(String),
+    Logs,
+    Version,
+    /// Help for a command, or every command when there is none
+    Help(Option<&'static str>),
+}
+
+/// What `wedge config` was asked to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigCommand {
+    Validate,
+    Get(String),
+    Set(String, String),
+}
+
+/// A command wedge takes, and its help
+struct CommandHelp {
+    name: &'static str,
+    usage: &'static str,
+    summary: &'static str,
+    details: &'static str,
+}
+
+#[rustfmt::skip]
+static COMMANDS: [CommandHelp; 12] = [
+    CommandHelp {
+        name: "status",
+        usage: "wedge status",
+        summary: "Show whether wedge is installed and where links open",
+        details: "Exits with 0 when wedge is installed and healthy, 1 when `wedge doctor` finds a problem and 2 when wedge isn't installed. This is also what wedge shows when started without a command, like from the Start menu.",
+    },
+    CommandHelp {
+        name: "open",
+        usage: "wedge open <url>",
+        summary: "Open a url the way wedge deflects it",
+        details: "Opens the url as if a program had asked Edge to, so rules, the search engine and the browser from your settings all apply. Exits with 1 when nothing could open it.",
+    },
+    CommandHelp {
+        name: "pause",
+        usage: "wedge pause [--for <duration>]",
+        summary: "Let Edge open links until you resume",
+        details: "Every launch of Edge is passed through to it, as if wedge wasn't installed, until `wedge resume`. --for ends the pause on its own after a duration of up to 365d like 45s, 30m, 2h or 1d. Pausing only affects your account.",
+    },
+    CommandHelp {
+        name: "resume",
+        usage: "wedge resume",
+        summary: "Deflect links again after a pause",
+        details: "",
+    },
+    CommandHelp {
+        name: "explain",
+        usage: "wedge explain [--json] (<microsoft-edge: url> | -- <edge args>)",
+        summary: "Show step by step how a launch of Edge would be handled",
+        details: "Runs the same steps as deflecting a real launch without opening anything: parsing the command line, the invocation class and its action, the urls found, the default browser, Bing search rewriting, rule matching and the final decision. Give either a microsoft-edge: url or, after --, the args Edge was launched with. --json prints the steps as JSON.",
+    },
+    CommandHelp {
+        name: "doctor",
+        usage: "wedge doctor [--json] [--user]",
+        summary: "Check the install for problems",
+        details: "Checks the installed files, the Edge proxies, the registry and the Start menu shortcut. --json prints the report as JSON and --user checks a per-user install. Exits with 0 when healthy, 1 when a check failed and 2 when wedge isn't installed.",
+    },
+    CommandHelp {
+        name: "repair",
+        usage: "wedge repair [--user]",
+        summary: "Put back what Edge updates removed",
+        details: "Recreates the Edge proxies, the IFEO filter and anything else missing from the install. Repairing an install for every user asks for administrator rights, --user repairs a per-user install.",
+    },
+    CommandHelp {
+        name: "config",
+        usage: "wedge config validate | get <key> | set <key> <value>",
+        summary: "Check, read or change config files",
+        details: "validate checks both config files, printing where any errors are. get prints a setting from your config file, or the one next to wedge.exe when you have none. set changes a setting in your config file, reading the value as JSON or else as text. Keys of nested settings are dotted, like actions.notification.",
+    },
+    CommandHelp {
+        name: "policy",
+        usage: "wedge policy <folder>",
+        summary: "Write the Group Policy templates to a folder",
+        details: "Writes wedge.admx and en-US\\wedge.adml laid out like C:\\Windows\\PolicyDefinitions, ready to copy there or to your central store. The folder is created if its parent exists.",
+    },
+    CommandHelp {
+        name: "logs",
+        usage: "wedge logs",
+        summary: "Show links wedge failed to open",
+        details: "Prints the log wedge keeps in the temp folder.",
+    },
+    CommandHelp {
+        name: "version",
+        usage: "wedge version",
+        summary: "Show the version of wedge",
+        details: "",
+    },
+    CommandHelp {
+        name: "help",
+        usage: "wedge help [command]",
+        summary: "Show help for a command",
+        details: "",
+    },
+];
+
+fn find_command(name: &str) -> Option<&'static CommandHelp> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+/// A command line wedge can't make sense of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageError {
+    /// Command it was meant for, if that much is known
+    pub command: Option<&'static str>,
+    pub message: String,
+}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.command.and_then(find_command) {
+            Some(command) => write!(
+                f,
+                "{}\nUsage: {}\nRun `wedge help {}` for details",
+                self.message, command.usage, command.name
+            ),
+            None => write!(f, "{}\nRun `wedge help` to see every command", self.message),
+        }
+    }
+}
+
+/// Works out what wedge was started to do. Windows starts it with the path to edge first, or
+/// just a `microsoft-edge:` url, and anything else is a command.
+pub fn parse_command(argv: &[String], edges: &[EdgeInstall]) -> Result<Command, UsageError> {
+    let first = match argv.get(1) {
+        Some(first) => first.as_str(),
+        None => return Ok(Command::Status),
+    };
+    let is_edge = first.to_lowercase().ends_with("msedge.exe")
+        || edges
+            .iter()
+            .any(|edge| edge.path.eq_ignore_ascii_case(first));
+    if is_edge || (argv.len() == 2 && first.starts_with("microsoft-edge:")) {
+        return Ok(Command::Deflect);
+    }
+
+    let name = match first {
+        "-h" | "--help" | "/?" => "help",
+        "-V" | "--version" => "version",
+        name => name,
+    };
+    let command = find_command(name).ok_or_else(|| UsageError {
+        command: None,
+        message: format!("Unknown command `{}`", name),
+    })?;
+    let args: Vec<&str> = argv[2..].iter().map(String::as_str).collect();
+    // Anything after `--` belongs to edge
+    let wants_help = args
+        .iter()
+        .take_while(|arg| **arg != "--")
+        .any(|arg| *arg == "-h" || *arg == "--help");
+    if name != "help" && wants_help {
+        return Ok(Command::Help(Some(command.name)));
+    }
+
+    let usage = |message: String| UsageError {
+        command: Some(command.name),
+        message,
+    };
+    // Flags can come in any order, anything else is unexpected
+    let flags = |allowed: &[&str]| -> Result<Vec<bool>, UsageError> {
+        match args.iter().find(|arg| !allowed.contains(*arg)) {
+            Some(arg) => Err(usage(format!("Unexpected argument `{}`", arg))),
+            None => Ok(allowed.iter().map(|flag| args.contains(flag)).collect()),
+        }
+    };
+
+    Ok(match (command.name, args.as_slice()) {
+        ("status", _) => flags(&[]).map(|_| Command::Status)?,
+        ("open", [url]) => Command::Open(String::from(*url)),
+        ("open", _) => return Err(usage(String::from("Expected a url to open"))),
+        ("pause", []) => Command::Pause { duration: None },
+        ("pause", ["--for", duration]) => match parse_duration(duration) {
+            Some(duration) => Command::Pause {
+                duration: Some(duration),
+            },
+            None => {
+                return Err(usage(format!(
+                    "Expected a duration of up to {} like 30m, 2h or 1d, not `{}`",
+                    format_duration(MAX_DURATION),
+                    duration
+                )))
+            }
+        },
+        ("pause", ["--for"]) => {
+            return Err(usage(String::from(
+                "Expected a duration like 30m, 2h or 1d",
+            )))
+        }
+        ("pause", _) => flags(&[]).map(|_| Command::Pause { duration: None })?,
+        ("resume", _) => flags(&[]).map(|_| Command::Resume)?,
+        ("explain", _) => {
+            let json = args.first() == Some(&"--json");
+            let rest = &args[if json { 1 } else { 0 }..];
+            let args = match rest.split_first() {
+                Some((&"--", edge)) if !edge.is_empty() => edge,
+                Some((url, [])) if url.starts_with("microsoft-edge:") => rest,
+                _ => {
+                    return Err(usage(String::from(
+                        "Expected a microsoft-edge: url, or -- followed by Edge's args",
+                    )))
+                }
+            };
+            Command::Explain {
+                json,
+                args: args.iter().map(|arg| String::from(*arg)).collect(),
+            }
+        }
+        ("doctor", _) => {
+            let flags = flags(&["--json", "--user"])?;
+            Command::Doctor {
+                json: flags[0],
+                user: flags[1],
+            }
+        }
+        ("repair", _) => Command::Repair {
+            user: flags(&["--user"])?[0],
+        },
+        ("config", ["validate"]) => Command::Config(ConfigCommand::Validate),
+        ("config", ["get", key]) => Command::Config(ConfigCommand::Get(String::from(*key))),
+        ("config", ["set", key, value]) => {
+            Command::Config(ConfigCommand::Set(String::from(*key), String::from(*value)))
+        }
+        ("config", _) => return Err(usage(String::from("Expected validate, get or set"))),
+        ("policy", [dir]) => Command::Policy(String::from(*dir)),
+        ("policy", _) => {
+            return Err(usage(String::from(
+                "Expected a folder to write the templates to",
+            )))
+        }
+        ("logs", _) => flags(&[]).map(|_| Command::Logs)?,
+        ("version", _) => flags(&[]).map(|_| Command::Version)?,
+        (_, []) => Command::Help(None),
+        (_, [topic]) => Command::Help(Some(
+            find_command(topic)
+                .ok_or_else(|| usage(format!("Unknown command `{}`", topic)))?
+                .name,
+        )),
+        (_, _) => return Err(usage(String::from("Expected at most one command"))),
+    })
+}
+
+/// Help for a command, or a list of every command
+pub fn help(command: Option<&str>) -> String {
+    match command.and_then(find_command) {
+        Some(command) if command.details.is_empty() => {
+            format!("Usage: {}\n\n{}", command.usage, command.summary)
+        }
+        Some(command) => format!(
+            "Usage: {}\n\n{}. {}",
+            command.usage, command.summary, command.details
+        ),
+        None => {
+            let mut lines = vec![
+                format!("Wedge {}, the Edge deflector", VERSION),
+                String::new(),
+                String::from("Usage: wedge [command] [arguments]"),
+                String::new(),
+                String::from("Commands:"),
+            ];
+            for command in &COMMANDS {
+                lines.push(format!("  {:<9} {}", command.name, command.summary));
+            }
+            lines.push(String::new());
+            lines.push(String::from(
+                "Without a command wedge shows its status. Windows starts wedge in place of Edge \
+                 with the path to msedge.exe first, which deflects the launch.",
+            ));
+            lines.push(String::new());
+            lines.push(format!(
+                "Exit codes: {} success, {} failure or a problem found, {} not installed, {} bad \
+                 arguments",
+                EXIT_OK, EXIT_FAILURE, EXIT_NOT_INSTALLED, EXIT_USAGE
+            ));
+            lines.join("\n")
+        }
+    }
+}
+
+/// Summary of the install and settings shown by `wedge status`
+pub fn status_text(report: &Report, config: &Config, log_path: &str) -> String {
+    let mut lines = vec![];
+    if report.installed {
+        lines.push(format!(
+            "Wedge {} is installed for {}",
+            VERSION,
+            match report.scope {
+                Scope::Machine => "every user",
+                Scope::User => "this user",
+            }
+        ));
+        let failed = report
+            .checks
+            .iter()
+            .filter(|check| check.problem.is_some())
+            .count();
+        lines.push(match failed {
+            0 => String::from("Everything is in place"),
+            1 => String::from("1 problem found, run `wedge doctor` to see it"),
+            failed => format!("{} problems found, run `wedge doctor` to see them", failed),
+        });
+    } else {
+        lines.push(format!("Wedge {} isn't installed", VERSION));
+    }
+
+    let default_browser = report
+        .default_browser
+        .info()
+        .map_or("an unknown browser", |info| info.name);
+    lines.push(match config.browser {
+        Some(target) if target != Target::DefaultBrowser => format!(
+            "Links open in {}, the default browser is {}",
+            target.name(),
+            default_browser
+        ),
+        _ => format!("Links open in the default browser, {}", default_browser),
+    });
+    lines.push(match &config.search_engine {
+        Some(engine) => format!("Bing searches go to {}", engine.name()),
+        None => String::from("Bing searches are left alone"),
+    });
+    if !config.rules.is_empty() {
+        lines.push(format!(
+            "{} rules route links to other browsers",
+            config.rules.len()
+        ));
+    }
+    if config.paused {
+        lines.push(String::from(
+            "Deflection is paused, so Edge opens links until `wedge resume`",
+        ));
+    }
+    lines.push(if config.logging {
+        format!("Failures are logged to {}", log_path)
+    } else {
+        String::from("Logging is off")
+    });
+    lines.push(String::new());
+    lines.push(String::from(
+        "Run `wedge help` to see what else wedge can do",
+    ));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        browser::{Browser, Channel},
+        doctor::Check,
+        install::MSEDGE_PATH,
+        search::SearchEngine,
+    };
+
+    fn parse(args: &[&str]) -> Result<Command, UsageError> {
+        let mut argv = vec![String::from("wedge.exe")];
+        argv.extend(args.iter().map(|arg| String::from(*arg)));
+        let edges = [EdgeInstall {
+            channel: Channel::Beta,
+            path: String::from(r"D:\Edge Beta\edge.exe"),
+        }];
+        parse_command(&argv, &edges)
+    }
+
+    fn usage(command: Option<&'static str>, message: &str) -> Result<Command, UsageError> {
+        Err(UsageError {
+            command,
+            message: String::from(message),
+        })
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(Ok(Command::Status), parse(&[]));
+        assert_eq!(Ok(Command::Deflect), parse(&[MSEDGE_PATH, "--flag"]));
+        assert_eq!(Ok(Command::Deflect), parse(&[r"D:\Edge Beta\edge.exe"]));
+        assert_eq!(
+            Ok(Command::Deflect),
+            parse(&["microsoft-edge:https://a.com"])
+        );
+        assert_eq!(Ok(Command::Status), parse(&["status"]));
+        assert_eq!(
+            Ok(Command::Open(String::from("https://a.com"))),
+            parse(&["open", "https://a.com"])
+        );
+        assert_eq!(
+            Ok(Command::Doctor {
+                json: true,
+                user: false
+            }),
+            parse(&["doctor", "--json"])
+        );
+        assert_eq!(
+            Ok(Command::Repair { user: true }),
+            parse(&["repair", "--user"])
+        );
+        assert_eq!(
+            Ok(Command::Config(ConfigCommand::Set(
+                String::from("logging"),
+                String::from("false")
+            ))),
+            parse(&["config", "set", "logging", "false"])
+        );
+        assert_eq!(
+            Ok(Command::Explain {
+                json: true,
+                args: vec![String::from("microsoft-edge:https://a.com")]
+            }),
+            parse(&["explain", "--json", "microsoft-edge:https://a.com"])
+        );
+        assert_eq!(
+            Ok(Command::Explain {
+                json: false,
+                args: vec![String::from(MSEDGE_PATH), String::from("--help")]
+            }),
+            parse(&["explain", "--", MSEDGE_PATH, "--help"])
+        );
+        assert_eq!(Ok(Command::Pause { duration: None }), parse(&["pause"]));
+        assert_eq!(
+            Ok(Command::Pause {
+                duration: Some(30 * 60)
+            }),
+            parse(&["pause", "--for", "30m"])
+        );
+        assert_eq!(Ok(Command::Resume), parse(&["resume"]));
+        assert_eq!(
+            Ok(Command::Policy(String::from(r"C:\PolicyDefinitions"))),
+            parse(&["policy", r"C:\PolicyDefinitions"])
+        );
+        assert_eq!(Ok(Command::Logs), parse(&["logs"]));
+        assert_eq!(Ok(Command::Version), parse(&["--version"]));
+        assert_eq!(Ok(Command::Help(None)), parse(&["help"]));
+        assert_eq!(Ok(Command::Help(None)), parse(&["-h"]));
+        assert_eq!(
+            Ok(Command::Help(Some("doctor"))),
+            parse(&["help", "doctor"])
+        );
+        assert_eq!(Ok(Command::Help(Some("open"))), parse(&["open", "--help"]));
+
+        assert_eq!(usage(None, "Unknown command `start`"), parse(&["start"]));
+        assert_eq!(
+            usage(Some("open"), "Expected a url to open"),
+            parse(&["open"])
+        );
+        assert_eq!(
+            usage(
+                Some("explain"),
+                "Expected a microsoft-edge: url, or -- followed by Edge's args"
+            ),
+            parse(&["explain", "https://a.com"])
+        );
+        assert_eq!(
+            usage(
+                Some("pause"),
+                "Expected a duration of up to 365d like 30m, 2h or 1d, not `soon`"
+            ),
+            parse(&["pause", "--for", "soon"])
+        );
+        assert_eq!(
+            usage(
+                Some("pause"),
+                "Expected a duration of up to 365d like 30m, 2h or 1d, not `999999999999d`"
+            ),
+            parse(&["pause", "--for", "999999999999d"])
+        );
+        assert_eq!(
+            usage(Some("pause"), "Expected a duration like 30m, 2h or 1d"),
+            parse(&["pause", "--for"])
+        );
+        assert_eq!(
+            usage(Some("pause"), "Unexpected argument `30m`"),
+            parse(&["pause", "30m"])
+        );
+        assert_eq!(
+            usage(Some("doctor"), "Unexpected argument `--all`"),
+            parse(&["doctor", "--user", "--all"])
+        );
+        assert_eq!(
+            usage(Some("config"), "Expected validate, get or set"),
+            parse(&["config", "get"])
+        );
+        assert_eq!(
+            usage(
+                Some("policy"),
+                "Expected a folder to write the templates to"
+            ),
+            parse(&["policy"])
+        );
+        assert_eq!(
+            usage(Some("help"), "Unknown command `start`"),
+            parse(&["help", "start"])
+        );
+        assert_eq!(
+            "Unexpected argument `x`\nUsage: wedge logs\nRun `wedge help logs` for details",
+            parse(&["logs", "x"]).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_help() {
+        let overview = help(None);
+        for command in &COMMANDS {
+            assert!(overview.contains(&format!("  {:<9} {}", command.name, command.summary)));
+            assert!(help(Some(command.name)).starts_with(&format!("Usage: {}", command.usage)));
+        }
+        assert_eq!(
+            "Usage: wedge version\n\nShow the version of wedge",
+            help(Some("version"))
+        );
+    }
+
+    #[test]
+    fn test_status_text() {
+        let mut report = Report {
+            scope: Scope::Machine,
+            installed: true,
+            checks: vec![Check {
+                name: String::from("File"),
+                problem: None,
+            }],
+            default_browser: Browser::Firefox,
+            prog_id: Some(String::from("FirefoxURL")),
+        };
+        let mut config = Config::default();
+        assert_eq!(
+            format!(
+                "Wedge {} is installed for every user\n\
+                 Everything is in place\n\
+                 Links open in the default browser, firefox\n\
+                 Bing searches are left alone\n\
+                 Failures are logged to C:\\Temp\\wedge.log\n\
+                 \n\
+                 Run `wedge help` to see what else wedge can do",
+                VERSION
+            ),
+            status_text(&report, &config, r"C:\Temp\wedge.log")
+        );
+
+        report.checks[0].problem = Some(String::from("Missing"));
+        config.browser = Some(Target::Browser(Browser::Brave));
+        config.search_engine = Some(SearchEngine::DuckDuckGo);
+        config.logging = false;
+        config.paused = true;
+        let status = status_text(&report, &config, "");
+        assert!(status.contains("1 problem found, run `wedge doctor` to see it"));
+        assert!(status.contains("Links open in brave, the default browser is firefox"));
+        assert!(status.contains("Bing searches go to duckduckgo"));
+        assert!(status.contains("Logging is off"));
+        assert!(status.contains("Deflection is paused, so Edge opens links until `wedge resume`"));
+
+        report.installed = false;
+        assert!(status_text(&report, &config, "")
+            .starts_with(&format!("Wedge {} isn't installed", VERSION)));
+    }
+}
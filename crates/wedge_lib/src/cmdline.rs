@@ -0,0 +1,442 @@
+use std::fmt;
+
+/// Switch that makes chromium treat the rest of the command line as a single argument
+pub const SINGLE_ARGUMENT: &str = "--single-argument";
+
+/// Switch prefixes chromium understands on windows
+const SWITCH_PREFIXES: &[&str] = &["--", "-", "/"];
+
+/// An argument as interpreted by chromium
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Arg {
+    /// `--name` or `--name=value`. Chromium lowercases switch names on windows.
+    Switch { name: String, value: Option<String> },
+    /// Everything following `--single-argument`, taken verbatim
+    SingleArgument(String),
+    /// `--`, after which nothing is treated as a switch
+    Terminator,
+    /// Anything else, usually a url or a file
+    Positional(String),
+}
+
+/// A single argument split from the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Token {
+    /// Exact text of the argument including any whitespace before it
+    raw: String,
+    /// Argument with quotes and escapes removed
+    value: String,
+}
+
+/// Command line of a chromium based browser such as edge
+///
+/// Keeps the original text of every argument so it can be written back out unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandLine {
+    tokens: Vec<Token>,
+    args: Vec<Arg>,
+}
+
+impl CommandLine {
+    /// Parses a raw windows command line. The first argument is the program.
+    pub fn parse(raw: &str) -> CommandLine {
+        let mut tokens = vec![];
+        let mut rest = raw;
+        while !rest.trim_start_matches(is_separator).is_empty() {
+            let (token, remainder) = if tokens.is_empty() {
+                split_program(rest)
+            } else {
+                split_arg(rest)
+            };
+            tokens.push(token);
+            rest = remainder;
+        }
+
+        // Keep trailing whitespace so the command line is reproduced exactly
+        if !rest.is_empty() {
+            match tokens.last_mut() {
+                Some(token) => token.raw.push_str(rest),
+                None => tokens.push(Token {
+                    raw: String::from(rest),
+                    value: String::new(),
+                }),
+            }
+        }
+
+        CommandLine::from_tokens(tokens)
+    }
+
+    /// Builds a command line from already split arguments. The first argument is the program.
+    pub fn from_args<S: AsRef<str>>(args: &[S]) -> CommandLine {
+        CommandLine::from_tokens(
+            args.iter()
+                .enumerate()
+                .map(|(i, arg)| {
+                    let value = arg.as_ref();
+                    Token {
+                        raw: if i == 0 {
+                            quote(value)
+                        } else {
+                            format!(" {}", quote(value))
+                        },
+                        value: String::from(value),
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    fn from_tokens(tokens: Vec<Token>) -> CommandLine {
+        let mut args = vec![];
+        let mut terminated = false;
+        for (i, token) in tokens.iter().enumerate().skip(1) {
+            let value = &token.value;
+            if terminated {
+                args.push(Arg::Positional(value.clone()));
+            } else if value == "--" {
+                terminated = true;
+                args.push(Arg::Terminator);
+            } else if value == SINGLE_ARGUMENT {
+                // Chromium takes everything after the switch and a single separator verbatim
+                let rest: String = tokens[i + 1..].iter().map(|t| t.raw.as_str()).collect();
+                let mut chars = rest.chars();
+                if chars.clone().next().map_or(false, is_separator) {
+                    chars.next();
+                }
+                args.push(Arg::SingleArgument(chars.as_str().to_string()));
+                break;
+            } else {
+                args.push(parse_switch(value).unwrap_or_else(|| Arg::Positional(value.clone())));
+            }
+        }
+
+        CommandLine { tokens, args }
+    }
+
+    /// Path of the program being launched
+    pub fn program(&self) -> Option<&str> { self.tokens.first().map(|t| t.value.as_str()) }
+
+    /// Arguments after the program as interpreted by chromium
+    pub fn args(&self) -> &[Arg] { &self.args }
+
+    /// Is the switch present?
+    pub fn has_switch(&self, name: &str) -> bool {
+        self.switches().any(|(switch, _)| switch == name)
+    }
+
+    /// Value of the last occurrence of a switch, as chromium would read it
+    pub fn switch_value(&self, name: &str) -> Option<&str> {
+        self.switches()
+            .filter(|(switch, _)| *switch == name)
+            .last()
+            .and_then(|(_, value)| value)
+    }
+
+    /// Names and values of every switch
+    pub fn switches(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.args.iter().filter_map(|arg| match arg {
+            Arg::Switch { name, value } => {
+                Some((name.as_str(), value.as_ref().map(|v| v.as_str())))
+            }
+            _ => None,
+        })
+    }
+
+    /// Every positional argument, including the one following `--single-argument`
+    pub fn positionals(&self) -> Vec<&str> {
+        self.args
+            .iter()
+            .filter_map(|arg| match arg {
+                Arg::Positional(value) | Arg::SingleArgument(value) => Some(value.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Arguments split the way windows splits them for the launched process, including the
+    /// program
+    pub fn argv(&self) -> Vec<String> { self.tokens.iter().map(|t| t.value.clone()).collect() }
+
+    /// Drops the program so that the first argument becomes the program. This is how an IFEO
+    /// debugger finds the command line of the process it intercepted.
+    pub fn shift(&self) -> CommandLine {
+        let mut tokens: Vec<Token> = self.tokens.iter().skip(1).cloned().collect();
+        if let Some(first) = tokens.first_mut() {
+            first.raw = String::from(first.raw.trim_start_matches(is_separator));
+        }
+        CommandLine::from_tokens(tokens)
+    }
+}
+
+impl fmt::Display for CommandLine {
+    /// Writes out the command line exactly as it was parsed
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for token in &self.tokens {
+            f.write_str(&token.raw)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_separator(c: char) -> bool { c == ' ' || c == '\t' }
+
+/// Splits args the way the part of a command line after the program is split
+pub fn split_args(raw: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut rest = raw;
+    while !rest.trim_start_matches(is_separator).is_empty() {
+        let (token, remainder) = split_arg(rest);
+        args.push(token.value);
+        rest = remainder;
+    }
+    args
+}
+
+/// Parses chromium switches like `--name`, `-name=value` or `/name`
+fn parse_switch(value: &str) -> Option<Arg> {
+    let prefix = SWITCH_PREFIXES.iter().find(|p| value.starts_with(*p))?;
+    let switch = &value[prefix.len()..];
+    if switch.is_empty() {
+        return None;
+    }
+
+    let mut parts = switch.splitn(2, '=');
+    Some(Arg::Switch {
+        name: parts.next().unwrap_or_default().to_lowercase(),
+        value: parts.next().map(String::from),
+    })
+}
+
+/// Splits off the program, which windows parses without backslash escapes. A quoted program
+/// ends at the closing quote.
+fn split_program(raw: &str) -> (Token, &str) {
+    let start = raw.len() - raw.trim_start_matches(is_separator).len();
+    let program = &raw[start..];
+    let (value, end) = if program.starts_with('"') {
+        match program[1..].find('"') {
+            Some(i) => (&program[1..=i], start + i + 2),
+            None => (&program[1..], raw.len()),
+        }
+    } else {
+        let i = program.find(is_separator).unwrap_or_else(|| program.len());
+        (&program[..i], start + i)
+    };
+
+    (
+        Token {
+            raw: String::from(&raw[..end]),
+            value: String::from(value),
+        },
+        &raw[end..],
+    )
+}
+
+/// Splits off the next argument using the rules of `CommandLineToArgvW`
+///
+/// https://docs.microsoft.com/en-us/cpp/c-language/parsing-c-command-line-arguments
+fn split_arg(raw: &str) -> (Token, &str) {
+    let start = raw.len() - raw.trim_start_matches(is_separator).len();
+    let mut value = String::new();
+    let mut in_quotes = false;
+    let mut backslashes = 0;
+    let mut end = raw.len();
+    let mut chars = raw[start..].char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                // 2n backslashes and a quote produce n backslashes and toggle quoting,
+                // 2n+1 backslashes and a quote produce n backslashes and a literal quote
+                value.extend(std::iter::repeat('\\').take(backslashes / 2));
+                if backslashes % 2 == 1 {
+                    value.push('"');
+                } else if in_quotes && chars.peek().map(|(_, c)| *c) == Some('"') {
+                    // A doubled quote inside quotes is a literal quote
+                    chars.next();
+                    value.push('"');
+                } else {
+                    in_quotes = !in_quotes;
+                }
+                backslashes = 0;
+            }
+            _ => {
+                value.extend(std::iter::repeat('\\').take(backslashes));
+                backslashes = 0;
+                if is_separator(c) && !in_quotes {
+                    end = start + i;
+                    break;
+                }
+                value.push(c);
+            }
+        }
+    }
+    if end == raw.len() {
+        value.extend(std::iter::repeat('\\').take(backslashes));
+    }
+
+    (
+        Token {
+            raw: String::from(&raw[..end]),
+            value,
+        },
+        &raw[end..],
+    )
+}
+
+/// Quotes an argument so windows splits it back into the same value
+pub fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c: char| is_separator(c) || c == '"' || c == '\n') {
+        return String::from(arg);
+    }
+
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.extend(std::iter::repeat('\\').take(backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat('\\').take(backslashes));
+                backslashes = 0;
+            }
+        }
+        if c != '\\' {
+            quoted.push(c);
+        }
+    }
+    quoted.extend(std::iter::repeat('\\').take(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn switch(name: &str, value: Option<&str>) -> Arg {
+        Arg::Switch {
+            name: String::from(name),
+            value: value.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let raw = r#""C:\Program Files (x86)\Microsoft\Edge\Application\msedge.exe"  --profile-directory=Default -No-Startup-Window /flag "--user-data-dir=C:\Some Dir\\" https://example.com/ -- --not-a-switch"#;
+        let cmd = CommandLine::parse(raw);
+        assert_eq!(
+            Some(r"C:\Program Files (x86)\Microsoft\Edge\Application\msedge.exe"),
+            cmd.program()
+        );
+        assert_eq!(
+            &[
+                switch("profile-directory", Some("Default")),
+                switch("no-startup-window", None),
+                switch("flag", None),
+                switch("user-data-dir", Some(r"C:\Some Dir\")),
+                Arg::Positional(String::from("https://example.com/")),
+                Arg::Terminator,
+                Arg::Positional(String::from("--not-a-switch")),
+            ][..],
+            cmd.args()
+        );
+        assert!(cmd.has_switch("no-startup-window"));
+        assert!(!cmd.has_switch("not-a-switch"));
+        assert_eq!(Some("Default"), cmd.switch_value("profile-directory"));
+        assert_eq!(None, cmd.switch_value("flag"));
+        assert_eq!(
+            vec!["https://example.com/", "--not-a-switch"],
+            cmd.positionals()
+        );
+        assert_eq!(raw, cmd.to_string());
+        assert_eq!(
+            vec!["--new-window", r"--user-data-dir=C:\Some Dir\"],
+            split_args(r#" --new-window "--user-data-dir=C:\Some Dir\\" "#)
+        );
+    }
+
+    #[test]
+    fn test_single_argument() {
+        let raw = r#"msedge.exe --single-argument microsoft-edge:?url=https%3A%2F%2Fexample.com "quoted"  --flag "#;
+        let cmd = CommandLine::parse(raw);
+        assert_eq!(
+            &[Arg::SingleArgument(String::from(
+                r#"microsoft-edge:?url=https%3A%2F%2Fexample.com "quoted"  --flag "#
+            ))][..],
+            cmd.args()
+        );
+        assert!(!cmd.has_switch("flag"));
+        assert_eq!(raw, cmd.to_string());
+
+        let cmd = CommandLine::from_args(&["msedge.exe", "--single-argument", "https://a.com/"]);
+        assert_eq!(vec!["https://a.com/"], cmd.positionals());
+    }
+
+    #[test]
+    fn test_windows_splitting() {
+        for (raw, argv) in &[
+            (r#"a.exe "a b c" d e"#, vec!["a.exe", "a b c", "d", "e"]),
+            (r#"a.exe "ab\"c" "\\" d"#, vec![
+                "a.exe", r#"ab"c"#, r"\", "d",
+            ]),
+            (r#"a.exe a\\\b d"e f"g h"#, vec![
+                "a.exe", r"a\\\b", "de fg", "h",
+            ]),
+            (r#"a.exe a\\\"b c d"#, vec!["a.exe", r#"a\"b"#, "c", "d"]),
+            (r#"a.exe a\\\\"b c" d e"#, vec![
+                "a.exe", r"a\\b c", "d", "e",
+            ]),
+            (r#"a.exe "a""b" """#, vec!["a.exe", r#"a"b"#, ""]),
+            (r#""C:\dir\a.exe"x\" y"#, vec![
+                r"C:\dir\a.exe",
+                r#"x""#,
+                "y",
+            ]),
+            ("\ta.exe\t\tb ", vec!["a.exe", "b"]),
+        ] {
+            let cmd = CommandLine::parse(raw);
+            assert_eq!(*argv, cmd.argv());
+            assert_eq!(*raw, cmd.to_string());
+        }
+        assert_eq!(Vec::<String>::new(), CommandLine::parse("").argv());
+        assert_eq!("  ", CommandLine::parse("  ").to_string());
+    }
+
+    #[test]
+    fn test_from_args() {
+        let argv = vec![
+            r"C:\Program Files\a.exe",
+            "plain",
+            "",
+            "with space",
+            r#"quote"d"#,
+            r"trailing\",
+            r"trailing space\ ",
+            r#"\\"\"#,
+        ];
+        let cmd = CommandLine::from_args(&argv);
+        assert_eq!(argv, cmd.argv());
+        assert_eq!(argv, CommandLine::parse(&cmd.to_string()).argv());
+    }
+
+    #[test]
+    fn test_shift() {
+        let raw = r#""C:\Program Files (x86)\Wedge\wedge.exe" "C:\Program Files (x86)\Microsoft\Edge\Application\msedge.exe" --single-argument microsoft-edge:https://example.com"#;
+        let cmd = CommandLine::parse(raw).shift();
+        assert_eq!(
+            Some(r"C:\Program Files (x86)\Microsoft\Edge\Application\msedge.exe"),
+            cmd.program()
+        );
+        assert_eq!(
+            vec!["microsoft-edge:https://example.com"],
+            cmd.positionals()
+        );
+        assert_eq!(
+            r#""C:\Program Files (x86)\Microsoft\Edge\Application\msedge.exe" --single-argument microsoft-edge:https://example.com"#,
+            cmd.to_string()
+        );
+    }
+}
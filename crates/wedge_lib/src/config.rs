@@ -0,0 +1,690 @@
+use crate::{
+    cmdline::split_args,
+    invocation::{Action, InvocationClass},
+    policy::load_policies,
+    registry::{Hive, Key, RegValue, Registry},
+    rules::{Rule, Target},
+    search::SearchEngine,
+    system::System,
+};
+use serde::{de, Deserialize, Deserializer};
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt};
+
+/// Registry key user settings are read from
+pub const SETTINGS_KEY: &str = r"Software\Wedge";
+
+/// Version of the config file format this build reads
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Name of config files, kept in `%APPDATA%\Wedge` for a user and in the install location for
+/// every user
+pub const CONFIG_FILE_NAME: &str = "config.json";
+
+/// User settings for wedge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Browser deflected urls open in when no rule picks one
+    pub browser: Option<Target>,
+    /// Args that browser is launched with before the url
+    pub browser_args: Vec<String>,
+    /// Search engine bing searches are rewritten to. Bing is left alone when unset.
+    pub search_engine: Option<SearchEngine>,
+    /// Actions overriding the default action of an invocation class
+    pub actions: BTreeMap<InvocationClass, Action>,
+    /// Rules routing deflected urls to a browser, in the order they're evaluated
+    pub rules: Vec<Rule>,
+    /// Whether launch failures are logged
+    pub logging: bool,
+    /// Whether deflection is paused, which comes from `wedge pause` rather than settings
+    pub paused: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            browser: None,
+            browser_args: vec![],
+            search_engine: None,
+            actions: BTreeMap::new(),
+            rules: vec![],
+            logging: true,
+            paused: false,
+        }
+    }
+}
+
+impl Config {
+    /// Loads user settings from `HKCU\Software\Wedge`. Missing or invalid values are ignored.
+    pub fn load(registry: &dyn Registry) -> Config {
+        let mut config = Config::default();
+        config.load_registry(registry);
+        config
+    }
+
+    /// Loads settings from, in increasing precedence, the machine config file, the registry, the
+    /// user config file and group policy. Files and policies that don't parse are skipped,
+    /// passing why to `warn`.
+    pub fn load_all(
+        system: &dyn System,
+        paths: &ConfigPaths,
+        warn: &mut dyn FnMut(&str),
+    ) -> Config {
+        let mut config = Config::default();
+        let mut apply_file = |config: &mut Config, path: &str| match read_config_file(system, path)
+        {
+            Ok(Some(file)) => config.apply(file),
+            Ok(None) => {}
+            Err(e) => warn(&format!("Ignoring {}", e.in_file(path))),
+        };
+        apply_file(&mut config, &paths.machine);
+        config.load_registry(system.registry());
+        apply_file(&mut config, &paths.user);
+        config.apply(load_policies(system.registry(), warn));
+        config
+    }
+
+    /// Overrides settings with the ones a config file sets
+    pub fn apply(&mut self, file: ConfigFile) {
+        if file.browser.is_some() {
+            self.browser = file.browser;
+        }
+        if let Some(args) = file.browser_args {
+            self.browser_args = args;
+        }
+        if file.search_engine.is_some() {
+            self.search_engine = file.search_engine;
+        }
+        self.actions.extend(file.actions);
+        if let Some(rules) = file.rules {
+            self.rules = rules;
+        }
+        if let Some(logging) = file.logging {
+            self.logging = logging;
+        }
+    }
+
+    fn load_registry(&mut self, registry: &dyn Registry) {
+        let config = self;
+        if let Ok(settings) = Key::predef(registry, Hive::CurrentUser).open_subkey(SETTINGS_KEY) {
+            if let Some(browser) = settings
+                .get_string("Browser")
+                .ok()
+                .and_then(|name| Target::from_name(&name))
+            {
+                config.browser = Some(browser);
+            }
+            if let Ok(args) = settings.get_string("BrowserArgs") {
+                config.browser_args = split_args(&args);
+            }
+            if let Some(engine) = settings
+                .get_string("SearchEngine")
+                .ok()
+                .and_then(|name| SearchEngine::from_name(&name))
+            {
+                config.search_engine = Some(engine);
+            }
+
+            // Actions are stored as values named after the class in the Actions subkey
+            if let Ok(actions) = settings.open_subkey("Actions") {
+                for (name, value) in actions.enum_values().unwrap_or_default() {
+                    if let (Some(class), RegValue::String(action)) =
+                        (InvocationClass::from_name(&name), value)
+                    {
+                        if let Some(action) = Action::from_name(&action) {
+                            config.actions.insert(class, action);
+                        }
+                    }
+                }
+            }
+
+            if let Ok(rules) = settings.open_subkey("Rules") {
+                config.rules = load_rules(&rules);
+            }
+        }
+    }
+
+    /// Action to take for an invocation class
+    pub fn action(&self, class: InvocationClass) -> Action {
+        self.actions
+            .get(&class)
+            .cloned()
+            .unwrap_or_else(|| class.default_action())
+    }
+}
+
+/// Where config files are looked for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigPaths {
+    /// Defaults for every user, next to the installed wedge.exe
+    pub machine: String,
+    /// The user's own settings in `%APPDATA%\Wedge`
+    pub user: String,
+}
+
+impl ConfigPaths {
+    /// Config files of the running wedge and the current user
+    #[cfg(windows)]
+    pub fn locate() -> ConfigPaths {
+        let install_dir = crate::win32::get_self_location()
+            .ok()
+            .and_then(|path| Some(path.parent()?.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+        let app_data = std::env::var("APPDATA").unwrap_or_default();
+        ConfigPaths {
+            machine: format!(r"{}\{}", install_dir, CONFIG_FILE_NAME),
+            user: format!(r"{}\Wedge\{}", app_data, CONFIG_FILE_NAME),
+        }
+    }
+}
+
+/// Settings a config file holds. Anything left out keeps the value from before it.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ConfigFile {
+    #[serde(deserialize_with = "deserialize_version")]
+    pub version: u32,
+    pub browser: Option<Target>,
+    pub browser_args: Option<Vec<String>>,
+    pub search_engine: Option<SearchEngine>,
+    /// Actions by invocation class, the per-source toggles
+    #[serde(default)]
+    pub actions: BTreeMap<InvocationClass, Action>,
+    /// Replaces the rules from before when set
+    pub rules: Option<Vec<Rule>>,
+    pub logging: Option<bool>,
+}
+
+/// Why a config file is invalid, and where
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// Line and column of the problem, counting from 1. Zero when it isn't about a position.
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}:{}: {}", self.line, self.column, self.message)
+        }
+    }
+}
+
+impl ConfigError {
+    /// Describes the error prefixed with the file it is in, as `path:line:column: message`
+    pub fn in_file(&self, path: &str) -> String {
+        if self.line == 0 {
+            format!("{}: {}", path, self)
+        } else {
+            format!("{}:{}", path, self)
+        }
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> ConfigError {
+        // serde_json puts the position at the end of its message
+        let position = format!(" at line {} column {}", e.line(), e.column());
+        ConfigError {
+            line: e.line(),
+            column: e.column(),
+            message: e.to_string().trim_end_matches(&position).to_string(),
+        }
+    }
+}
+
+/// Text of a config file without the byte order mark Notepad saves UTF-8 files with
+fn strip_bom(text: &str) -> &str { text.trim_start_matches('\u{FEFF}') }
+
+/// Parses and validates the text of a config file
+pub fn parse_config(text: &str) -> Result<ConfigFile, ConfigError> {
+    Ok(serde_json::from_str(strip_bom(text))?)
+}
+
+/// Reads a config file, or `None` when there isn't one
+pub fn read_config_file(
+    system: &dyn System,
+    path: &str,
+) -> Result<Option<ConfigFile>, ConfigError> {
+    match system.read_file(path) {
+        Ok(contents) => parse_config(&String::from_utf8_lossy(&contents)).map(Some),
+        Err(ref e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(ConfigError {
+            line: 0,
+            column: 0,
+            message: e.to_string(),
+        }),
+    }
+}
+
+/// Value of a setting in the text of a config file, such as `search-engine` or
+/// `actions.notification`. Fails when the file isn't valid.
+pub fn get_setting(text: &str, key: &str) -> Result<Option<Value>, ConfigError> {
+    parse_config(text)?;
+    let json: Value = serde_json::from_str(strip_bom(text))?;
+    Ok(key
+        .split('.')
+        .try_fold(&json, |json, name| json.get(name))
+        .cloned())
+}
+
+/// Changes a setting in the text of a config file, or starts a new file when there is no text.
+/// The value is read as JSON, falling back on a plain string, and `null` removes the setting.
+/// Returns the new text, as long as the file is still valid with the change.
+pub fn set_setting(text: Option<&str>, key: &str, value: &str) -> Result<String, ConfigError> {
+    let mut json: Value = match text {
+        Some(text) => serde_json::from_str(strip_bom(text))?,
+        None => serde_json::json!({ "version": CONFIG_VERSION }),
+    };
+    let value = serde_json::from_str(value).unwrap_or_else(|_| Value::from(value));
+    insert_setting(&mut json, key, value)?;
+
+    let text = serde_json::to_string_pretty(&json).unwrap();
+    parse_config(&text)?;
+    Ok(text)
+}
+
+/// Sets a dotted key in the JSON of a config file, creating the objects it is in. `null`
+/// removes the key.
+pub(crate) fn insert_setting(json: &mut Value, key: &str, value: Value) -> Result<(), ConfigError> {
+    let invalid = || ConfigError {
+        line: 0,
+        column: 0,
+        message: format!("`{}` isn't inside an object", key),
+    };
+
+    let names: Vec<&str> = key.split('.').collect();
+    let (last, parents) = names.split_last().unwrap();
+    let mut object = json;
+    for name in parents {
+        object = object
+            .as_object_mut()
+            .ok_or_else(invalid)?
+            .entry(*name)
+            .or_insert_with(|| Value::Object(Default::default()));
+    }
+    let object = object.as_object_mut().ok_or_else(invalid)?;
+    if value.is_null() {
+        object.remove(*last);
+    } else {
+        object.insert(String::from(*last), value);
+    }
+    Ok(())
+}
+
+fn deserialize_version<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let version = u32::deserialize(deserializer)?;
+    if version == CONFIG_VERSION {
+        Ok(version)
+    } else {
+        Err(de::Error::custom(format!(
+            "unsupported version {}, expected {}",
+            version, CONFIG_VERSION
+        )))
+    }
+}
+
+/// Deserializes a setting from its name, as parsed by `from_name`
+fn deserialize_name<'de, D, T>(
+    deserializer: D,
+    what: &str,
+    from_name: fn(&str) -> Option<T>,
+) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = String::deserialize(deserializer)?;
+    from_name(&name).ok_or_else(|| de::Error::custom(format!("unknown {} `{}`", what, name)))
+}
+
+impl<'de> Deserialize<'de> for Target {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Target, D::Error> {
+        deserialize_name(deserializer, "browser", Target::from_name)
+    }
+}
+
+impl<'de> Deserialize<'de> for SearchEngine {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SearchEngine, D::Error> {
+        deserialize_name(deserializer, "search engine", SearchEngine::from_name)
+    }
+}
+
+impl<'de> Deserialize<'de> for InvocationClass {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<InvocationClass, D::Error> {
+        deserialize_name(deserializer, "invocation class", InvocationClass::from_name)
+    }
+}
+
+impl<'de> Deserialize<'de> for Action {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Action, D::Error> {
+        deserialize_name(deserializer, "action", Action::from_name)
+    }
+}
+
+/// Loads rules stored as numbered subkeys of `key`, evaluated in numeric order. Rules
+/// without a valid target or with an invalid pattern are skipped.
+fn load_rules(key: &Key) -> Vec<Rule> {
+    let mut names: Vec<(u32, String)> = key
+        .enum_keys()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|name| Some((name.parse().ok()?, name)))
+        .collect();
+    names.sort();
+
+    names
+        .into_iter()
+        .filter_map(|(_, name)| {
+            let key = key.open_subkey(&name).ok()?;
+            let rule = Rule {
+                host: key.get_string("Host").ok(),
+                path_prefix: key.get_string("PathPrefix").ok(),
+                path_regex: key.get_string("PathRegex").ok(),
+                scheme: key.get_string("Scheme").ok(),
+                target: Target::from_name(&key.get_string("Target").ok()?)?,
+                args: key
+                    .get_string("Args")
+                    .map(|args| split_args(&args))
+                    .unwrap_or_default(),
+            };
+            rule.validate().ok()?;
+            Some(rule)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{browser::Browser, registry::MemoryRegistry, system::MemorySystem};
+
+    #[test]
+    fn test_load() {
+        let registry = MemoryRegistry::new();
+        assert_eq!(Config::default(), Config::load(&registry));
+
+        let settings = Key::predef(&registry, Hive::CurrentUser)
+            .create_subkey(SETTINGS_KEY)
+            .unwrap();
+        settings.set_value("SearchEngine", "Kagi").unwrap();
+        let actions = settings.create_subkey("Actions").unwrap();
+        actions.set_value("web-url", "deflect").unwrap();
+        actions.set_value("notification", "Block").unwrap();
+        actions.set_value("unknown-class", "block").unwrap();
+        actions.set_value("web-app", "unknown-action").unwrap();
+        actions.set_value("other", 1u32).unwrap();
+
+        let config = Config::load(&registry);
+        assert_eq!(Some(SearchEngine::Kagi), config.search_engine);
+        assert_eq!(Action::Deflect, config.action(InvocationClass::WebUrl));
+        assert_eq!(Action::Block, config.action(InvocationClass::Notification));
+        assert_eq!(Action::PassThrough, config.action(InvocationClass::WebApp));
+        assert_eq!(Action::PassThrough, config.action(InvocationClass::Other));
+        assert_eq!(Action::Deflect, config.action(InvocationClass::ProtocolUrl));
+        assert_eq!(2, config.actions.len());
+        assert!(config.rules.is_empty());
+    }
+
+    #[test]
+    fn test_load_rules() {
+        let registry = MemoryRegistry::new();
+        let rules = Key::predef(&registry, Hive::CurrentUser)
+            .create_subkey(SETTINGS_KEY)
+            .unwrap()
+            .create_subkey("Rules")
+            .unwrap();
+        let rule = rules.create_subkey("10").unwrap();
+        rule.set_value("Scheme", "http").unwrap();
+        rule.set_value("Target", "chrome").unwrap();
+        rule.set_value("Args", "--incognito --new-window").unwrap();
+        let rule = rules.create_subkey("2").unwrap();
+        rule.set_value("Host", "*.sharepoint.com").unwrap();
+        rule.set_value("PathPrefix", "/sites/").unwrap();
+        rule.set_value("Target", "edge").unwrap();
+        let rule = rules.create_subkey("3").unwrap();
+        rule.set_value("PathRegex", "(").unwrap();
+        rule.set_value("Target", "firefox").unwrap();
+        let rule = rules.create_subkey("4").unwrap();
+        rule.set_value("Target", "netscape").unwrap();
+        let rule = rules.create_subkey("named").unwrap();
+        rule.set_value("Target", "firefox").unwrap();
+
+        assert_eq!(
+            vec![
+                Rule {
+                    host: Some(String::from("*.sharepoint.com")),
+                    path_prefix: Some(String::from("/sites/")),
+                    ..Rule::new(Target::Edge)
+                },
+                Rule {
+                    scheme: Some(String::from("http")),
+                    args: vec![String::from("--incognito"), String::from("--new-window")],
+                    ..Rule::new(Target::Browser(Browser::Chrome))
+                },
+            ],
+            Config::load(&registry).rules
+        );
+    }
+
+    const CONFIG: &str = r#"{
+    "version": 1,
+    "browser": "firefox",
+    "browser-args": ["-new-window"],
+    "search-engine": "DuckDuckGo",
+    "actions": { "notification": "block" },
+    "rules": [
+        { "host": "*.sharepoint.com", "target": "edge" },
+        { "path-regex": "^/watch", "target": "chrome", "args": ["--incognito"] }
+    ],
+    "logging": false
+}"#;
+
+    fn error(line: usize, column: usize, message: &str) -> Result<ConfigFile, ConfigError> {
+        Err(ConfigError {
+            line,
+            column,
+            message: String::from(message),
+        })
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let file = parse_config(CONFIG).unwrap();
+        assert_eq!(Some(Target::Browser(Browser::Firefox)), file.browser);
+        assert_eq!(Some(vec![String::from("-new-window")]), file.browser_args);
+        assert_eq!(Some(SearchEngine::DuckDuckGo), file.search_engine);
+        assert_eq!(
+            Some(&Action::Block),
+            file.actions.get(&InvocationClass::Notification)
+        );
+        assert_eq!(
+            Some(vec![
+                Rule {
+                    host: Some(String::from("*.sharepoint.com")),
+                    ..Rule::new(Target::Edge)
+                },
+                Rule {
+                    path_regex: Some(String::from("^/watch")),
+                    args: vec![String::from("--incognito")],
+                    ..Rule::new(Target::Browser(Browser::Chrome))
+                },
+            ]),
+            file.rules
+        );
+        assert_eq!(Some(false), file.logging);
+        assert_eq!(
+            ConfigFile {
+                version: 1,
+                ..ConfigFile::default()
+            },
+            parse_config(r#"{ "version": 1 }"#).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_config_errors() {
+        assert_eq!(error(1, 2, "missing field `version`"), parse_config("{}"));
+        assert_eq!(
+            error(1, 16, "unsupported version 2, expected 1"),
+            parse_config(r#"{ "version": 2 }"#)
+        );
+        assert_eq!(
+            error(
+                3,
+                12,
+                "unknown field `browsr`, expected one of `version`, `browser`, `browser-args`, \
+                 `search-engine`, `actions`, `rules`, `logging`"
+            ),
+            parse_config("{\n    \"version\": 1,\n    \"browsr\": \"firefox\"\n}")
+        );
+        assert_eq!(
+            error(2, 25, "unknown browser `netscape`"),
+            parse_config("{ \"version\": 1,\n  \"browser\": \"netscape\" }")
+        );
+        assert_eq!(
+            error(1, 49, "unknown action `never`"),
+            parse_config(r#"{ "version": 1, "actions": { "web-app": "never" } }"#)
+        );
+        assert!(parse_config(
+            r#"{ "version": 1, "rules": [{ "path-regex": "(", "target": "edge" }] }"#
+        )
+        .unwrap_err()
+        .message
+        .starts_with("invalid path regex"));
+        assert_eq!(
+            error(1, 17, "trailing comma"),
+            parse_config(r#"{ "version": 1, }"#)
+        );
+        assert_eq!(
+            "1:17: trailing comma",
+            parse_config(r#"{ "version": 1, }"#)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_byte_order_mark() {
+        let system = MemorySystem::new(&[r"C:\Wedge"]);
+        system
+            .write_file(
+                r"C:\Wedge\config.json",
+                b"\xEF\xBB\xBF{ \"version\": 1, \"logging\": false }",
+            )
+            .unwrap();
+        let file = read_config_file(&system, r"C:\Wedge\config.json")
+            .unwrap()
+            .unwrap();
+        assert_eq!(Some(false), file.logging);
+
+        let text = "\u{FEFF}{ \"version\": 1 }";
+        assert_eq!(None, get_setting(text, "browser").unwrap());
+        assert!(set_setting(Some(text), "browser", "firefox").is_ok());
+    }
+
+    #[test]
+    fn test_load_all() {
+        let system = MemorySystem::new(&[r"C:\Wedge", r"C:\Users\dev\AppData\Roaming\Wedge"]);
+        let paths = ConfigPaths {
+            machine: format!(r"C:\Wedge\{}", CONFIG_FILE_NAME),
+            user: format!(r"C:\Users\dev\AppData\Roaming\Wedge\{}", CONFIG_FILE_NAME),
+        };
+        let mut warnings = vec![];
+        assert_eq!(
+            Config::default(),
+            Config::load_all(&system, &paths, &mut |line| warnings
+                .push(String::from(line)))
+        );
+
+        // The registry goes over the machine defaults, and the user's file over both
+        system
+            .write_file(&paths.machine, CONFIG.as_bytes())
+            .unwrap();
+        let settings = Key::predef(&system.registry, Hive::CurrentUser)
+            .create_subkey(SETTINGS_KEY)
+            .unwrap();
+        settings.set_value("SearchEngine", "Kagi").unwrap();
+        settings.set_value("Browser", "brave").unwrap();
+        system
+            .write_file(&paths.user, br#"{ "version": 1, "browser": "vivaldi" }"#)
+            .unwrap();
+        let config = Config::load_all(&system, &paths, &mut |line| {
+            warnings.push(String::from(line))
+        });
+        assert_eq!(Some(Target::Browser(Browser::Vivaldi)), config.browser);
+        assert_eq!(Some(SearchEngine::Kagi), config.search_engine);
+        assert_eq!(Action::Block, config.action(InvocationClass::Notification));
+        assert_eq!(2, config.rules.len());
+        assert!(!config.logging);
+        assert!(warnings.is_empty());
+
+        // Broken files are skipped with a warning
+        system
+            .write_file(&paths.user, br#"{ "version": 1, "browser": 3 }"#)
+            .unwrap();
+        let config = Config::load_all(&system, &paths, &mut |line| {
+            warnings.push(String::from(line))
+        });
+        assert_eq!(Some(Target::Browser(Browser::Brave)), config.browser);
+        assert_eq!(
+            vec![format!(
+                "Ignoring {}:1:28: invalid type: integer `3`, expected a string",
+                paths.user
+            )],
+            warnings
+        );
+
+        // Group policy goes over everything
+        Key::predef(&system.registry, Hive::CurrentUser)
+            .create_subkey(crate::policy::POLICY_KEY)
+            .unwrap()
+            .set_value("Browser", "edge")
+            .unwrap();
+        let config = Config::load_all(&system, &paths, &mut |_| {});
+        assert_eq!(Some(Target::Edge), config.browser);
+        assert_eq!(Some(SearchEngine::Kagi), config.search_engine);
+    }
+
+    #[test]
+    fn test_settings() {
+        let text = set_setting(None, "browser", "firefox").unwrap();
+        assert_eq!(
+            Some(Value::from("firefox")),
+            get_setting(&text, "browser").unwrap()
+        );
+        let text = set_setting(Some(&text), "actions.notification", "block").unwrap();
+        let text = set_setting(Some(&text), "logging", "false").unwrap();
+        assert_eq!(
+            Some(Value::from("block")),
+            get_setting(&text, "actions.notification").unwrap()
+        );
+        assert_eq!(
+            Some(Value::from(false)),
+            get_setting(&text, "logging").unwrap()
+        );
+        assert_eq!(None, get_setting(&text, "search-engine").unwrap());
+
+        let text = set_setting(Some(&text), "browser", "null").unwrap();
+        assert_eq!(None, get_setting(&text, "browser").unwrap());
+        assert_eq!(
+            ConfigFile {
+                version: 1,
+                actions: vec![(InvocationClass::Notification, Action::Block)]
+                    .into_iter()
+                    .collect(),
+                logging: Some(false),
+                ..ConfigFile::default()
+            },
+            parse_config(&text).unwrap()
+        );
+
+        // Changes that would break the file are refused
+        assert!(set_setting(Some(&text), "browser", "netscape").is_err());
+        assert!(set_setting(Some(&text), "colour", "blue").is_err());
+        assert!(set_setting(Some(&text), "logging.file", "wedge.log").is_err());
+    }
+}
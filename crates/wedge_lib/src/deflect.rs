@@ -0,0 +1,698 @@
+use crate::{
+    browser::{Browser, BrowserProbe},
+    cmdline::CommandLine,
+    config::Config,
+    edge::EdgeInstall,
+    install::{MSEDGE_PATH, MSEDGE_PROXY_PATH},
+    invocation::{classify, Action, InvocationClass},
+    rules::{route, Target},
+    search::rewrite_bing_search,
+    uri::parse_edge_arg_url,
+};
+
+/// Details about the system wedge is deflecting edge on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Env {
+    /// Path to the edge executable wedge is registered as the debugger for
+    pub msedge_path: String,
+    /// Alternate path to the edge executable that our IFEO filter ignores
+    pub msedge_proxy_path: String,
+}
+
+impl Default for Env {
+    fn default() -> Env {
+        Env {
+            msedge_path: String::from(MSEDGE_PATH),
+            msedge_proxy_path: String::from(MSEDGE_PROXY_PATH),
+        }
+    }
+}
+
+impl Env {
+    /// Details about the edge install wedge was launched in place of, which windows passes as
+    /// the first arg. As the protocol handler, the first install found is used instead.
+    pub fn invoked(argv: &[String], edges: &[EdgeInstall]) -> Env {
+        let program = argv.get(1).map(String::as_str).unwrap_or_default();
+        edges
+            .iter()
+            .find(|edge| edge.path.eq_ignore_ascii_case(program))
+            .or_else(|| edges.first())
+            .map_or_else(Env::default, |edge| Env {
+                msedge_path: edge.path.clone(),
+                msedge_proxy_path: edge.proxy_path(),
+            })
+    }
+}
+
+/// A url deflected away from edge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    /// Url to open
+    pub url: String,
+    /// Url edge was asked to open, when it was rewritten to the configured search engine
+    pub rewritten_from: Option<String>,
+    /// Browser to open the url with
+    pub target: Target,
+    /// Args the browser is launched with before the url
+    pub args: Vec<String>,
+}
+
+impl Link {
+    /// Link opened with the default browser
+    pub fn new(url: &str) -> Link {
+        Link {
+            url: String::from(url),
+            rewritten_from: None,
+            target: Target::DefaultBrowser,
+            args: vec![],
+        }
+    }
+}
+
+/// What wedge should do with an invocation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Open the urls with their target browsers, in order
+    OpenUrl(Vec<Link>),
+    /// Launch edge with the same args it would have originally been called with
+    PassThrough { program: String, args: Vec<String> },
+    /// Don't launch anything
+    Block,
+}
+
+/// A step taken while deciding how to handle an invocation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stage {
+    /// Edge's command line, as parsed from the args wedge was launched with
+    Parsed {
+        program: Option<String>,
+        args: Vec<String>,
+    },
+    /// Wedge wasn't launched in place of edge, so nothing is launched
+    NotDebugger,
+    /// Deflection is paused, so edge handles everything
+    Paused,
+    /// Why edge was launched, and the action configured for it
+    Classified {
+        class: InvocationClass,
+        action: Action,
+    },
+    /// Url found in a positional arg, unwrapped from a `microsoft-edge:` url when it is one
+    Extracted { arg: String, url: Option<String> },
+    /// Browser windows opens links with
+    DefaultBrowser(Browser),
+    /// A bing search rewritten to the configured search engine
+    Rewritten { from: String, to: String },
+    /// Index of the rule matching a url, if any, and the target the url was routed to
+    Routed {
+        url: String,
+        rule: Option<usize>,
+        target: Target,
+    },
+}
+
+/// Decides how to handle wedge being launched with `command_line`, which has wedge's own path
+/// shifted off
+///
+/// When registered as the IFEO debugger, windows launches wedge with the command line edge was
+/// meant to be launched with appended, so what is left is exactly that. As the
+/// "microsoft-edge:" protocol handler, it is launched with just the url, which is handled as if
+/// edge was asked to open it.
+pub fn decide(
+    command_line: &CommandLine,
+    env: &Env,
+    config: &Config,
+    probe: &dyn BrowserProbe,
+) -> Decision {
+    decide_traced(command_line, env, config, probe, &mut |_| {})
+}
+
+/// Decides like `decide`, passing each step taken to `trace`
+pub fn decide_traced(
+    command_line: &CommandLine,
+    env: &Env,
+    config: &Config,
+    probe: &dyn BrowserProbe,
+    trace: &mut dyn FnMut(Stage),
+) -> Decision {
+    let edge = match command_line.argv().as_slice() {
+        [url] if url.starts_with("microsoft-edge:") => CommandLine::from_args(&[
+            env.msedge_path.clone(),
+            String::from("--single-argument"),
+            url.clone(),
+        ]),
+        _ => command_line.clone(),
+    };
+    trace(Stage::Parsed {
+        program: edge.program().map(String::from),
+        args: edge.argv().into_iter().skip(1).collect(),
+    });
+
+    let is_running_as_debugger = edge.program().map_or(false, |program| {
+        program.eq_ignore_ascii_case(&env.msedge_path)
+    });
+    if !is_running_as_debugger {
+        trace(Stage::NotDebugger);
+        return Decision::Block;
+    }
+
+    let pass_through = Decision::PassThrough {
+        // Get path to edge executable through alternate execution path that avoids ifeo
+        program: env.msedge_proxy_path.clone(),
+        args: edge.argv().split_off(1),
+    };
+
+    if config.paused {
+        trace(Stage::Paused);
+        return pass_through;
+    }
+
+    let class = classify(&edge);
+    let action = config.action(class);
+    trace(Stage::Classified { class, action });
+    match action {
+        Action::PassThrough => return pass_through,
+        Action::Block => return Decision::Block,
+        Action::Deflect => {}
+    }
+
+    // Only deflect when every url can be opened elsewhere, so nothing is silently dropped
+    let positionals = edge.positionals();
+    let mut urls: Vec<String> = vec![];
+    for arg in &positionals {
+        let url = parse_edge_arg_url(arg);
+        trace(Stage::Extracted {
+            arg: String::from(*arg),
+            url: url.clone(),
+        });
+        urls.extend(url);
+    }
+    if urls.is_empty() || urls.len() != positionals.len() {
+        return pass_through;
+    }
+
+    // Rules still apply when edge is the default browser, it just has nothing to deflect to
+    let default_browser = probe.default_browser();
+    trace(Stage::DefaultBrowser(default_browser));
+    let edge_is_default = default_browser == Browser::Edge;
+    let links: Vec<Link> = urls
+        .into_iter()
+        .map(|url| {
+            let mut link = match config
+                .search_engine
+                .as_ref()
+                .and_then(|engine| rewrite_bing_search(&url, engine))
+            {
+                Some(rewritten) => {
+                    trace(Stage::Rewritten {
+                        from: url.clone(),
+                        to: rewritten.clone(),
+                    });
+                    Link {
+                        rewritten_from: Some(url),
+                        ..Link::new(&rewritten)
+                    }
+                }
+                None => Link::new(&url),
+            };
+            let (rule, target) = route(
+                &config.rules,
+                &link.url,
+                config.browser.unwrap_or(Target::DefaultBrowser),
+            );
+            trace(Stage::Routed {
+                url: link.url.clone(),
+                rule,
+                target,
+            });
+            link.target = match target {
+                Target::DefaultBrowser if edge_is_default => Target::Edge,
+                target => target,
+            };
+            link.args = match rule {
+                Some(index) => config.rules[index].args.clone(),
+                None => config.browser_args.clone(),
+            };
+            link
+        })
+        .collect();
+
+    // Let edge open the urls exactly as it was asked to when none are leaving it
+    if links.iter().all(|link| link.target == Target::Edge) {
+        return pass_through;
+    }
+
+    Decision::OpenUrl(links)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{invocation::InvocationClass, rules::Rule, search::SearchEngine};
+
+    struct FakeProbe(Browser);
+
+    impl BrowserProbe for FakeProbe {
+        fn default_browser(&self) -> Browser { self.0 }
+    }
+
+    /// Edge's command line, as left once wedge's path is shifted off
+    fn edge(args: &[&str]) -> CommandLine {
+        CommandLine::from_args(&[&[MSEDGE_PATH], args].concat())
+    }
+
+    fn pass_through(args: &[&str]) -> Decision {
+        Decision::PassThrough {
+            program: String::from(MSEDGE_PROXY_PATH),
+            args: args.iter().map(|a| String::from(*a)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_not_debugger() {
+        let probe = FakeProbe(Browser::Firefox);
+        let config = Config::default();
+        let env = Env::default();
+        let shifted = |raw: &str| CommandLine::parse(raw).shift();
+        assert_eq!(Decision::Block, decide(&shifted(""), &env, &config, &probe));
+        assert_eq!(
+            Decision::Block,
+            decide(
+                &shifted(r#""C:\Program Files (x86)\Wedge\wedge.exe""#),
+                &env,
+                &config,
+                &probe
+            )
+        );
+        assert_eq!(
+            Decision::Block,
+            decide(
+                &shifted("wedge.exe http://example.com"),
+                &env,
+                &config,
+                &probe
+            )
+        );
+    }
+
+    #[test]
+    fn test_invoked() {
+        let stable = EdgeInstall::from_path(MSEDGE_PATH);
+        let beta =
+            EdgeInstall::from_path(r"C:\Program Files\Microsoft\Edge Beta\Application\msedge.exe");
+        let edges = vec![stable, beta.clone()];
+        let invoked = |program: &str| {
+            Env::invoked(&[String::from("wedge.exe"), String::from(program)], &edges)
+        };
+        assert_eq!(
+            Env {
+                msedge_path: beta.path.clone(),
+                msedge_proxy_path: beta.proxy_path(),
+            },
+            invoked(&beta.path.to_uppercase())
+        );
+        assert_eq!(Env::default(), invoked("microsoft-edge:http://example.com"));
+        assert_eq!(
+            Env::default(),
+            Env::invoked(&[String::from("wedge.exe"), String::from(MSEDGE_PATH)], &[])
+        );
+    }
+
+    #[test]
+    fn test_protocol_handler() {
+        let probe = FakeProbe(Browser::Firefox);
+        let config = Config::default();
+        let env = Env::default();
+        let protocol_argv = |url: &str| CommandLine::from_args(&[url]);
+        assert_eq!(
+            Decision::OpenUrl(vec![Link::new("http://example.com")]),
+            decide(
+                &protocol_argv("microsoft-edge:http://example.com"),
+                &env,
+                &config,
+                &probe
+            )
+        );
+        assert_eq!(
+            pass_through(&["--single-argument", "microsoft-edge:about:blank"]),
+            decide(
+                &protocol_argv("microsoft-edge:about:blank"),
+                &env,
+                &config,
+                &probe
+            )
+        );
+    }
+
+    #[test]
+    fn test_open_url() {
+        let probe = FakeProbe(Browser::Firefox);
+        let config = Config::default();
+        let env = Env::default();
+        assert_eq!(
+            Decision::OpenUrl(vec![Link::new("http://example.com")]),
+            decide(
+                &edge(&[
+                    "--single-argument",
+                    "microsoft-edge:?url=http%3A%2F%2Fexample.com"
+                ]),
+                &env,
+                &config,
+                &probe
+            )
+        );
+        assert_eq!(
+            Decision::OpenUrl(vec![Link::new(
+                "https://www.bing.com/search?q=wedge&form=WNSGPH"
+            )]),
+            decide(
+                &edge(&["microsoft-edge:https://www.bing.com/search?q=wedge&form=WNSGPH"]),
+                &env,
+                &config,
+                &probe
+            )
+        );
+    }
+
+    #[test]
+    fn test_single_argument_verbatim() {
+        let probe = FakeProbe(Browser::Firefox);
+        let config = Config::default();
+        let env = Env::default();
+
+        // Quotes after `--single-argument` are part of the url, as they are to edge
+        let command_line = CommandLine::parse(&format!(
+            r#""C:\Program Files (x86)\Wedge\wedge.exe" "{}" --single-argument https://example.com/?q="wedge""#,
+            MSEDGE_PATH
+        ));
+        assert_eq!(
+            Decision::OpenUrl(vec![Link::new(r#"https://example.com/?q="wedge""#)]),
+            decide(&command_line.shift(), &env, &config, &probe)
+        );
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let probe = FakeProbe(Browser::Chrome);
+        let config = Config {
+            search_engine: Some(SearchEngine::DuckDuckGo),
+            ..Config::default()
+        };
+        let env = Env::default();
+        assert_eq!(
+            Decision::OpenUrl(vec![Link {
+                rewritten_from: Some(String::from(
+                    "https://www.bing.com/search?q=wedge&form=WNSGPH"
+                )),
+                ..Link::new("https://duckduckgo.com/?q=wedge")
+            }]),
+            decide(
+                &edge(&["microsoft-edge:https://www.bing.com/search?q=wedge&form=WNSGPH"]),
+                &env,
+                &config,
+                &probe
+            )
+        );
+        assert_eq!(
+            Decision::OpenUrl(vec![Link::new("https://example.com/")]),
+            decide(
+                &edge(&["microsoft-edge:https://example.com/"]),
+                &env,
+                &config,
+                &probe
+            )
+        );
+    }
+
+    #[test]
+    fn test_pass_through() {
+        let config = Config::default();
+        let env = Env::default();
+
+        // Edge is the default browser
+        let probe = FakeProbe(Browser::Edge);
+        let args = ["--single-argument", "microsoft-edge:http://example.com"];
+        assert_eq!(
+            pass_through(&args),
+            decide(&edge(&args), &env, &config, &probe)
+        );
+
+        // Nothing to deflect
+        let probe = FakeProbe(Browser::Firefox);
+        assert_eq!(pass_through(&[]), decide(&edge(&[]), &env, &config, &probe));
+        let args = ["--type=renderer", "--field-trial-handle=1234"];
+        assert_eq!(
+            pass_through(&args),
+            decide(&edge(&args), &env, &config, &probe)
+        );
+        let args = ["microsoft-edge:calc.exe"];
+        assert_eq!(
+            pass_through(&args),
+            decide(&edge(&args), &env, &config, &probe)
+        );
+    }
+
+    #[test]
+    fn test_paused() {
+        let probe = FakeProbe(Browser::Firefox);
+        let config = Config {
+            paused: true,
+            ..Config::default()
+        };
+        let env = Env::default();
+        let args = ["--single-argument", "microsoft-edge:http://example.com"];
+        assert_eq!(
+            pass_through(&args),
+            decide(&edge(&args), &env, &config, &probe)
+        );
+
+        // Even invocations that would be blocked reach edge
+        assert_eq!(
+            pass_through(&["--notification-launch-id=1"]),
+            decide(
+                &edge(&["--notification-launch-id=1"]),
+                &env,
+                &Config {
+                    actions: vec![(InvocationClass::Notification, Action::Block)]
+                        .into_iter()
+                        .collect(),
+                    ..config
+                },
+                &probe
+            )
+        );
+    }
+
+    #[test]
+    fn test_actions() {
+        let probe = FakeProbe(Browser::Firefox);
+        let env = Env::default();
+        let mut config = Config::default();
+        config
+            .actions
+            .insert(InvocationClass::ProtocolUrl, Action::PassThrough);
+        config
+            .actions
+            .insert(InvocationClass::Notification, Action::Block);
+
+        let args = ["microsoft-edge:http://example.com"];
+        assert_eq!(
+            pass_through(&args),
+            decide(&edge(&args), &env, &config, &probe)
+        );
+        let args = ["--notification-launch-id=1", "microsoft-edge:http://a.com"];
+        assert_eq!(Decision::Block, decide(&edge(&args), &env, &config, &probe));
+
+        // Edge internals are never deflected by default
+        let config = Config::default();
+        let args = ["--type=utility", "microsoft-edge:http://example.com"];
+        assert_eq!(
+            pass_through(&args),
+            decide(&edge(&args), &env, &config, &probe)
+        );
+    }
+
+    #[test]
+    fn test_web_urls() {
+        let probe = FakeProbe(Browser::Firefox);
+        let config = Config {
+            search_engine: Some(SearchEngine::Google),
+            ..Config::default()
+        };
+        let env = Env::default();
+
+        // Outlook and teams launch edge with plain urls
+        assert_eq!(
+            Decision::OpenUrl(vec![Link::new("https://example.com/mail")]),
+            decide(
+                &edge(&["--single-argument", "https://example.com/mail"]),
+                &env,
+                &config,
+                &probe
+            )
+        );
+
+        // Every url is opened in order
+        assert_eq!(
+            Decision::OpenUrl(vec![
+                Link::new("https://a.example.com/"),
+                Link {
+                    rewritten_from: Some(String::from("https://www.bing.com/search?q=b")),
+                    ..Link::new("https://www.google.com/search?q=b")
+                },
+                Link::new("http://c.example.com/"),
+            ]),
+            decide(
+                &edge(&[
+                    "--profile-directory=Default",
+                    "https://a.example.com/",
+                    "microsoft-edge:https://www.bing.com/search?q=b",
+                    "http://c.example.com/"
+                ]),
+                &env,
+                &config,
+                &probe
+            )
+        );
+
+        // Anything that can't be deflected is left to edge
+        let args = ["https://example.com/", r"C:\some.pdf"];
+        assert_eq!(
+            pass_through(&args),
+            decide(&edge(&args), &env, &config, &probe)
+        );
+    }
+
+    #[test]
+    fn test_rules() {
+        let env = Env::default();
+        let config = Config {
+            rules: vec![
+                Rule {
+                    host: Some(String::from("*.sharepoint.com")),
+                    ..Rule::new(Target::Edge)
+                },
+                Rule {
+                    host: Some(String::from("github.com")),
+                    ..Rule::new(Target::Browser(Browser::Firefox))
+                },
+            ],
+            ..Config::default()
+        };
+        let link = |url: &str, target: Target| Link {
+            target,
+            ..Link::new(url)
+        };
+
+        // Each url goes to its own browser
+        let probe = FakeProbe(Browser::Chrome);
+        assert_eq!(
+            Decision::OpenUrl(vec![
+                link("https://contoso.sharepoint.com/", Target::Edge),
+                link("https://github.com/", Target::Browser(Browser::Firefox)),
+                link("https://example.com/", Target::DefaultBrowser),
+            ]),
+            decide(
+                &edge(&[
+                    "https://contoso.sharepoint.com/",
+                    "https://github.com/",
+                    "https://example.com/"
+                ]),
+                &env,
+                &config,
+                &probe
+            )
+        );
+
+        // Edge handles the invocation untouched when every url is routed to it
+        let args = ["--single-argument", "https://contoso.sharepoint.com/"];
+        assert_eq!(
+            pass_through(&args),
+            decide(&edge(&args), &env, &config, &probe)
+        );
+
+        // Rules still deflect when edge is the default browser
+        let probe = FakeProbe(Browser::Edge);
+        assert_eq!(
+            Decision::OpenUrl(vec![
+                link("https://github.com/", Target::Browser(Browser::Firefox)),
+                link("https://example.com/", Target::Edge),
+            ]),
+            decide(
+                &edge(&["https://github.com/", "https://example.com/"]),
+                &env,
+                &config,
+                &probe
+            )
+        );
+
+        // Urls no rule picks a browser for open in the preferred one
+        let config = Config {
+            browser: Some(Target::Browser(Browser::Brave)),
+            ..config
+        };
+        assert_eq!(
+            Decision::OpenUrl(vec![
+                link("https://github.com/", Target::Browser(Browser::Firefox)),
+                link("https://example.com/", Target::Browser(Browser::Brave)),
+            ]),
+            decide(
+                &edge(&["https://github.com/", "https://example.com/"]),
+                &env,
+                &config,
+                &probe
+            )
+        );
+
+        // Rules can still send urls to the default browser
+        let probe = FakeProbe(Browser::Chrome);
+        let config = Config {
+            rules: vec![Rule {
+                host: Some(String::from("example.com")),
+                ..Rule::new(Target::DefaultBrowser)
+            }],
+            ..config
+        };
+        assert_eq!(
+            Decision::OpenUrl(vec![
+                link("https://example.com/", Target::DefaultBrowser),
+                link("https://github.com/", Target::Browser(Browser::Brave)),
+            ]),
+            decide(
+                &edge(&["https://example.com/", "https://github.com/"]),
+                &env,
+                &config,
+                &probe
+            )
+        );
+
+        // Browsers are launched with the args of the rule, or of the preferred browser
+        let config = Config {
+            browser_args: vec![String::from("-new-window")],
+            rules: vec![Rule {
+                host: Some(String::from("example.com")),
+                args: vec![String::from("--incognito")],
+                ..Rule::new(Target::Browser(Browser::Chrome))
+            }],
+            ..config
+        };
+        assert_eq!(
+            Decision::OpenUrl(vec![
+                Link {
+                    args: vec![String::from("--incognito")],
+                    ..link("https://example.com/", Target::Browser(Browser::Chrome))
+                },
+                Link {
+                    args: vec![String::from("-new-window")],
+                    ..link("https://github.com/", Target::Browser(Browser::Brave))
+                },
+            ]),
+            decide(
+                &edge(&["https://example.com/", "https://github.com/"]),
+                &env,
+                &config,
+                &probe
+            )
+        );
+    }
+}
@@ -0,0 +1,413 @@
+use crate::{
+    browser::{get_default_prog_id, Browser},
+    cli::{EXIT_FAILURE, EXIT_NOT_INSTALLED, EXIT_OK},
+    edge::EdgeInstall,
+    install::{
+        application_actions, ifeo_actions, ifeo_filters, InstallPaths, Scope, APP_NAME,
+        BINARY_NAME, LICENSE_NAME, UNINSTALLER_NAME,
+    },
+    plan::{Action, FileRecord, Manifest},
+    registry::{join, Key},
+    system::System,
+};
+#[cfg(windows)]
+use crate::{
+    edge::{discover, known_folders},
+    error::WedgeError,
+    install::start_menu_location,
+    system::WindowsSystem,
+};
+use serde_json::json;
+
+/// Outcome of checking one thing install set up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: String,
+    /// What is wrong, if anything
+    pub problem: Option<String>,
+}
+
+impl Check {
+    fn new(name: String, result: Result<(), String>) -> Check {
+        Check {
+            name,
+            problem: result.err(),
+        }
+    }
+}
+
+/// Health of a wedge install
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub scope: Scope,
+    /// Is the wedge binary there at all?
+    pub installed: bool,
+    pub checks: Vec<Check>,
+    pub default_browser: Browser,
+    /// ProgId of the default handler for http links
+    pub prog_id: Option<String>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool { self.checks.iter().all(|check| check.problem.is_none()) }
+
+    /// Exit code for detection scripts: 0 when healthy, 1 when a check failed and 2 when wedge
+    /// isn't installed
+    pub fn exit_code(&self) -> i32 {
+        if !self.installed {
+            EXIT_NOT_INSTALLED
+        } else if !self.passed() {
+            EXIT_FAILURE
+        } else {
+            EXIT_OK
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![format!("Wedge doctor ({} install)", scope_name(self.scope))];
+        for check in &self.checks {
+            lines.push(match &check.problem {
+                None => format!("[ OK ] {}", check.name),
+                Some(problem) => format!("[FAIL] {}: {}", check.name, problem),
+            });
+        }
+        lines.push(format!(
+            "Default browser: {} ({})",
+            browser_name(self.default_browser),
+            self.prog_id.as_ref().map_or("no ProgId", String::as_str)
+        ));
+        let failed = self.checks.iter().filter(|c| c.problem.is_some()).count();
+        lines.push(match (self.installed, failed) {
+            (false, _) => String::from("Wedge isn't installed"),
+            (true, 0) => String::from("All checks passed"),
+            (true, failed) => format!("{} of {} checks failed", failed, self.checks.len()),
+        });
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        let checks: Vec<_> = self
+            .checks
+            .iter()
+            .map(|check| {
+                json!({
+                    "name": check.name,
+                    "passed": check.problem.is_none(),
+                    "problem": check.problem,
+                })
+            })
+            .collect();
+        serde_json::to_string_pretty(&json!({
+            "scope": scope_name(self.scope),
+            "installed": self.installed,
+            "passed": self.passed(),
+            "exit_code": self.exit_code(),
+            "default_browser": browser_name(self.default_browser),
+            "prog_id": self.prog_id,
+            "checks": checks,
+        }))
+        .unwrap()
+    }
+}
+
+fn scope_name(scope: Scope) -> &'static str {
+    match scope {
+        Scope::Machine => "machine",
+        Scope::User => "user",
+    }
+}
+
+fn browser_name(browser: Browser) -> &'static str {
+    browser.info().map_or("unknown", |info| info.name)
+}
+
+/// Checks a file against what install recorded writing
+fn check_file(system: &dyn System, expected: &FileRecord) -> Result<(), String> {
+    let contents = system
+        .read_file(&expected.path)
+        .map_err(|e| e.to_string())?;
+    let actual = FileRecord::new(&expected.path, &contents);
+    if actual.size != expected.size {
+        Err(format!(
+            "{} bytes, expected {} bytes",
+            actual.size, expected.size
+        ))
+    } else if actual.hash != expected.hash {
+        Err(String::from("Contents changed since install"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks a registry value install set is still there
+fn check_value(system: &dyn System, action: &Action) -> Option<Check> {
+    let (hive, path, name, value) = match action {
+        Action::SetValue {
+            hive,
+            path,
+            name,
+            value,
+        } => (hive, path, name, value),
+        _ => return None,
+    };
+    let actual = Key::predef(system.registry(), *hive)
+        .open_subkey(path)
+        .and_then(|key| key.get_value(name));
+    Some(Check::new(
+        format!(
+            "Registry value `{}` of `{}`",
+            if name.is_empty() { "(default)" } else { name },
+            join(hive.name(), path)
+        ),
+        match actual {
+            Ok(ref actual) if actual == value => Ok(()),
+            Ok(actual) => Err(format!("{}, expected {}", actual, value)),
+            Err(e) => Err(e.to_string()),
+        },
+    ))
+}
+
+/// Checks everything an install for `scope` set up is still in place
+pub fn diagnose(
+    system: &dyn System,
+    scope: Scope,
+    paths: &InstallPaths,
+    start_menu_dir: &str,
+    edges: Vec<EdgeInstall>,
+) -> Report {
+    let mut checks = vec![];
+
+    // Files are checked against the manifest, or just for being there when it's missing
+    let manifest_path = paths.manifest_path();
+    let manifest = Manifest::load(system, &manifest_path);
+    checks.push(Check::new(
+        format!("Install manifest `{}`", manifest_path),
+        manifest.as_ref().map(|_| ()).map_err(|e| e.to_string()),
+    ));
+    let files = match manifest {
+        Ok(ref manifest) if !manifest.files.is_empty() => manifest.files.clone(),
+        _ => vec![],
+    };
+    if files.is_empty() {
+        for name in &[BINARY_NAME, UNINSTALLER_NAME, LICENSE_NAME] {
+            let path = join(&paths.install_dir, name);
+            let exists = system.path_exists(&path);
+            checks.push(Check::new(
+                format!("File `{}`", path),
+                if exists {
+                    Ok(())
+                } else {
+                    Err(String::from("Missing"))
+                },
+            ));
+        }
+    }
+    for file in &files {
+        checks.push(Check::new(
+            format!("File `{}`", file.path),
+            check_file(system, file),
+        ));
+    }
+
+    // The uninstaller's size is only known from the manifest
+    let install_size = if files.is_empty() {
+        None
+    } else {
+        Some(files.iter().map(|file| file.size as u32).sum())
+    };
+    let mut actions = application_actions(scope, paths, install_size);
+
+    if scope == Scope::Machine {
+        checks.push(Check::new(
+            String::from("Edge installs"),
+            if edges.is_empty() {
+                Err(String::from("None found"))
+            } else {
+                Ok(())
+            },
+        ));
+        let edges = ifeo_filters(system.registry(), paths, edges);
+        for (edge, _) in &edges {
+            let proxy = edge.proxy_path();
+            checks.push(Check::new(
+                format!("Proxy `{}`", proxy),
+                match system.read_link(&proxy) {
+                    Ok(ref target) if target.eq_ignore_ascii_case(&edge.path) => Ok(()),
+                    Ok(target) => Err(format!("Points to `{}`, expected `{}`", target, edge.path)),
+                    Err(e) => Err(e.to_string()),
+                },
+            ));
+        }
+        actions.extend(ifeo_actions(paths, &edges));
+    }
+    checks.extend(
+        actions
+            .iter()
+            .filter_map(|action| check_value(system, action)),
+    );
+
+    let shortcut = format!(r"{}\{}.lnk", start_menu_dir, APP_NAME);
+    checks.push(Check::new(
+        format!("Shortcut `{}`", shortcut),
+        if system.path_exists(&shortcut) {
+            Ok(())
+        } else {
+            Err(String::from("Missing"))
+        },
+    ));
+
+    let prog_id = get_default_prog_id(system.registry()).ok();
+    Report {
+        scope,
+        installed: system.path_exists(&paths.binary_path()),
+        checks,
+        default_browser: prog_id
+            .as_ref()
+            .map_or(Browser::Unknown, |prog_id| Browser::from_prog_id(prog_id)),
+        prog_id,
+    }
+}
+
+/// Checks the install on this machine. Without a scope, the install for every user is checked
+/// unless only a per-user install exists.
+#[cfg(windows)]
+pub fn doctor(scope: Option<Scope>) -> Result<Report, WedgeError> {
+    let scope = match scope {
+        Some(scope) => scope,
+        None => {
+            let user = InstallPaths::for_scope(Scope::User)?;
+            if !WindowsSystem.path_exists(&InstallPaths::default().install_dir)
+                && WindowsSystem.path_exists(&user.install_dir)
+            {
+                Scope::User
+            } else {
+                Scope::Machine
+            }
+        }
+    };
+    Ok(diagnose(
+        &WindowsSystem,
+        scope,
+        &InstallPaths::for_scope(scope)?,
+        &start_menu_location(scope)?.to_string_lossy(),
+        discover(&WindowsSystem, &known_folders()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        install::{install_plan, InstallContext, IFEO_KEY, MSEDGE_PATH},
+        plan::Runner,
+        registry::Hive,
+        system::MemorySystem,
+    };
+
+    const START_MENU: &str = r"C:\ProgramData\Microsoft\Windows\Start Menu\Programs";
+
+    fn installed() -> MemorySystem {
+        let system = MemorySystem::new(&[
+            r"C:",
+            r"C:\Program Files (x86)",
+            r"C:\Program Files (x86)\Microsoft\Edge\Application",
+            START_MENU,
+        ]);
+        let paths = InstallPaths::default();
+        let context = InstallContext {
+            scope: Scope::Machine,
+            files: vec![
+                (BINARY_NAME, vec![1; 2048]),
+                (UNINSTALLER_NAME, vec![2; 1024]),
+                (LICENSE_NAME, b"MIT".to_vec()),
+            ],
+            start_menu_dir: String::from(START_MENU),
+            default_browser: Browser::Firefox,
+            edges: ifeo_filters(&system.registry, &paths, vec![stable()]),
+            paths,
+        };
+        Runner::new(&system, install_plan(&context))
+            .with_manifest(&context.paths.manifest_path(), Manifest::default())
+            .run(|_| {})
+            .unwrap();
+        Key::predef(&system.registry, Hive::CurrentUser)
+            .create_subkey(
+                r"Software\Microsoft\Windows\Shell\Associations\UrlAssociations\http\UserChoice",
+            )
+            .unwrap()
+            .set_value("Progid", "FirefoxURL")
+            .unwrap();
+        system
+    }
+
+    fn stable() -> EdgeInstall { EdgeInstall::from_path(MSEDGE_PATH) }
+
+    fn diagnose_machine(system: &MemorySystem) -> Report {
+        diagnose(
+            system,
+            Scope::Machine,
+            &InstallPaths::default(),
+            START_MENU,
+            vec![stable()],
+        )
+    }
+
+    fn problems(report: &Report) -> Vec<(&str, &str)> {
+        report
+            .checks
+            .iter()
+            .filter_map(|check| Some((check.name.as_str(), check.problem.as_ref()?.as_str())))
+            .collect()
+    }
+
+    #[test]
+    fn test_healthy() {
+        let report = diagnose_machine(&installed());
+        assert_eq!(Vec::<(&str, &str)>::new(), problems(&report));
+        assert_eq!(0, report.exit_code());
+        assert_eq!(Browser::Firefox, report.default_browser);
+        assert_eq!(Some(String::from("FirefoxURL")), report.prog_id);
+        assert!(report.to_text().ends_with("All checks passed"));
+    }
+
+    #[test]
+    fn test_broken() {
+        let system = installed();
+        let paths = InstallPaths::default();
+        system.write_file(&paths.binary_path(), &[0; 2048]).unwrap();
+        system.remove_file(&stable().proxy_path()).unwrap();
+        Key::predef(&system.registry, Hive::LocalMachine)
+            .open_subkey(IFEO_KEY)
+            .unwrap()
+            .set_value("UseFilter", 0u32)
+            .unwrap();
+
+        let report = diagnose_machine(&system);
+        let problems = problems(&report);
+        assert_eq!(
+            vec![
+                r"File `C:\Program Files (x86)\Wedge\wedge.exe`",
+                r"Proxy `C:\Program Files (x86)\Microsoft\Edge\Application\msedge-wedge-proxy.exe`",
+                r"Registry value `UseFilter` of `HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows NT\CurrentVersion\Image File Execution Options\msedge.exe`",
+            ],
+            problems.iter().map(|(name, _)| *name).collect::<Vec<_>>()
+        );
+        assert_eq!("Contents changed since install", problems[0].1);
+        assert_eq!(1, report.exit_code());
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json!(false), json["passed"]);
+        assert_eq!(json!(1), json["exit_code"]);
+        assert_eq!(json!("firefox"), json["default_browser"]);
+    }
+
+    #[test]
+    fn test_not_installed() {
+        let system = MemorySystem::new(&[r"C:"]);
+        let report = diagnose_machine(&system);
+        assert!(!report.installed);
+        assert_eq!(2, report.exit_code());
+        assert_eq!(Browser::Unknown, report.default_browser);
+        assert!(report.to_text().ends_with("Wedge isn't installed"));
+    }
+}
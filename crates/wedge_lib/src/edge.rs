@@ -0,0 +1,241 @@
+use crate::{
+    browser::Channel,
+    registry::{join, Hive, Key},
+    system::{parent, System},
+};
+
+/// Name of the symlink to edge created next to each install, which the IFEO filter ignores
+pub const PROXY_NAME: &str = "msedge-wedge-proxy.exe";
+
+/// Every channel of edge, stable first
+pub const CHANNELS: [Channel; 4] = [
+    Channel::Stable,
+    Channel::Beta,
+    Channel::Dev,
+    Channel::Canary,
+];
+
+/// Keys EdgeUpdate registers each channel under, 32 bit view first
+const CLIENTS_KEYS: [&str; 2] = [
+    r"SOFTWARE\WOW6432Node\Microsoft\EdgeUpdate\Clients",
+    r"SOFTWARE\Microsoft\EdgeUpdate\Clients",
+];
+
+/// App Paths entry edge registers so it can be launched by name
+const APP_PATH_KEY: &str = r"SOFTWARE\Microsoft\Windows\CurrentVersion\App Paths\msedge.exe";
+
+/// Id EdgeUpdate knows a channel by
+fn client_id(channel: Channel) -> &'static str {
+    match channel {
+        Channel::Stable => "{56EB18F8-B008-4CBD-B6D2-8C97FE7E9062}",
+        Channel::Beta => "{2CD8A007-E189-409D-A2C8-9AF4EF3C72AA}",
+        Channel::Dev => "{0D50BFEC-CD6A-4F9A-964C-C7416E3ACB10}",
+        Channel::Canary => "{65C35B14-6C1D-4122-AC46-7148CC9D6497}",
+    }
+}
+
+/// Folder a channel installs to, under Program Files or the local appdata folder
+fn folder_name(channel: Channel) -> &'static str {
+    match channel {
+        Channel::Stable => "Edge",
+        Channel::Beta => "Edge Beta",
+        Channel::Dev => "Edge Dev",
+        Channel::Canary => "Edge SxS",
+    }
+}
+
+/// An install of edge found on the machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EdgeInstall {
+    pub channel: Channel,
+    /// Path to msedge.exe
+    pub path: String,
+}
+
+impl EdgeInstall {
+    /// Works out the channel of an msedge.exe from the folder it is installed in
+    pub fn from_path(path: &str) -> EdgeInstall {
+        let folder = parent(path)
+            .and_then(parent)
+            .and_then(|dir| dir.rsplit('\\').next())
+            .unwrap_or_default();
+        let channel = CHANNELS
+            .iter()
+            .cloned()
+            .find(|channel| folder.eq_ignore_ascii_case(folder_name(*channel)))
+            .unwrap_or(Channel::Stable);
+        EdgeInstall {
+            channel,
+            path: String::from(path),
+        }
+    }
+
+    /// Alternate path to this install that our IFEO filter ignores
+    pub fn proxy_path(&self) -> String { join(parent(&self.path).unwrap_or_default(), PROXY_NAME) }
+}
+
+/// Folders edge may be installed under, from the environment
+#[cfg(windows)]
+pub fn known_folders() -> Vec<String> {
+    let mut folders: Vec<String> = vec![];
+    for name in &[
+        "ProgramFiles(x86)",
+        "ProgramW6432",
+        "ProgramFiles",
+        "LOCALAPPDATA",
+    ] {
+        if let Ok(folder) = std::env::var(name) {
+            if !folders.iter().any(|f| f.eq_ignore_ascii_case(&folder)) {
+                folders.push(folder);
+            }
+        }
+    }
+    folders
+}
+
+/// Finds every install of edge, stable first. Installs EdgeUpdate and App Paths know about are
+/// found wherever they are, the rest are looked for in `folders`. Paths that don't exist are
+/// skipped.
+pub fn discover(system: &dyn System, folders: &[String]) -> Vec<EdgeInstall> {
+    let mut installs: Vec<EdgeInstall> = vec![];
+    let mut add = |install: EdgeInstall| {
+        if system.path_exists(&install.path)
+            && !installs
+                .iter()
+                .any(|i| i.path.eq_ignore_ascii_case(&install.path))
+        {
+            installs.push(install);
+        }
+    };
+
+    for hive in &[Hive::LocalMachine, Hive::CurrentUser] {
+        let root = Key::predef(system.registry(), *hive);
+
+        // EdgeUpdate records the folder of every channel it keeps up to date
+        for clients in &CLIENTS_KEYS {
+            for channel in &CHANNELS {
+                let location = root
+                    .open_subkey(&join(clients, client_id(*channel)))
+                    .and_then(|client| client.get_string("location"));
+                if let Ok(location) = location {
+                    add(EdgeInstall {
+                        channel: *channel,
+                        path: join(location.trim_matches('"'), "msedge.exe"),
+                    });
+                }
+            }
+        }
+
+        if let Ok(path) = root
+            .open_subkey(APP_PATH_KEY)
+            .and_then(|app_path| app_path.get_string(""))
+        {
+            add(EdgeInstall::from_path(path.trim_matches('"')));
+        }
+    }
+
+    for folder in folders {
+        for channel in &CHANNELS {
+            add(EdgeInstall {
+                channel: *channel,
+                path: join(
+                    folder,
+                    &format!(
+                        r"Microsoft\{}\Application\msedge.exe",
+                        folder_name(*channel)
+                    ),
+                ),
+            });
+        }
+    }
+
+    installs.sort_by_key(|install| CHANNELS.iter().position(|c| *c == install.channel));
+    installs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::MemorySystem;
+
+    const STABLE: &str = r"C:\Program Files (x86)\Microsoft\Edge\Application\msedge.exe";
+    const BETA: &str = r"C:\Program Files\Microsoft\Edge Beta\Application\msedge.exe";
+    const CANARY: &str = r"C:\Users\dev\AppData\Local\Microsoft\Edge SxS\Application\msedge.exe";
+
+    fn install(channel: Channel, path: &str) -> EdgeInstall {
+        EdgeInstall {
+            channel,
+            path: String::from(path),
+        }
+    }
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(
+            install(Channel::Stable, STABLE),
+            EdgeInstall::from_path(STABLE)
+        );
+        assert_eq!(install(Channel::Beta, BETA), EdgeInstall::from_path(BETA));
+        assert_eq!(
+            install(Channel::Canary, CANARY),
+            EdgeInstall::from_path(CANARY)
+        );
+        assert_eq!(
+            r"C:\Program Files\Microsoft\Edge Beta\Application\msedge-wedge-proxy.exe",
+            EdgeInstall::from_path(BETA).proxy_path()
+        );
+    }
+
+    #[test]
+    fn test_discover() {
+        let installs = [STABLE, BETA, CANARY];
+        let dirs: Vec<&str> = installs.iter().map(|path| parent(path).unwrap()).collect();
+        let system = MemorySystem::new(&dirs);
+        for path in &installs {
+            system.write_file(path, b"").unwrap();
+        }
+        assert!(discover(&system, &[]).is_empty());
+
+        // Canary is only found in the known folders
+        let folders = vec![
+            String::from(r"C:\Program Files (x86)"),
+            String::from(r"C:\Users\dev\AppData\Local"),
+        ];
+        assert_eq!(
+            vec![
+                install(Channel::Stable, STABLE),
+                install(Channel::Canary, CANARY)
+            ],
+            discover(&system, &folders)
+        );
+
+        // Registered installs are found anywhere, and installs that are gone are skipped
+        let hklm = Key::predef(&system.registry, Hive::LocalMachine);
+        hklm.create_subkey(&join(CLIENTS_KEYS[0], client_id(Channel::Beta)))
+            .unwrap()
+            .set_value(
+                "location",
+                r"C:\Program Files\Microsoft\Edge Beta\Application",
+            )
+            .unwrap();
+        hklm.create_subkey(&join(CLIENTS_KEYS[0], client_id(Channel::Dev)))
+            .unwrap()
+            .set_value(
+                "location",
+                r"C:\Program Files\Microsoft\Edge Dev\Application",
+            )
+            .unwrap();
+        hklm.create_subkey(APP_PATH_KEY)
+            .unwrap()
+            .set_value("", STABLE)
+            .unwrap();
+        assert_eq!(
+            vec![
+                install(Channel::Stable, STABLE),
+                install(Channel::Beta, BETA),
+                install(Channel::Canary, CANARY)
+            ],
+            discover(&system, &folders[1..])
+        );
+    }
+}
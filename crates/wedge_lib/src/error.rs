@@ -0,0 +1,159 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+
+/// Everything that can go wrong in wedge, with enough context to tell the user what to fix
+#[derive(Debug)]
+pub enum WedgeError {
+    /// Reading or writing the registry failed
+    Registry {
+        /// Full path of the key, starting with its hive
+        key: String,
+        /// Name of the value, when the failure was about one
+        value: Option<String>,
+        source: io::Error,
+    },
+    /// Reading or writing a file or directory failed
+    Filesystem { path: PathBuf, source: io::Error },
+    /// Something needs wedge to run as administrator
+    Elevation {
+        /// What was being done, such as "create symlinks"
+        operation: &'static str,
+    },
+    /// A file embedded in the installer couldn't be unpacked
+    Resource { name: &'static str },
+    /// A browser couldn't be found or identified
+    BrowserDetection(String),
+    /// There was nothing to try opening a link or launch with
+    NoFallbacks,
+    /// Any other windows api call failed
+    Os {
+        /// What was being done, such as "locate the temp folder"
+        operation: &'static str,
+        source: io::Error,
+    },
+    /// A step of an install plan failed
+    Step {
+        step: usize,
+        steps: usize,
+        source: Box<WedgeError>,
+    },
+}
+
+impl WedgeError {
+    /// Wraps filesystem errors with the path they happened on, for use with `map_err`
+    pub fn filesystem<P: Into<PathBuf>>(path: P) -> impl FnOnce(io::Error) -> WedgeError {
+        let path = path.into();
+        move |source| WedgeError::Filesystem { path, source }
+    }
+
+    /// Wraps the last windows error with what was being done
+    pub fn last_os_error(operation: &'static str) -> WedgeError {
+        WedgeError::Os {
+            operation,
+            source: io::Error::last_os_error(),
+        }
+    }
+
+    /// Whether the failure was something not existing, which callers often ignore
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            WedgeError::Registry { source, .. }
+            | WedgeError::Filesystem { source, .. }
+            | WedgeError::Os { source, .. } => source.kind() == ErrorKind::NotFound,
+            WedgeError::Step { source, .. } => source.is_not_found(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for WedgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WedgeError::Registry {
+                key,
+                value: None,
+                source,
+            } => write!(f, "Registry key `{}`: {}", key, source),
+            WedgeError::Registry {
+                key,
+                value: Some(value),
+                source,
+            } => write!(f, "Registry value `{}` of `{}`: {}", value, key, source),
+            WedgeError::Filesystem { path, source } => {
+                write!(f, "File `{}`: {}", path.display(), source)
+            }
+            WedgeError::Elevation { operation } => write!(
+                f,
+                "Administrator rights are needed to {}. Run wedge as administrator.",
+                operation
+            ),
+            WedgeError::Resource { name } => write!(
+                f,
+                "Failed unpacking `{}` from the installer. Try downloading it again.",
+                name
+            ),
+            WedgeError::BrowserDetection(message) => write!(f, "{}", message),
+            WedgeError::NoFallbacks => {
+                write!(f, "No browser or fallback is configured that could open it")
+            }
+            WedgeError::Os { operation, source } => {
+                write!(f, "Failed to {}: {}", operation, source)
+            }
+            WedgeError::Step {
+                step,
+                steps,
+                source,
+            } => write!(f, "Error on step {}/{} {}", step, steps, source),
+        }
+    }
+}
+
+impl Error for WedgeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WedgeError::Registry { source, .. }
+            | WedgeError::Filesystem { source, .. }
+            | WedgeError::Os { source, .. } => Some(source),
+            WedgeError::Step { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let error = WedgeError::Step {
+            step: 2,
+            steps: 5,
+            source: Box::new(WedgeError::Registry {
+                key: String::from(r"HKEY_LOCAL_MACHINE\Software\Wedge"),
+                value: Some(String::from("Debugger")),
+                source: io::Error::new(ErrorKind::PermissionDenied, "Access is denied."),
+            }),
+        };
+        assert_eq!(
+            r"Error on step 2/5 Registry value `Debugger` of `HKEY_LOCAL_MACHINE\Software\Wedge`: Access is denied.",
+            error.to_string()
+        );
+        assert!(error.source().is_some());
+        assert!(!error.is_not_found());
+
+        let error = WedgeError::filesystem(r"C:\Wedge")(io::Error::from(ErrorKind::NotFound));
+        assert!(error.is_not_found());
+        assert_eq!(
+            "Administrator rights are needed to create symlinks. Run wedge as administrator.",
+            WedgeError::Elevation {
+                operation: "create symlinks"
+            }
+            .to_string()
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use crate::{
+    browser::{Browser, BrowserProbe},
+    cmdline::{quote, CommandLine},
+    config::Config,
+    deflect::{decide_traced, Decision, Env, Stage},
+};
+use serde_json::{json, Value};
+
+/// How wedge handles an invocation, step by step
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    pub stages: Vec<Stage>,
+    pub decision: Decision,
+}
+
+/// Args wedge would be launched with for what `wedge explain` was given: a `microsoft-edge:`
+/// url, or edge's command line with or without the path to edge first
+pub fn explain_argv(args: &[String], env: &Env) -> Vec<String> {
+    let mut argv = vec![String::from("wedge.exe")];
+    let has_program = args.first().map_or(false, |first| {
+        first.starts_with("microsoft-edge:") || first.to_lowercase().ends_with("msedge.exe")
+    });
+    if !has_program {
+        argv.push(env.msedge_path.clone());
+    }
+    argv.extend(args.iter().cloned());
+    argv
+}
+
+/// Decides how to handle wedge being launched with `argv` exactly as deflecting does, without
+/// launching anything
+pub fn explain(
+    argv: &[String],
+    env: &Env,
+    config: &Config,
+    probe: &dyn BrowserProbe,
+) -> Explanation {
+    let mut stages = vec![];
+    let decision = decide_traced(
+        &CommandLine::from_args(argv).shift(),
+        env,
+        config,
+        probe,
+        &mut |stage| stages.push(stage),
+    );
+    Explanation { stages, decision }
+}
+
+fn browser_name(browser: Browser) -> &'static str {
+    browser.info().map_or("unknown", |info| info.name)
+}
+
+fn join_args(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl Explanation {
+    pub fn to_text(&self) -> String {
+        let mut lines: Vec<String> = self
+            .stages
+            .iter()
+            .enumerate()
+            .map(|(i, stage)| {
+                let text = match stage {
+                    Stage::Parsed { program, args } => format!(
+                        "Command line: program {}, args {}",
+                        program
+                            .as_ref()
+                            .map_or_else(|| String::from("(none)"), |program| quote(program)),
+                        if args.is_empty() {
+                            String::from("(none)")
+                        } else {
+                            join_args(args)
+                        }
+                    ),
+                    Stage::NotDebugger => {
+                        String::from("Not a launch of edge, so there is nothing to handle")
+                    }
+                    Stage::Paused => String::from("Deflection is paused, so edge handles it"),
+                    Stage::Classified { class, action } => format!(
+                        "Invocation class: {}, which is set to {}",
+                        class.name(),
+                        action.name()
+                    ),
+                    Stage::Extracted {
+                        arg,
+                        url: Some(url),
+                    } if url == arg => {
+                        format!("Url: {}", url)
+                    }
+                    Stage::Extracted {
+                        arg,
+                        url: Some(url),
+                    } => {
+                        format!("Url: {} unwrapped from {}", url, arg)
+                    }
+                    Stage::Extracted { arg, url: None } => {
+                        format!("Url: none in {}, so edge has to open it", arg)
+                    }
+                    Stage::DefaultBrowser(browser) => {
+                        format!("Default browser: {}", browser_name(*browser))
+                    }
+                    Stage::Rewritten { from, to } => {
+                        format!("Bing search: {} rewritten to {}", from, to)
+                    }
+                    Stage::Routed {
+                        url,
+                        rule: Some(rule),
+                        target,
+                    } => format!(
+                        "Rules: rule {} matches {}, sending it to {}",
+                        rule + 1,
+                        url,
+                        target.name()
+                    ),
+                    Stage::Routed {
+                        url,
+                        rule: None,
+                        target,
+                    } => format!("Rules: none match {}, sending it to {}", url, target.name()),
+                };
+                format!("{}. {}", i + 1, text)
+            })
+            .collect();
+
+        match &self.decision {
+            Decision::OpenUrl(links) => {
+                for link in links {
+                    lines.push(if link.args.is_empty() {
+                        format!("Decision: open {} in {}", link.url, link.target.name())
+                    } else {
+                        format!(
+                            "Decision: open {} in {} with {}",
+                            link.url,
+                            link.target.name(),
+                            join_args(&link.args)
+                        )
+                    });
+                }
+            }
+            Decision::PassThrough { program, args } => lines.push(format!(
+                "Decision: pass through to {} {}",
+                quote(program),
+                join_args(args)
+            )),
+            Decision::Block => lines.push(String::from("Decision: launch nothing")),
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> String {
+        let stages: Vec<Value> = self
+            .stages
+            .iter()
+            .map(|stage| match stage {
+                Stage::Parsed { program, args } => json!({
+                    "stage": "parse",
+                    "program": program,
+                    "args": args,
+                }),
+                Stage::NotDebugger => json!({ "stage": "not-edge" }),
+                Stage::Paused => json!({ "stage": "paused" }),
+                Stage::Classified { class, action } => json!({
+                    "stage": "class",
+                    "class": class.name(),
+                    "action": action.name(),
+                }),
+                Stage::Extracted { arg, url } => json!({
+                    "stage": "url",
+                    "arg": arg,
+                    "url": url,
+                }),
+                Stage::DefaultBrowser(browser) => json!({
+                    "stage": "default-browser",
+                    "browser": browser_name(*browser),
+                }),
+                Stage::Rewritten { from, to } => json!({
+                    "stage": "rewrite",
+                    "from": from,
+                    "to": to,
+                }),
+                Stage::Routed { url, rule, target } => json!({
+                    "stage": "rule",
+                    "url": url,
+                    "rule_index": rule,
+                    "target": target.name(),
+                }),
+            })
+            .collect();
+        let decision = match &self.decision {
+            Decision::OpenUrl(links) => {
+                let links: Vec<Value> = links
+                    .iter()
+                    .map(|link| {
+                        json!({
+                            "url": link.url,
+                            "rewritten_from": link.rewritten_from,
+                            "target": link.target.name(),
+                            "args": link.args,
+                        })
+                    })
+                    .collect();
+                json!({ "action": "open", "links": links })
+            }
+            Decision::PassThrough { program, args } => json!({
+                "action": "pass-through",
+                "program": program,
+                "args": args,
+            }),
+            Decision::Block => json!({ "action": "block" }),
+        };
+        serde_json::to_string_pretty(&json!({
+            "stages": stages,
+            "decision": decision,
+        }))
+        .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        install::{MSEDGE_PATH, MSEDGE_PROXY_PATH},
+        rules::{Rule, Target},
+        search::SearchEngine,
+    };
+
+    struct FakeProbe(Browser);
+
+    impl BrowserProbe for FakeProbe {
+        fn default_browser(&self) -> Browser { self.0 }
+    }
+
+    fn args(args: &[&str]) -> Vec<String> { args.iter().map(|arg| String::from(*arg)).collect() }
+
+    #[test]
+    fn test_explain_argv() {
+        let env = Env::default();
+        let uri = "microsoft-edge:https://example.com";
+        assert_eq!(args(&["wedge.exe", uri]), explain_argv(&args(&[uri]), &env));
+        assert_eq!(
+            args(&["wedge.exe", MSEDGE_PATH, "--flag"]),
+            explain_argv(&args(&[MSEDGE_PATH, "--flag"]), &env)
+        );
+        assert_eq!(
+            args(&["wedge.exe", MSEDGE_PATH, "https://example.com"]),
+            explain_argv(&args(&["https://example.com"]), &env)
+        );
+    }
+
+    #[test]
+    fn test_explain() {
+        let env = Env::default();
+        let config = Config {
+            search_engine: Some(SearchEngine::DuckDuckGo),
+            rules: vec![Rule {
+                host: Some(String::from("*.sharepoint.com")),
+                ..Rule::new(Target::Edge)
+            }],
+            ..Config::default()
+        };
+        let probe = FakeProbe(Browser::Firefox);
+
+        let argv = explain_argv(
+            &args(&["microsoft-edge:?url=https%3A%2F%2Fwww.bing.com%2Fsearch%3Fq%3Dwedge"]),
+            &env,
+        );
+        let explanation = explain(&argv, &env, &config, &probe);
+        assert_eq!(
+            explanation.decision,
+            crate::deflect::decide(
+                &CommandLine::from_args(&argv).shift(),
+                &env,
+                &config,
+                &probe
+            )
+        );
+        assert_eq!(
+            format!(
+                "1. Command line: program \"{}\", args --single-argument \
+                 microsoft-edge:?url=https%3A%2F%2Fwww.bing.com%2Fsearch%3Fq%3Dwedge\n\
+                 2. Invocation class: protocol-url, which is set to deflect\n\
+                 3. Url: https://www.bing.com/search?q=wedge unwrapped from \
+                 microsoft-edge:?url=https%3A%2F%2Fwww.bing.com%2Fsearch%3Fq%3Dwedge\n\
+                 4. Default browser: firefox\n\
+                 5. Bing search: https://www.bing.com/search?q=wedge rewritten to \
+                 https://duckduckgo.com/?q=wedge\n\
+                 6. Rules: none match https://duckduckgo.com/?q=wedge, sending it to default\n\
+                 Decision: open https://duckduckgo.com/?q=wedge in default",
+                MSEDGE_PATH
+            ),
+            explanation.to_text()
+        );
+
+        // Edge opens what it was asked to when every url stays in it
+        let argv = explain_argv(&args(&["https://contoso.sharepoint.com/"]), &env);
+        let json: Value =
+            serde_json::from_str(&explain(&argv, &env, &config, &probe).to_json()).unwrap();
+        assert_eq!(
+            json!({ "stage": "rule", "url": "https://contoso.sharepoint.com/", "rule_index": 0, "target": "edge" }),
+            json["stages"][4]
+        );
+        assert_eq!(
+            json!({ "action": "pass-through", "program": MSEDGE_PROXY_PATH, "args": ["https://contoso.sharepoint.com/"] }),
+            json["decision"]
+        );
+
+        let config = Config {
+            browser_args: args(&["-private-window"]),
+            ..config
+        };
+        let argv = explain_argv(&args(&["https://example.com/"]), &env);
+        assert!(explain(&argv, &env, &config, &probe)
+            .to_text()
+            .ends_with("Decision: open https://example.com/ in default with -private-window"));
+
+        let text = explain(&args(&["wedge.exe", "status"]), &env, &config, &probe).to_text();
+        assert!(text.ends_with(
+            "2. Not a launch of edge, so there is nothing to handle\nDecision: launch nothing"
+        ));
+    }
+}
@@ -0,0 +1,251 @@
+use crate::{
+    edge::EdgeInstall,
+    install::{application_actions, ifeo_actions, InstallPaths, Scope, BINARY_NAME, IFEO_KEY},
+    plan::Action,
+    registry::{join, Hive, RegValue},
+};
+
+/// Registry changes made by install for a single edge, leaving out files, shortcuts and the proxy
+fn registry_actions(paths: &InstallPaths, msedge_path: &str) -> Vec<Action> {
+    let mut actions = application_actions(Scope::Machine, paths, None);
+    actions.extend(ifeo_actions(paths, &[(
+        EdgeInstall::from_path(msedge_path),
+        0,
+    )]));
+    actions
+}
+
+/// Renders the registry changes made by install as a `.reg` file for regedit. The IFEO filter
+/// always uses index 0.
+pub fn reg_file(paths: &InstallPaths, msedge_path: &str) -> String {
+    let mut lines = vec![String::from("Windows Registry Editor Version 5.00")];
+    let mut section = String::new();
+    for action in registry_actions(paths, msedge_path) {
+        let (hive, path) = match &action {
+            Action::CreateKey { hive, path }
+            | Action::CreateSharedKey { hive, path }
+            | Action::SetValue { hive, path, .. } => (hive, path),
+            _ => continue,
+        };
+        let key = join(hive.name(), path);
+        if key != section {
+            lines.push(String::new());
+            lines.push(format!("[{}]", key));
+            section = key;
+        }
+        if let Action::SetValue { name, value, .. } = &action {
+            let name = if name.is_empty() {
+                String::from("@")
+            } else {
+                reg_string(name)
+            };
+            let value = match value {
+                RegValue::String(value) => reg_string(value),
+                RegValue::Dword(value) => format!("dword:{:08x}", value),
+                raw @ RegValue::Raw { .. } => raw.to_string(),
+            };
+            lines.push(format!("{}={}", name, value));
+        }
+    }
+    lines.push(String::new());
+    lines.join("\r\n")
+}
+
+/// Quotes a string for a `.reg` file
+fn reg_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+const SCRIPT_HELPERS: &str = r#"$ErrorActionPreference = 'Stop'
+
+function New-RegistryKey([string]$Path) {
+    if (-not (Test-Path -LiteralPath $Path)) {
+        New-Item -Path $Path -Force | Out-Null
+    }
+}
+
+function Set-RegistryValue([string]$Path, [string]$Name, $Value, [string]$Type) {
+    if ($Name -eq '') {
+        $Name = '(default)'
+    }
+    New-ItemProperty -LiteralPath $Path -Name $Name -Value $Value -PropertyType $Type -Force | Out-Null
+}
+"#;
+
+const SCRIPT_IFEO_FILTER: &str = r#"# Reuse the IFEO filter already pointing at wedge, otherwise take the lowest free one
+$IfeoFilter = $null
+if (Test-Path -LiteralPath $Ifeo) {
+    foreach ($Filter in Get-ChildItem -LiteralPath $Ifeo) {
+        if ((Get-ItemProperty -LiteralPath $Filter.PSPath).Debugger -eq $Debugger) {
+            $IfeoFilter = $Filter.PSChildName
+        }
+    }
+}
+if ($null -eq $IfeoFilter) {
+    $IfeoFilter = 0
+    while (Test-Path -LiteralPath "$Ifeo\$IfeoFilter") {
+        $IfeoFilter++
+    }
+}
+"#;
+
+/// Renders the registry changes made by install as a PowerShell script that is safe to run
+/// again. The install directory and edge path become parameters defaulting to the ones given,
+/// and the IFEO filter is picked when the script runs.
+pub fn powershell_script(paths: &InstallPaths, msedge_path: &str) -> String {
+    let ifeo_key = registry_path(Hive::LocalMachine, IFEO_KEY);
+    // Longest first so the IFEO filter key isn't taken for the IFEO key
+    let substitutions = [
+        (format!(r"{}\0", ifeo_key), r"$Ifeo\$IfeoFilter"),
+        (ifeo_key.clone(), "$Ifeo"),
+        (format!("\"{}\"", paths.binary_path()), "$Debugger"),
+        (paths.install_dir.clone(), "$InstallDir"),
+        (String::from(msedge_path), "$EdgePath"),
+    ];
+    let quote = |text: &str| {
+        let mut escaped = script_escape(text);
+        for (literal, variable) in &substitutions {
+            escaped = escaped.replace(&script_escape(literal), variable);
+        }
+        format!("\"{}\"", escaped)
+    };
+
+    let mut lines = vec![
+        String::from("# Registers wedge for all users, run elevated"),
+        String::from("param("),
+        format!(
+            "    [string]$InstallDir = {},",
+            script_string(&paths.install_dir)
+        ),
+        format!("    [string]$EdgePath = {}", script_string(msedge_path)),
+        String::from(")"),
+        String::new(),
+        String::from(SCRIPT_HELPERS),
+        format!("$Ifeo = {}", script_string(&ifeo_key)),
+        format!(r#"$Debugger = "`"$InstallDir\{}`"""#, BINARY_NAME),
+        String::from(SCRIPT_IFEO_FILTER),
+    ];
+    for action in registry_actions(paths, msedge_path) {
+        match action {
+            Action::CreateKey { hive, path } | Action::CreateSharedKey { hive, path } => lines
+                .push(format!(
+                    "New-RegistryKey {}",
+                    quote(&registry_path(hive, &path))
+                )),
+            Action::SetValue {
+                hive,
+                path,
+                name,
+                value,
+            } => {
+                let (value, kind) = match value {
+                    RegValue::String(value) => (quote(&value), "String"),
+                    RegValue::Dword(value) => (value.to_string(), "DWord"),
+                    RegValue::Raw { kind: 11, bytes } if bytes.len() == 8 => {
+                        let mut qword = [0; 8];
+                        qword.copy_from_slice(&bytes);
+                        (u64::from_le_bytes(qword).to_string(), "QWord")
+                    }
+                    // PowerShell can only write the other types from typed values, so their
+                    // bytes are kept as binary
+                    RegValue::Raw { bytes, .. } => (
+                        format!(
+                            "([byte[]]({}))",
+                            bytes
+                                .iter()
+                                .map(|byte| format!("0x{:02x}", byte))
+                                .collect::<Vec<String>>()
+                                .join(",")
+                        ),
+                        "Binary",
+                    ),
+                };
+                lines.push(format!(
+                    "Set-RegistryValue {} {} {} {}",
+                    quote(&registry_path(hive, &path)),
+                    script_string(&name),
+                    value,
+                    kind
+                ));
+            }
+            _ => {}
+        }
+    }
+    lines.push(String::new());
+    lines.join("\n").replace('\n', "\r\n")
+}
+
+/// Path of a registry key as understood by PowerShell's registry provider
+fn registry_path(hive: Hive, path: &str) -> String {
+    join(&format!("Registry::{}", hive.name()), path)
+}
+
+/// Double quotes a string for PowerShell
+fn script_string(text: &str) -> String { format!("\"{}\"", script_escape(text)) }
+
+/// Escapes what PowerShell would otherwise expand inside double quotes
+fn script_escape(text: &str) -> String {
+    text.replace('`', "``")
+        .replace('"', "`\"")
+        .replace('$', "`$")
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::install::MSEDGE_PATH;
+    use std::{env, fs};
+
+    /// Compares with a file under `golden`, rewriting it instead when `WEDGE_UPDATE_GOLDEN` is set
+    pub(crate) fn check_golden(name: &str, actual: &str) {
+        let path = format!("{}/golden/{}", env!("CARGO_MANIFEST_DIR"), name);
+        if env::var_os("WEDGE_UPDATE_GOLDEN").is_some() {
+            fs::write(&path, actual).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), actual);
+    }
+
+    const EDGE: &str = r"D:\Apps\Edge\msedge.exe";
+
+    fn paths() -> InstallPaths {
+        InstallPaths {
+            install_dir: String::from(r"D:\Apps\Wedge"),
+        }
+    }
+
+    #[test]
+    fn test_reg_file() {
+        check_golden(
+            "install.reg",
+            &reg_file(&InstallPaths::default(), MSEDGE_PATH),
+        );
+        let reg = reg_file(&paths(), EDGE);
+        assert!(reg.contains(r#"@="\"D:\\Apps\\Wedge\\wedge.exe\"""#));
+        assert!(reg.contains(r#""FilterFullPath"="D:\\Apps\\Edge\\msedge.exe""#));
+        assert!(!reg.contains("Program Files"));
+    }
+
+    #[test]
+    fn test_powershell_script() {
+        check_golden(
+            "install.ps1",
+            &powershell_script(&InstallPaths::default(), MSEDGE_PATH),
+        );
+        let script = powershell_script(&paths(), EDGE);
+        assert!(script.contains(r#"[string]$InstallDir = "D:\Apps\Wedge","#));
+        assert!(script.contains(r#"[string]$EdgePath = "D:\Apps\Edge\msedge.exe""#));
+        assert_eq!(
+            powershell_script(&InstallPaths::default(), MSEDGE_PATH)
+                .lines()
+                .skip(4)
+                .collect::<Vec<_>>(),
+            script.lines().skip(4).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_script_string() {
+        assert_eq!(r#""`"a`" `$b ``c""#, script_string(r#""a" $b `c"#));
+        assert_eq!(r#""\"a\" \\b""#, reg_string(r#""a" \b"#));
+    }
+}
@@ -0,0 +1,426 @@
+#[cfg(windows)]
+use crate::win32::{shell_execute, shell_execute_with};
+use crate::{
+    cmdline::quote,
+    deflect::{Env, Link},
+    error::WedgeError,
+    launch::{launch_link, Launch},
+    registry::Registry,
+    rules::Target,
+};
+use std::path::PathBuf;
+#[cfg(windows)]
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Set in the environment of launches that may start wedge again, such as edge by its own path.
+/// A wedge started with it set leaves those launches out, so falling back can't loop.
+pub const GUARD_VAR: &str = "WEDGE_FALLBACK";
+
+/// One way of opening something
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attempt {
+    /// Launches a program directly
+    Launch(Launch),
+    /// Launches a program windows finds by name through App Paths, such as `firefox.exe`
+    LaunchByName {
+        program: &'static str,
+        parameters: String,
+    },
+    /// Opens a url with whatever windows has registered for it
+    OpenUrl(String),
+}
+
+impl Attempt {
+    /// What the attempt does, for logging
+    pub fn describe(&self) -> String {
+        match self {
+            Attempt::Launch(Launch { program, args }) => {
+                format!("Launch `{}` with {:?}", program, args)
+            }
+            Attempt::LaunchByName {
+                program,
+                parameters,
+            } => format!("Launch `{}` by name with `{}`", program, parameters),
+            Attempt::OpenUrl(url) => format!("Open `{}` with its registered handler", url),
+        }
+    }
+}
+
+/// An attempt along with whether it may start wedge again, which needs `GUARD_VAR` set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fallback {
+    pub attempt: Attempt,
+    pub reenters: bool,
+}
+
+/// Builds a list of attempts, leaving out repeats and, when `guarded`, ones that would start
+/// wedge again
+struct Chain {
+    fallbacks: Vec<Fallback>,
+    guarded: bool,
+}
+
+impl Chain {
+    fn push(&mut self, attempt: Attempt, reenters: bool) {
+        let repeated = self.fallbacks.iter().any(|f| f.attempt == attempt);
+        let allowed = !reenters || !self.guarded;
+        if allowed && !repeated {
+            self.fallbacks.push(Fallback { attempt, reenters });
+        }
+    }
+
+    /// Adds launching edge through the proxy, then by its own path
+    fn push_edge(&mut self, env: &Env, args: Vec<String>) {
+        if !env.msedge_proxy_path.eq_ignore_ascii_case(&env.msedge_path) {
+            self.push(
+                Attempt::Launch(Launch {
+                    program: env.msedge_proxy_path.clone(),
+                    args: args.clone(),
+                }),
+                false,
+            );
+        }
+        self.push(
+            Attempt::Launch(Launch {
+                program: env.msedge_path.clone(),
+                args,
+            }),
+            true,
+        );
+    }
+}
+
+/// Ways of opening a link, best first: the browser it is routed to, the default browser, the
+/// handler windows has for the url, edge through the proxy and edge by its own path. Browsers
+/// that can't be found are logged and left out.
+pub fn link_fallbacks(
+    registry: &dyn Registry,
+    env: &Env,
+    link: &Link,
+    guarded: bool,
+    log: &mut dyn FnMut(&str),
+) -> Vec<Fallback> {
+    let mut chain = Chain {
+        fallbacks: vec![],
+        guarded,
+    };
+    let mut push_link = |chain: &mut Chain, link: &Link| match launch_link(registry, env, link) {
+        // Edge by its own path means the proxy went missing, which is left for last
+        Ok(ref launch) if launch.program.eq_ignore_ascii_case(&env.msedge_path) => {}
+        Ok(launch) => chain.push(Attempt::Launch(launch), false),
+        Err(e) => {
+            log(&format!(
+                "Couldn't find a browser for `{}`: {}",
+                link.url, e
+            ));
+            // Windows may still know the browser by name
+            if let Target::Browser(browser) = link.target {
+                if let Some(program) = browser.executable_name() {
+                    chain.push(
+                        Attempt::LaunchByName {
+                            program,
+                            parameters: link
+                                .args
+                                .iter()
+                                .chain(Some(&link.url))
+                                .map(|arg| quote(arg))
+                                .collect::<Vec<_>>()
+                                .join(" "),
+                        },
+                        false,
+                    );
+                }
+            }
+        }
+    };
+
+    push_link(&mut chain, link);
+    if link.target != Target::DefaultBrowser {
+        // Args are meant for the browser the link was routed to
+        push_link(&mut chain, &Link {
+            target: Target::DefaultBrowser,
+            args: vec![],
+            ..link.clone()
+        });
+    }
+    // Windows may hand the url to edge, which starts wedge again
+    chain.push(Attempt::OpenUrl(link.url.clone()), true);
+    chain.push_edge(env, vec![
+        String::from("--single-argument"),
+        link.url.clone(),
+    ]);
+    chain.fallbacks
+}
+
+/// Ways of passing a launch through to edge: as decided, then edge by its own path
+pub fn pass_through_fallbacks(
+    env: &Env,
+    program: &str,
+    args: &[String],
+    guarded: bool,
+) -> Vec<Fallback> {
+    let mut chain = Chain {
+        fallbacks: vec![],
+        guarded,
+    };
+    chain.push(
+        Attempt::Launch(Launch {
+            program: String::from(program),
+            args: args.to_vec(),
+        }),
+        program.eq_ignore_ascii_case(&env.msedge_path),
+    );
+    chain.push_edge(env, args.to_vec());
+    chain.fallbacks
+}
+
+/// Tries each fallback in turn until one works, logging every failure. Returns the last error
+/// when none work, or `NoFallbacks` when there were none.
+pub fn try_in_order<F>(
+    fallbacks: &[Fallback],
+    mut open: F,
+    log: &mut dyn FnMut(&str),
+) -> Result<(), WedgeError>
+where
+    F: FnMut(&Fallback) -> Result<(), WedgeError>,
+{
+    let mut result = Err(WedgeError::NoFallbacks);
+    for fallback in fallbacks {
+        result = open(fallback);
+        match &result {
+            Ok(()) => break,
+            Err(e) => log(&format!("Failed: {} ({})", fallback.attempt.describe(), e)),
+        }
+    }
+    result
+}
+
+/// Makes an attempt on this machine, setting `GUARD_VAR` for the programs it starts when it may
+/// start wedge again
+#[cfg(windows)]
+pub fn open(fallback: &Fallback) -> Result<(), WedgeError> {
+    let guard = |command: &mut Command| {
+        if fallback.reenters {
+            command.env(GUARD_VAR, "1");
+        }
+    };
+    match &fallback.attempt {
+        Attempt::Launch(Launch { program, args }) => {
+            let mut command = Command::new(program);
+            guard(command.args(args));
+            command
+                .spawn()
+                .map(|_| ())
+                .map_err(WedgeError::filesystem(program))
+        }
+        Attempt::LaunchByName {
+            program,
+            parameters,
+        } => shell_execute_with(program, parameters),
+        // ShellExecute can only pass along our own environment, so it holds `GUARD_VAR` just
+        // for the call and is then put back the way it was
+        Attempt::OpenUrl(url) if fallback.reenters => {
+            let previous = std::env::var_os(GUARD_VAR);
+            std::env::set_var(GUARD_VAR, "1");
+            let result = shell_execute(url);
+            match previous {
+                Some(previous) => std::env::set_var(GUARD_VAR, previous),
+                None => std::env::remove_var(GUARD_VAR),
+            }
+            result
+        }
+        Attempt::OpenUrl(url) => shell_execute(url),
+    }
+}
+
+/// Where wedge logs what went wrong, since it has no window to show it in
+pub fn log_path() -> PathBuf { std::env::temp_dir().join("wedge.log") }
+
+/// Appends a line to the log
+#[cfg(windows)]
+pub fn append_log(line: &str) {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    if let Ok(mut file) = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path())
+    {
+        let _ = writeln!(file, "[{}] {}", seconds, line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        browser::Browser,
+        install::{MSEDGE_PATH, MSEDGE_PROXY_PATH},
+        registry::{Hive, Key, MemoryRegistry},
+    };
+    use std::io::{Error, ErrorKind};
+
+    const URL: &str = "https://example.com/";
+
+    fn launch(program: &str) -> Attempt {
+        Attempt::Launch(Launch {
+            program: String::from(program),
+            args: vec![String::from("--single-argument"), String::from(URL)],
+        })
+    }
+
+    fn attempts(fallbacks: &[Fallback]) -> Vec<(Attempt, bool)> {
+        fallbacks
+            .iter()
+            .map(|f| (f.attempt.clone(), f.reenters))
+            .collect()
+    }
+
+    #[test]
+    fn test_link_fallbacks() {
+        let registry = MemoryRegistry::new();
+        let env = Env::default();
+        let mut log = vec![];
+        let link = Link {
+            target: Target::Browser(Browser::Firefox),
+            args: vec![String::from("-private-window")],
+            ..Link::new(URL)
+        };
+
+        // Nothing is registered, so windows is left to find firefox and the url's handler
+        let fallbacks = link_fallbacks(&registry, &env, &link, false, &mut |line| {
+            log.push(String::from(line))
+        });
+        assert_eq!(
+            vec![
+                (
+                    Attempt::LaunchByName {
+                        program: "firefox.exe",
+                        parameters: format!("-private-window {}", URL),
+                    },
+                    false
+                ),
+                (Attempt::OpenUrl(String::from(URL)), true),
+                (launch(MSEDGE_PROXY_PATH), false),
+                (launch(MSEDGE_PATH), true),
+            ],
+            attempts(&fallbacks)
+        );
+        assert_eq!(2, log.len());
+
+        // A wedge started by a fallback doesn't try what started it
+        let guarded = link_fallbacks(&registry, &env, &link, true, &mut |_| {});
+        assert_eq!(
+            vec![
+                (
+                    Attempt::LaunchByName {
+                        program: "firefox.exe",
+                        parameters: format!("-private-window {}", URL),
+                    },
+                    false
+                ),
+                (launch(MSEDGE_PROXY_PATH), false),
+            ],
+            attempts(&guarded)
+        );
+
+        // The default browser comes before windows' handler, and edge isn't tried twice
+        Key::predef(&registry, Hive::CurrentUser)
+            .create_subkey(
+                r"Software\Microsoft\Windows\Shell\Associations\UrlAssociations\http\UserChoice",
+            )
+            .unwrap()
+            .set_value("Progid", "MSEdgeHTM")
+            .unwrap();
+        Key::predef(&registry, Hive::ClassesRoot)
+            .create_subkey(r"MSEdgeHTM\shell\open\command")
+            .unwrap()
+            .set_value("", format!("\"{}\" --single-argument %1", MSEDGE_PATH))
+            .unwrap();
+        let fallbacks = link_fallbacks(&registry, &env, &Link::new(URL), false, &mut |_| {});
+        assert_eq!(
+            vec![
+                (launch(MSEDGE_PROXY_PATH), false),
+                (Attempt::OpenUrl(String::from(URL)), true),
+                (launch(MSEDGE_PATH), true),
+            ],
+            attempts(&fallbacks)
+        );
+    }
+
+    #[test]
+    fn test_pass_through_fallbacks() {
+        let env = Env::default();
+        let args = vec![String::from("--no-startup-window")];
+        let edge = |program: &str| {
+            Attempt::Launch(Launch {
+                program: String::from(program),
+                args: args.clone(),
+            })
+        };
+        assert_eq!(
+            vec![(edge(MSEDGE_PROXY_PATH), false), (edge(MSEDGE_PATH), true)],
+            attempts(&pass_through_fallbacks(
+                &env,
+                MSEDGE_PROXY_PATH,
+                &args,
+                false
+            ))
+        );
+
+        // Without a proxy edge is launched by its own path, unless that is what started wedge
+        let env = Env {
+            msedge_proxy_path: String::from(MSEDGE_PATH),
+            ..Env::default()
+        };
+        assert_eq!(
+            vec![(edge(MSEDGE_PATH), true)],
+            attempts(&pass_through_fallbacks(&env, MSEDGE_PATH, &args, false))
+        );
+        assert!(pass_through_fallbacks(&env, MSEDGE_PATH, &args, true).is_empty());
+    }
+
+    #[test]
+    fn test_try_in_order() {
+        let fallbacks = pass_through_fallbacks(&Env::default(), MSEDGE_PROXY_PATH, &[], false);
+        let mut log = vec![];
+        let mut tried = vec![];
+        let result = try_in_order(
+            &fallbacks,
+            |fallback| {
+                tried.push(fallback.clone());
+                if fallback.reenters {
+                    Ok(())
+                } else {
+                    Err(WedgeError::filesystem(MSEDGE_PROXY_PATH)(Error::new(
+                        ErrorKind::NotFound,
+                        "The system cannot find the file specified.",
+                    )))
+                }
+            },
+            &mut |line| log.push(String::from(line)),
+        );
+        assert!(result.is_ok());
+        assert_eq!(fallbacks, tried);
+        assert_eq!(
+            vec![format!(
+                "Failed: Launch `{}` with [] (File `{}`: The system cannot find the \
+                 file specified.)",
+                MSEDGE_PROXY_PATH, MSEDGE_PROXY_PATH
+            )],
+            log
+        );
+
+        match try_in_order(&[], |_| Ok(()), &mut |_| {}) {
+            Err(WedgeError::NoFallbacks) => {}
+            result => panic!("Expected no fallbacks, got {:?}", result),
+        }
+    }
+}
@@ -1,17 +1,28 @@
+#[cfg(windows)]
 use super::{win32::*, *};
-use crate::browser::*;
-use std::{
-    fs::{create_dir_all, remove_dir_all, remove_file, File},
-    io::{Error, ErrorKind, Write},
-    path::Path,
-    ptr::null_mut,
+#[cfg(windows)]
+use crate::{
+    browser::get_default_browser,
+    edge::{discover, known_folders},
+    error::WedgeError,
+    plan::Runner,
+    registry::SystemRegistry,
+    system::{System, WindowsSystem},
 };
+use crate::{
+    browser::Browser,
+    edge::EdgeInstall,
+    plan::{Action, Manifest, Undo},
+    registry::{Hive, Key, RegValue, Registry},
+};
+#[cfg(windows)]
+use std::{fs::remove_file, io::ErrorKind, path::Path, ptr::null_mut};
+#[cfg(windows)]
 use winapi::um::{
     libloaderapi::{FindResourceW, GetModuleHandleW, LoadResource, LockResource, SizeofResource},
     winnt::LPWSTR,
     winuser::RT_RCDATA,
 };
-use winreg::{enums::*, RegKey};
 
 // General install settings
 pub const INSTALL_DIR: &str = r"C:\Program Files (x86)\Wedge";
@@ -20,217 +31,1029 @@ pub const APP_DESC: &str =
     "Open web links normally forced to open in Microsoft Edge in your default web browser.";
 pub const APP_NAME: &str = "Wedge";
 pub const STEP_INTERVAL: u32 = 100;
-pub const STEP_COUNT: isize = 6;
 pub const MSEDGE_PATH: &str = r"C:\Program Files (x86)\Microsoft\Edge\Application\msedge.exe";
 pub const MSEDGE_PROXY_PATH: &str =
     r"C:\Program Files (x86)\Microsoft\Edge\Application\msedge-wedge-proxy.exe";
 
+// Registry keys under HKLM
+pub const APP_PATHS_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\App Paths";
+pub const UNINSTALL_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Uninstall";
+pub const IFEO_KEY: &str =
+    r"SOFTWARE\Microsoft\Windows NT\CurrentVersion\Image File Execution Options\msedge.exe";
+
 // Resource names
 pub const BINARY_NAME: &str = "wedge.exe";
 pub const UNINSTALLER_NAME: &str = "wedge uninstaller.exe";
 pub const LICENSE_NAME: &str = "LICENSE";
 
+// Record of what install changed, written to the install location
+pub const MANIFEST_NAME: &str = "install manifest.json";
+
 // Resource Ids
+#[cfg(windows)]
 const BINARY_RESOURCE: LPWSTR = MAKEINTRESOURCE!(301);
+#[cfg(windows)]
 const UNINSTALLER_RESOURCE: LPWSTR = MAKEINTRESOURCE!(302);
+#[cfg(windows)]
 const LICENSE_RESOURCE: LPWSTR = MAKEINTRESOURCE!(303);
 
-// Install size is computed during runtime
-static mut INSTALL_SIZE: u32 = 0;
-
-/// Install Wedge step by step
-pub fn install(step: usize) -> Result<String, Error> {
-    let install_path = Path::new(&INSTALL_DIR);
-    let install_path_string = format!("\"{}\"", install_path.to_str().unwrap());
-    let binary_path = install_path.join(&BINARY_NAME);
-    let uninstaller_path = install_path.join(&UNINSTALLER_NAME);
-    let binary_path_string = format!("\"{}\"", binary_path.to_str().unwrap());
-    let uninstaller_path_string = format!("\"{}\"", uninstaller_path.to_str().unwrap());
-
-    // Run different steps
-    Ok(match step {
-        // Unpack resources into installation folder
-        1 => unsafe {
-            let mut ret = vec![format!(
-                "Created install location `{}`",
-                install_path.display()
-            )];
-
-            // Create install directory
-            create_dir_all(&install_path)?;
-
-            // Module instance
-            let hinstance = GetModuleHandleW(null_mut());
-
-            // Reset install size
-            INSTALL_SIZE = 0;
-
-            // Unpack files and place in install directory iteratively
-            for (name, id) in &[
-                (BINARY_NAME, BINARY_RESOURCE),
-                (UNINSTALLER_NAME, UNINSTALLER_RESOURCE),
-                (LICENSE_NAME, LICENSE_RESOURCE),
-            ] {
-                // Unpack embedded binary resource
-                // Code derived from https://blog.kowalczyk.info/article/zy/embedding-binary-resources-on-windows.html
-                let res = FindResourceW(hinstance, *id, RT_RCDATA);
-                let res_handle = LoadResource(hinstance, res);
-                if res_handle.is_null() {
-                    return Err(Error::new(ErrorKind::Other, "Failed unpacking resource"));
-                }
-                let res_data = LockResource(res_handle) as *const u8;
-                let res_size = SizeofResource(hinstance, res) as usize;
-                let input_as_u32 = std::slice::from_raw_parts(res_data, res_size).to_vec();
-
-                // Write to install location
-                let path = install_path.join(name);
-                let mut file = File::create(&path)?;
-                file.write_all(&input_as_u32)?;
-
-                // Add unpacked file size to total install size
-                INSTALL_SIZE += res_size as u32;
-
-                // Notify successful extraction of file
-                ret.push(format!("Unpacked `{}`", path.to_str().unwrap()));
-            }
-            ret.join("\n")
-        },
-        // Registry keys
-        // https://docs.microsoft.com/en-us/windows/win32/shell/app-registration
-        2 => {
-            // Install for all users
-            let software = RegKey::predef(HKEY_LOCAL_MACHINE).open_subkey("Software")?;
-
-            // Register app path as per https://docs.microsoft.com/en-us/windows/win32/shell/app-registration
-            let (app_path, _) = software.create_subkey(
-                Path::new(r"Microsoft\Windows\CurrentVersion\App Paths").join(&BINARY_NAME),
-            )?;
-            app_path.set_value("", &binary_path_string)?;
-            app_path.set_value("Path", &install_path_string)?;
-
-            // Create AppId
-            let (class, _) = software.create_subkey(Path::new("Classes").join(&APP_ID))?;
-            class.set_value("", &"URL: Microsoft Edge Protocol Deflector")?;
-            class.set_value("URL Protocol", &"")?;
-            let (default_icon, _) = class.create_subkey("DefaultIcon")?;
-            default_icon.set_value("", &binary_path_string)?;
-            let (command, _) = class.create_subkey(r"shell\open\command")?;
-            command.set_value("", &format!("{} \"%1\"", &binary_path_string))?;
-
-            // Registering AppId
-            let registered_applications =
-                software.open_subkey_with_flags(r"RegisteredApplications", KEY_ALL_ACCESS)?;
-            registered_applications.set_value(
-                APP_ID,
-                &Path::new(r"Software\Clients")
-                    .join(&APP_ID)
-                    .join("Capabilities")
-                    .to_str()
-                    .unwrap(),
-            )?;
-
-            // Register Uninstaller
-            let (uninstall, _) = software.create_subkey(
-                Path::new(r"Microsoft\Windows\CurrentVersion\Uninstall").join(APP_ID),
-            )?;
-            uninstall.set_value("DisplayIcon", &binary_path_string)?;
-            uninstall.set_value("DisplayName", &APP_NAME)?;
-            uninstall.set_value("DisplayVersion", &"0.1.0")?;
-            uninstall.set_value("EstimatedSize", &(unsafe { INSTALL_SIZE } / 1024u32))?;
-            uninstall.set_value("InstallLocation", &install_path_string)?;
-            uninstall.set_value("NoModify", &1u32)?;
-            uninstall.set_value("NoRepair", &1u32)?;
-            uninstall.set_value("Publisher", &env!("CARGO_PKG_AUTHORS"))?;
-            uninstall.set_value("UninstallString", &uninstaller_path_string)?;
-            String::from("Registered application")
+/// Url opened once installed to let the redirector extensions know wedge is installed
+pub const NOTIFY_URL: &str =
+    "microsoft-edge:https://www.bing.com/#notify-redirect-extension-successful-wedge-install";
+pub const SUCCESS_MESSAGE: &str = "All steps completed successfully! You may now close this \
+                                   installer.\nWedge can be easily uninstalled in windows Apps \
+                                   & Features";
+
+/// Who wedge is installed for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Every user, deflecting edge itself through IFEO. Needs elevation.
+    Machine,
+    /// Only the current user, deflecting just the "microsoft-edge:" protocol
+    User,
+}
+
+impl Scope {
+    /// Hive the app and uninstaller are registered under
+    pub fn hive(self) -> Hive {
+        match self {
+            Scope::Machine => Hive::LocalMachine,
+            Scope::User => Hive::CurrentUser,
         }
-        // Create start menu link
-        3 => {
-            create_link(
-                &get_global_start_menu_location()?
-                    .join(&format!("{}.lnk", APP_NAME))
-                    .to_str()
-                    .unwrap(),
-                &binary_path_string,
-                "Wedge - The simple Open-Source Edge Deflector",
-            )?;
-            String::from("Created start menu shortcut")
+    }
+}
+
+/// Where wedge is installed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstallPaths {
+    pub install_dir: String,
+}
+
+impl Default for InstallPaths {
+    fn default() -> InstallPaths {
+        InstallPaths {
+            install_dir: String::from(INSTALL_DIR),
         }
-        // Register IFEO
-        4 => {
-            let (ifeo, _) = RegKey::predef(HKEY_LOCAL_MACHINE).create_subkey(r"SOFTWARE\Microsoft\Windows NT\CurrentVersion\Image File Execution Options\msedge.exe")?;
-            ifeo.set_value("UseFilter", &1u32)?;
+    }
+}
 
-            let (filter, _) = ifeo.create_subkey(r"0")?;
-            filter.set_value("Debugger", &binary_path_string)?;
-            filter.set_value("FilterFullPath", &MSEDGE_PATH)?;
+impl InstallPaths {
+    /// Default paths for a scope. Per-user installs go in the local appdata folder.
+    #[cfg(windows)]
+    pub fn for_scope(scope: Scope) -> Result<InstallPaths, WedgeError> {
+        Ok(match scope {
+            Scope::Machine => InstallPaths::default(),
+            Scope::User => InstallPaths {
+                install_dir: get_local_install_location()?
+                    .join(APP_NAME)
+                    .to_string_lossy()
+                    .into_owned(),
+            },
+        })
+    }
 
-            // Create a symlink in the install directory that links to the edge executable
-            // Critically, this allows executing msedge.exe by an alternate path that is ignored by our IFEO filter
-            create_symlink(MSEDGE_PROXY_PATH, MSEDGE_PATH)?;
+    pub fn binary_path(&self) -> String { format!(r"{}\{}", self.install_dir, BINARY_NAME) }
 
-            String::from("Registered IFEO")
+    pub fn manifest_path(&self) -> String { format!(r"{}\{}", self.install_dir, MANIFEST_NAME) }
+
+    /// Quoted install location, binary path and uninstaller path as stored in the registry
+    fn quoted(&self) -> (String, String, String) {
+        (
+            format!("\"{}\"", self.install_dir),
+            format!("\"{}\"", self.binary_path()),
+            format!("\"{}\\{}\"", self.install_dir, UNINSTALLER_NAME),
+        )
+    }
+}
+
+/// What the install plan depends on, gathered from the machine and installer beforehand
+pub struct InstallContext {
+    pub scope: Scope,
+    pub paths: InstallPaths,
+    /// Files to unpack into the install location, by name
+    pub files: Vec<(&'static str, Vec<u8>)>,
+    /// Folder the start menu shortcut is created in
+    pub start_menu_dir: String,
+    pub default_browser: Browser,
+    /// Edge installs to deflect with the IFEO filter subkey each uses, from `ifeo_filters`
+    pub edges: Vec<(EdgeInstall, u32)>,
+}
+
+/// Every change installing wedge makes, in order
+pub fn install_plan(context: &InstallContext) -> Vec<Action> {
+    let paths = &context.paths;
+    let (_, binary_path_string, _) = paths.quoted();
+
+    // Unpack resources into installation folder
+    let mut actions = vec![Action::CreateDir {
+        path: paths.install_dir.clone(),
+    }];
+    for (name, contents) in &context.files {
+        actions.push(Action::WriteFile {
+            path: format!(r"{}\{}", paths.install_dir, name),
+            contents: contents.clone(),
+        });
+    }
+
+    // Registry keys
+    let install_size = context.files.iter().map(|(_, c)| c.len() as u32).sum();
+    actions.extend(application_actions(
+        context.scope,
+        paths,
+        Some(install_size),
+    ));
+
+    // Create start menu link
+    actions.push(Action::CreateShortcut {
+        path: format!(r"{}\{}.lnk", context.start_menu_dir, APP_NAME),
+        target: binary_path_string,
+        description: String::from("Wedge - The simple Open-Source Edge Deflector"),
+    });
+
+    // Deflecting edge itself needs HKLM, so per-user installs only handle the protocol
+    if context.scope == Scope::Machine {
+        // Create a symlink that links to each edge executable
+        // Critically, this allows executing msedge.exe by an alternate path that is ignored by our IFEO filter
+        for (edge, _) in &context.edges {
+            actions.push(Action::CreateSymlink {
+                path: edge.proxy_path(),
+                target: edge.path.clone(),
+            });
         }
-        // Open link to confirm success
-        5 => {
-            // Notify redirector extension wedge was successfully installed
-            // Don't open in ie, edge, or unknown browser
-            let default_browser = get_default_browser().unwrap_or(Browser::Unknown);
-            if ![Browser::Edge, Browser::InternetExplorer, Browser::Unknown]
-                .contains(&default_browser)
-            {
-                shell_execute("microsoft-edge:https://www.bing.com/#notify-redirect-extension-successful-wedge-install");
-            }
 
-            String::from(
-                "All steps completed successfully! You may now close this installer.\nWedge can \
-                 be easily uninstalled in windows Apps & Features",
-            )
+        // Register IFEO
+        actions.extend(ifeo_actions(paths, &context.edges));
+    }
+
+    // Notify redirector extension wedge was successfully installed
+    // Don't open in ie, edge, or unknown browser
+    if ![Browser::Edge, Browser::InternetExplorer, Browser::Unknown]
+        .contains(&context.default_browser)
+    {
+        actions.push(Action::OpenUrl {
+            url: String::from(NOTIFY_URL),
+        });
+    }
+
+    actions
+}
+
+/// Gathers the files embedded in the installer and the machine's start menu and default browser
+#[cfg(windows)]
+pub fn load_install_context(scope: Scope) -> Result<InstallContext, WedgeError> {
+    let mut files = vec![];
+    unsafe {
+        // Module instance
+        let hinstance = GetModuleHandleW(null_mut());
+
+        for (name, id) in &[
+            (BINARY_NAME, BINARY_RESOURCE),
+            (UNINSTALLER_NAME, UNINSTALLER_RESOURCE),
+            (LICENSE_NAME, LICENSE_RESOURCE),
+        ] {
+            // Unpack embedded binary resource
+            // Code derived from https://blog.kowalczyk.info/article/zy/embedding-binary-resources-on-windows.html
+            let res = FindResourceW(hinstance, *id, RT_RCDATA);
+            let res_handle = LoadResource(hinstance, res);
+            if res_handle.is_null() {
+                return Err(WedgeError::Resource { name: *name });
+            }
+            let res_data = LockResource(res_handle) as *const u8;
+            let res_size = SizeofResource(hinstance, res) as usize;
+            files.push((
+                *name,
+                std::slice::from_raw_parts(res_data, res_size).to_vec(),
+            ));
         }
-        _ => String::new(),
+    }
+
+    gather_context(scope, files)
+}
+
+/// Gathers the machine's edge installs, start menu and default browser for installing `files`
+#[cfg(windows)]
+pub fn gather_context(
+    scope: Scope,
+    files: Vec<(&'static str, Vec<u8>)>,
+) -> Result<InstallContext, WedgeError> {
+    let paths = InstallPaths::for_scope(scope)?;
+    Ok(InstallContext {
+        scope,
+        edges: ifeo_filters(
+            &SystemRegistry,
+            &paths,
+            discover(&WindowsSystem, &known_folders()),
+        ),
+        paths,
+        files,
+        start_menu_dir: start_menu_location(scope)?.to_string_lossy().into_owned(),
+        default_browser: get_default_browser(&SystemRegistry).unwrap_or(Browser::Unknown),
     })
 }
 
-/// Uninstall Wedge
-pub fn uninstall() -> Result<(), Error> {
-    // Unregister IFEO
-    RegKey::predef(HKEY_LOCAL_MACHINE).delete_subkey_all(
-        r"SOFTWARE\Microsoft\Windows NT\CurrentVersion\Image File Execution Options\msedge.exe",
-    )?;
+/// Runner that installs wedge on this machine
+#[cfg(windows)]
+pub fn install_runner(scope: Scope) -> Result<Runner<'static>, WedgeError> {
+    let context = load_install_context(scope)?;
+
+    // Upgrading an install from before manifests existed, which were always for every user
+    let previous =
+        if scope == Scope::Machine && WindowsSystem.path_exists(&context.paths.install_dir) {
+            legacy_manifest(&context.start_menu_dir)
+        } else {
+            Manifest::default()
+        };
+
+    Ok(Runner::new(&WindowsSystem, install_plan(&context))
+        .with_manifest(&context.paths.manifest_path(), previous))
+}
 
-    let install_path = Path::new(&INSTALL_DIR);
+/// Folder the start menu shortcut goes in
+#[cfg(windows)]
+pub fn start_menu_location(scope: Scope) -> Result<std::path::PathBuf, WedgeError> {
+    match scope {
+        Scope::Machine => get_global_start_menu_location(),
+        Scope::User => get_user_start_menu_location(),
+    }
+}
 
-    // Try to delete install location. This will only succede when the original
-    // uninstaller stops running. panics after 100 failures.
+fn create_key(hive: Hive, path: &str) -> Action {
+    Action::CreateKey {
+        hive,
+        path: String::from(path),
+    }
+}
+
+fn set_value<V: Into<RegValue>>(hive: Hive, path: &str, name: &str, value: V) -> Action {
+    Action::SetValue {
+        hive,
+        path: String::from(path),
+        name: String::from(name),
+        value: value.into(),
+    }
+}
+
+/// Registers the app, its url protocol and uninstaller for the scope. The uninstaller's size is
+/// left out when the size of the installed files isn't known.
+///
+/// https://docs.microsoft.com/en-us/windows/win32/shell/app-registration
+pub fn application_actions(
+    scope: Scope,
+    paths: &InstallPaths,
+    install_size: Option<u32>,
+) -> Vec<Action> {
+    let (install_path_string, binary_path_string, mut uninstaller_path_string) = paths.quoted();
+    // Apps & Features "Modify" repairs the install
+    let mut modify_path_string = format!("{} repair", binary_path_string);
+    if scope == Scope::User {
+        // Tells the uninstaller and repair which install to work on
+        uninstaller_path_string.push_str(" /user");
+        modify_path_string.push_str(" --user");
+    }
+    let hive = scope.hive();
+    let app_path = format!(r"{}\{}", APP_PATHS_KEY, BINARY_NAME);
+    let class = format!(r"Software\Classes\{}", APP_ID);
+    let default_icon = format!(r"{}\DefaultIcon", class);
+    let command = format!(r"{}\shell\open\command", class);
+    let capabilities = format!(r"Software\Clients\{}\Capabilities", APP_ID);
+    let url_associations = format!(r"{}\URLAssociations", capabilities);
+    let uninstall = format!(r"{}\{}", UNINSTALL_KEY, APP_ID);
+    let registered_applications = r"Software\RegisteredApplications";
+
+    let mut actions = vec![
+        // Register app path as per https://docs.microsoft.com/en-us/windows/win32/shell/app-registration
+        create_key(hive, &app_path),
+        set_value(hive, &app_path, "", &binary_path_string),
+        set_value(hive, &app_path, "Path", &install_path_string),
+        // Create AppId
+        create_key(hive, &class),
+        set_value(hive, &class, "", "URL: Microsoft Edge Protocol Deflector"),
+        set_value(hive, &class, "URL Protocol", ""),
+        create_key(hive, &default_icon),
+        set_value(hive, &default_icon, "", &binary_path_string),
+        create_key(hive, &command),
+        set_value(
+            hive,
+            &command,
+            "",
+            format!("{} \"%1\"", &binary_path_string),
+        ),
+        // Registering AppId along with the capabilities it points to
+        create_key(hive, &capabilities),
+        set_value(hive, &capabilities, "ApplicationName", APP_NAME),
+        set_value(hive, &capabilities, "ApplicationDescription", APP_DESC),
+        create_key(hive, &url_associations),
+        set_value(hive, &url_associations, "microsoft-edge", APP_ID),
+        create_key(hive, registered_applications),
+        set_value(hive, registered_applications, APP_ID, &capabilities),
+        // Register Uninstaller
+        create_key(hive, &uninstall),
+        set_value(hive, &uninstall, "DisplayIcon", &binary_path_string),
+        set_value(hive, &uninstall, "DisplayName", APP_NAME),
+        set_value(hive, &uninstall, "DisplayVersion", "0.1.0"),
+    ];
+    if let Some(install_size) = install_size {
+        actions.push(set_value(
+            hive,
+            &uninstall,
+            "EstimatedSize",
+            install_size / 1024u32,
+        ));
+    }
+    actions.extend(vec![
+        set_value(hive, &uninstall, "InstallLocation", &install_path_string),
+        set_value(hive, &uninstall, "ModifyPath", &modify_path_string),
+        set_value(hive, &uninstall, "Publisher", env!("CARGO_PKG_AUTHORS")),
+        set_value(
+            hive,
+            &uninstall,
+            "UninstallString",
+            &uninstaller_path_string,
+        ),
+    ]);
+    actions
+}
+
+/// Picks the IFEO filter subkey for each edge install. Filters other software or policy set up
+/// for edge are left alone, so each install gets the filter already pointing it at wedge or the
+/// lowest free index.
+pub fn ifeo_filters(
+    registry: &dyn Registry,
+    paths: &InstallPaths,
+    edges: Vec<EdgeInstall>,
+) -> Vec<(EdgeInstall, u32)> {
+    let (_, binary_path_string, _) = paths.quoted();
+    let ifeo = Key::predef(registry, Hive::LocalMachine)
+        .open_subkey(IFEO_KEY)
+        .ok();
+    let mut taken = ifeo
+        .as_ref()
+        .and_then(|ifeo| ifeo.enum_keys().ok())
+        .unwrap_or_default();
+
+    let wedge_filter = |edge: &EdgeInstall| {
+        taken
+            .iter()
+            .find(|name| {
+                let filter = ifeo.as_ref().and_then(|ifeo| ifeo.open_subkey(name).ok());
+                let get = |value| filter.as_ref()?.get_string(value).ok();
+                get("Debugger").map_or(false, |debugger| {
+                    debugger.eq_ignore_ascii_case(&binary_path_string)
+                }) && get("FilterFullPath")
+                    .map_or(false, |path| path.eq_ignore_ascii_case(&edge.path))
+            })?
+            .parse()
+            .ok()
+    };
+    let reused: Vec<Option<u32>> = edges.iter().map(wedge_filter).collect();
+    edges
+        .into_iter()
+        .zip(reused)
+        .map(|(edge, index)| {
+            let index = index.unwrap_or_else(|| {
+                let index = (0..)
+                    .find(|index: &u32| !taken.iter().any(|name| *name == index.to_string()))
+                    .unwrap();
+                taken.push(index.to_string());
+                index
+            });
+            (edge, index)
+        })
+        .collect()
+}
+
+/// Registers wedge as the debugger of each edge install using IFEO filters. Values overwritten
+/// are kept in the manifest, so uninstalling puts back what was there.
+pub fn ifeo_actions(paths: &InstallPaths, edges: &[(EdgeInstall, u32)]) -> Vec<Action> {
+    let (_, binary_path_string, _) = paths.quoted();
+    let hive = Hive::LocalMachine;
+
+    // Security tools and IT keep their own settings in edge's IFEO key, so it is only deleted
+    // once wedge's filters are gone and nothing else was added
+    let mut actions = vec![
+        Action::CreateSharedKey {
+            hive,
+            path: String::from(IFEO_KEY),
+        },
+        set_value(hive, IFEO_KEY, "UseFilter", 1u32),
+    ];
+    for (edge, filter) in edges {
+        let filter = format!(r"{}\{}", IFEO_KEY, filter);
+        actions.extend(vec![
+            create_key(hive, &filter),
+            set_value(hive, &filter, "Debugger", &binary_path_string),
+            set_value(hive, &filter, "FilterFullPath", &edge.path),
+        ]);
+    }
+    actions
+}
+
+/// Uninstall Wedge by undoing what its manifest recorded
+#[cfg(windows)]
+pub fn uninstall(scope: Scope) -> Result<(), WedgeError> {
+    // Wait for the original uninstaller to stop running so the install location can be
+    // deleted. Gives up after 100 failures.
+    let uninstaller =
+        Path::new(&InstallPaths::for_scope(scope)?.install_dir).join(UNINSTALLER_NAME);
     let mut try_count = 0;
-    while remove_dir_all(&install_path).is_err() {
+    while let Err(e) = remove_file(&uninstaller) {
+        if e.kind() == ErrorKind::NotFound {
+            break;
+        }
         std::thread::sleep(std::time::Duration::from_millis(50));
         try_count = try_count + 1;
         if try_count > 100 {
-            panic!("");
+            return Err(WedgeError::filesystem(uninstaller)(e));
         }
     }
 
-    // Delete symlink
-    let _ = remove_file(Path::new(&MSEDGE_PROXY_PATH));
+    installed_manifest(scope)?.undo(&WindowsSystem, |_| {})
+}
 
-    // Delete shortcut if it's still there
-    let _ = remove_file(get_global_start_menu_location()?.join(&format!("{}.lnk", APP_NAME)));
+/// What uninstalling would change, without changing anything
+#[cfg(windows)]
+pub fn uninstall_preview(scope: Scope) -> Result<Vec<String>, WedgeError> {
+    Ok(installed_manifest(scope)?.preview(&WindowsSystem))
+}
 
-    // Remove local install registry entries
-    let software = RegKey::predef(HKEY_LOCAL_MACHINE).open_subkey("Software")?;
+/// Manifest of the current install. Installs for every user from before manifests existed get
+/// the changes they are known to make.
+#[cfg(windows)]
+fn installed_manifest(scope: Scope) -> Result<Manifest, WedgeError> {
+    match Manifest::load(
+        &WindowsSystem,
+        &InstallPaths::for_scope(scope)?.manifest_path(),
+    ) {
+        Ok(manifest) => Ok(manifest),
+        Err(ref e) if e.is_not_found() && scope == Scope::Machine => Ok(legacy_manifest(
+            &get_global_start_menu_location()?.to_string_lossy(),
+        )),
+        Err(e) => Err(e),
+    }
+}
 
-    // Remove registering of binary
-    software.delete_subkey_all(
-        Path::new(r"Microsoft\Windows\CurrentVersion\App Paths").join(&BINARY_NAME),
-    )?;
+/// Manifest of what installs made before manifests were recorded
+pub fn legacy_manifest(start_menu_dir: &str) -> Manifest {
+    let remove_file = |path: String| Undo::RemoveFile { path };
+    let delete_key = |path: String| Undo::DeleteKey {
+        hive: Hive::LocalMachine,
+        path,
+    };
+    Manifest {
+        undos: vec![
+            Undo::RemoveDir {
+                path: String::from(INSTALL_DIR),
+            },
+            delete_key(format!(r"{}\{}", APP_PATHS_KEY, BINARY_NAME)),
+            delete_key(format!(r"Software\Classes\{}", APP_ID)),
+            delete_key(format!(r"Software\Clients\{}", APP_ID)),
+            Undo::RestoreValue {
+                hive: Hive::LocalMachine,
+                path: String::from(r"Software\RegisteredApplications"),
+                name: String::from(APP_ID),
+                previous: None,
+            },
+            delete_key(format!(r"{}\{}", UNINSTALL_KEY, APP_ID)),
+            remove_file(format!(r"{}\{}.lnk", start_menu_dir, APP_NAME)),
+            remove_file(String::from(MSEDGE_PROXY_PATH)),
+            // Those installs always used the first filter
+            delete_key(format!(r"{}\0", IFEO_KEY)),
+        ],
+        ..Manifest::default()
+    }
+}
 
-    // Unregister AppId and "microsoft-edge:" url association
-    software.delete_subkey_all(Path::new("Classes").join(&APP_ID))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        plan::Runner,
+        registry::{Key, MemoryRegistry, Registry, REG_BINARY},
+        system::{MemoryFile, MemorySystem, System},
+    };
 
-    // Unregister uninstaller
-    software
-        .delete_subkey_all(Path::new(r"Microsoft\Windows\CurrentVersion\Uninstall").join(APP_ID))?;
+    /// Registry of a fresh windows install
+    fn fresh_registry() -> MemoryRegistry {
+        let registry = MemoryRegistry::new();
+        let hklm = Key::predef(&registry, Hive::LocalMachine);
+        for path in &[
+            r"Software\Classes",
+            r"Software\Clients",
+            r"Software\RegisteredApplications",
+            APP_PATHS_KEY,
+            UNINSTALL_KEY,
+            r"SOFTWARE\Microsoft\Windows NT\CurrentVersion\Image File Execution Options",
+        ] {
+            hklm.create_subkey(path).unwrap();
+        }
+        registry
+    }
+
+    /// Machine of a fresh windows install with edge
+    fn fresh_system() -> MemorySystem {
+        let mut system = MemorySystem::new(&[
+            r"C:",
+            r"C:\Program Files (x86)",
+            r"C:\Program Files (x86)\Microsoft",
+            r"C:\Program Files (x86)\Microsoft\Edge",
+            r"C:\Program Files (x86)\Microsoft\Edge\Application",
+            r"C:\ProgramData\Microsoft\Windows\Start Menu\Programs",
+        ]);
+        system.registry = fresh_registry();
+        system
+    }
+
+    fn stable() -> EdgeInstall { EdgeInstall::from_path(MSEDGE_PATH) }
 
-    Ok(())
+    fn beta() -> EdgeInstall {
+        EdgeInstall::from_path(r"C:\Program Files\Microsoft\Edge Beta\Application\msedge.exe")
+    }
+
+    fn context(registry: &dyn Registry, default_browser: Browser) -> InstallContext {
+        InstallContext {
+            scope: Scope::Machine,
+            paths: InstallPaths::default(),
+            files: vec![
+                (BINARY_NAME, vec![0; 200 * 1024]),
+                (UNINSTALLER_NAME, vec![0; 100 * 1024]),
+                (LICENSE_NAME, b"MIT".to_vec()),
+            ],
+            start_menu_dir: String::from(r"C:\ProgramData\Microsoft\Windows\Start Menu\Programs"),
+            default_browser,
+            edges: ifeo_filters(registry, &InstallPaths::default(), vec![stable()]),
+        }
+    }
+
+    fn value(path: &str, name: &str, value: RegValue) -> (String, String, RegValue) {
+        (
+            format!(r"HKEY_LOCAL_MACHINE\{}", path),
+            String::from(name),
+            value,
+        )
+    }
+
+    /// Edge's IFEO mitigation settings, a REG_BINARY value the registry backends must keep
+    fn mitigation_options() -> RegValue {
+        RegValue::Raw {
+            kind: REG_BINARY,
+            bytes: vec![0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        }
+    }
+
+    #[test]
+    fn test_register_application() {
+        let system = fresh_system();
+        let registry = &system.registry;
+        let keys_before = registry.dump_keys();
+        let mut runner = Runner::new(
+            &system,
+            application_actions(Scope::Machine, &InstallPaths::default(), Some(1024 * 300)),
+        );
+        runner.run(|_| {}).unwrap();
+
+        let binary = r#""C:\Program Files (x86)\Wedge\wedge.exe""#;
+        let install_dir = r#""C:\Program Files (x86)\Wedge""#;
+        let app_path = r"Software\Microsoft\Windows\CurrentVersion\App Paths\wedge.exe";
+        let uninstall = r"Software\Microsoft\Windows\CurrentVersion\Uninstall\Wedge";
+        let capabilities = r"Software\Clients\Wedge\Capabilities";
+        assert_eq!(
+            vec![
+                value(
+                    r"Software\Classes\Wedge",
+                    "",
+                    RegValue::from("URL: Microsoft Edge Protocol Deflector")
+                ),
+                value(
+                    r"Software\Classes\Wedge",
+                    "URL Protocol",
+                    RegValue::from("")
+                ),
+                value(
+                    r"Software\Classes\Wedge\DefaultIcon",
+                    "",
+                    RegValue::from(binary)
+                ),
+                value(
+                    r"Software\Classes\Wedge\shell\open\command",
+                    "",
+                    RegValue::from(format!("{} \"%1\"", binary))
+                ),
+                value(
+                    capabilities,
+                    "ApplicationDescription",
+                    RegValue::from(APP_DESC)
+                ),
+                value(capabilities, "ApplicationName", RegValue::from("Wedge")),
+                value(
+                    r"Software\Clients\Wedge\Capabilities\URLAssociations",
+                    "microsoft-edge",
+                    RegValue::from("Wedge")
+                ),
+                value(app_path, "", RegValue::from(binary)),
+                value(app_path, "Path", RegValue::from(install_dir)),
+                value(uninstall, "DisplayIcon", RegValue::from(binary)),
+                value(uninstall, "DisplayName", RegValue::from("Wedge")),
+                value(uninstall, "DisplayVersion", RegValue::from("0.1.0")),
+                value(uninstall, "EstimatedSize", RegValue::Dword(300)),
+                value(uninstall, "InstallLocation", RegValue::from(install_dir)),
+                value(
+                    uninstall,
+                    "ModifyPath",
+                    RegValue::from(format!("{} repair", binary))
+                ),
+                value(
+                    uninstall,
+                    "Publisher",
+                    RegValue::from(env!("CARGO_PKG_AUTHORS"))
+                ),
+                value(
+                    uninstall,
+                    "UninstallString",
+                    RegValue::from(r#""C:\Program Files (x86)\Wedge\wedge uninstaller.exe""#)
+                ),
+                value(
+                    r"Software\RegisteredApplications",
+                    "Wedge",
+                    RegValue::from(r"Software\Clients\Wedge\Capabilities")
+                ),
+            ],
+            registry.dump_values()
+        );
+
+        runner.manifest().undo(&system, |_| {}).unwrap();
+        assert!(registry.dump_values().is_empty());
+        assert_eq!(keys_before, registry.dump_keys());
+    }
+
+    #[test]
+    fn test_register_ifeo() {
+        let system = fresh_system();
+        let registry = &system.registry;
+        let keys_before = registry.dump_keys();
+        let mut runner = Runner::new(
+            &system,
+            ifeo_actions(&InstallPaths::default(), &[(stable(), 0)]),
+        );
+        runner.run(|_| {}).unwrap();
+
+        let ifeo =
+            r"Software\Microsoft\Windows NT\CurrentVersion\Image File Execution Options\msedge.exe";
+        assert_eq!(
+            vec![
+                value(ifeo, "UseFilter", RegValue::Dword(1)),
+                value(
+                    &format!(r"{}\0", ifeo),
+                    "Debugger",
+                    RegValue::from(r#""C:\Program Files (x86)\Wedge\wedge.exe""#)
+                ),
+                value(
+                    &format!(r"{}\0", ifeo),
+                    "FilterFullPath",
+                    RegValue::from(MSEDGE_PATH)
+                ),
+            ],
+            registry.dump_values()
+        );
+
+        runner.manifest().undo(&system, |_| {}).unwrap();
+        assert_eq!(keys_before, registry.dump_keys());
+    }
+
+    #[test]
+    fn test_register_ifeo_keeps_later_settings() {
+        let system = fresh_system();
+        let registry = &system.registry;
+        let mut runner = Runner::new(
+            &system,
+            ifeo_actions(&InstallPaths::default(), &[(stable(), 0)]),
+        );
+        runner.run(|_| {}).unwrap();
+        let manifest = Manifest::from_json(&runner.manifest().to_json()).unwrap();
+
+        // Settings added to edge's IFEO key after installing outlast uninstalling
+        let ifeo = Key::predef(registry, Hive::LocalMachine)
+            .open_subkey(IFEO_KEY)
+            .unwrap();
+        ifeo.set_value("MitigationOptions", mitigation_options())
+            .unwrap();
+        ifeo.create_subkey("1")
+            .unwrap()
+            .set_value("Debugger", r"C:\Tools\monitor.exe")
+            .unwrap();
+        manifest.undo(&system, |_| {}).unwrap();
+        let ifeo =
+            r"Software\Microsoft\Windows NT\CurrentVersion\Image File Execution Options\msedge.exe";
+        assert_eq!(
+            vec![
+                value(ifeo, "MitigationOptions", mitigation_options()),
+                value(
+                    &format!(r"{}\1", ifeo),
+                    "Debugger",
+                    RegValue::from(r"C:\Tools\monitor.exe")
+                ),
+            ],
+            registry.dump_values()
+        );
+        assert!(!registry.key_exists(Hive::LocalMachine, &format!(r"{}\0", IFEO_KEY)));
+    }
+
+    #[test]
+    fn test_register_ifeo_preserves_settings() {
+        let system = fresh_system();
+        let registry = &system.registry;
+        let ifeo = Key::predef(registry, Hive::LocalMachine)
+            .create_subkey(IFEO_KEY)
+            .unwrap();
+        ifeo.set_value("MitigationOptions", mitigation_options())
+            .unwrap();
+        ifeo.set_value("UseFilter", 0u32).unwrap();
+        let monitor = ifeo.create_subkey("0").unwrap();
+        monitor
+            .set_value("Debugger", r"C:\Tools\monitor.exe")
+            .unwrap();
+        monitor
+            .set_value("FilterFullPath", r"C:\Edge\msedge.exe")
+            .unwrap();
+        let keys_before = registry.dump_keys();
+        let values_before = registry.dump_values();
+
+        // Filters already there are skipped, unless they are wedge's own
+        let paths = InstallPaths::default();
+        let edges = ifeo_filters(registry, &paths, vec![stable()]);
+        assert_eq!(vec![(stable(), 1)], edges);
+        let mut runner = Runner::new(&system, ifeo_actions(&paths, &edges));
+        runner.run(|_| {}).unwrap();
+        assert_eq!(edges, ifeo_filters(registry, &paths, vec![stable()]));
+        assert_eq!(Ok(1), ifeo.get_u32("UseFilter").map_err(|_| ()));
+        assert_eq!(
+            Ok(String::from(r"C:\Tools\monitor.exe")),
+            monitor.get_string("Debugger").map_err(|_| ())
+        );
+
+        Manifest::from_json(&runner.manifest().to_json())
+            .unwrap()
+            .undo(&system, |_| {})
+            .unwrap();
+        assert_eq!(keys_before, registry.dump_keys());
+        assert_eq!(values_before, registry.dump_values());
+    }
+
+    #[test]
+    fn test_install_plan() {
+        let system = fresh_system();
+        Runner::new(
+            &system,
+            install_plan(&context(&system.registry, Browser::Firefox)),
+        )
+        .run(|_| {})
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                (
+                    String::from(
+                        r"C:\Program Files (x86)\Microsoft\Edge\Application\msedge-wedge-proxy.exe"
+                    ),
+                    MemoryFile::Symlink {
+                        target: String::from(MSEDGE_PATH)
+                    }
+                ),
+                (
+                    String::from(r"C:\Program Files (x86)\Wedge\LICENSE"),
+                    MemoryFile::File(b"MIT".to_vec())
+                ),
+                (
+                    String::from(r"C:\Program Files (x86)\Wedge\wedge uninstaller.exe"),
+                    MemoryFile::File(vec![0; 100 * 1024])
+                ),
+                (
+                    String::from(r"C:\Program Files (x86)\Wedge\wedge.exe"),
+                    MemoryFile::File(vec![0; 200 * 1024])
+                ),
+                (
+                    String::from(r"C:\ProgramData\Microsoft\Windows\Start Menu\Programs\Wedge.lnk"),
+                    MemoryFile::Shortcut {
+                        target: String::from(r#""C:\Program Files (x86)\Wedge\wedge.exe""#),
+                        description: String::from("Wedge - The simple Open-Source Edge Deflector"),
+                    }
+                ),
+            ],
+            system.dump_files()
+        );
+        assert!(system.registry.key_exists(Hive::LocalMachine, IFEO_KEY));
+        assert_eq!(vec![NOTIFY_URL], system.opened_urls());
+
+        // Edge users aren't sent to the extension
+        assert!(!install_plan(&context(&system.registry, Browser::Edge))
+            .iter()
+            .any(|action| match action {
+                Action::OpenUrl { .. } => true,
+                _ => false,
+            }));
+    }
+
+    #[test]
+    fn test_install_channels() {
+        let system = fresh_system();
+        system.create_dir(r"C:\Program Files").unwrap();
+        system.create_dir(r"C:\Program Files\Microsoft").unwrap();
+        system
+            .create_dir(r"C:\Program Files\Microsoft\Edge Beta")
+            .unwrap();
+        system
+            .create_dir(r"C:\Program Files\Microsoft\Edge Beta\Application")
+            .unwrap();
+        let paths = InstallPaths::default();
+        let mut channels = context(&system.registry, Browser::Firefox);
+        channels.edges = ifeo_filters(&system.registry, &paths, vec![stable(), beta()]);
+        assert_eq!(vec![(stable(), 0), (beta(), 1)], channels.edges);
+        Runner::new(&system, install_plan(&channels))
+            .run(|_| {})
+            .unwrap();
+
+        // Each channel gets its own proxy and filter, which are reused whatever order they are
+        // found in
+        for (edge, filter) in &channels.edges {
+            assert_eq!(
+                Some(&(edge.proxy_path(), MemoryFile::Symlink {
+                    target: edge.path.clone()
+                })),
+                system
+                    .dump_files()
+                    .iter()
+                    .find(|(path, _)| *path == edge.proxy_path())
+            );
+            let filter = Key::predef(&system.registry, Hive::LocalMachine)
+                .open_subkey(&format!(r"{}\{}", IFEO_KEY, filter))
+                .unwrap();
+            assert_eq!(
+                Ok(edge.path.clone()),
+                filter.get_string("FilterFullPath").map_err(|_| ())
+            );
+        }
+        assert_eq!(
+            vec![(beta(), 1), (stable(), 0)],
+            ifeo_filters(&system.registry, &paths, vec![beta(), stable()])
+        );
+    }
+
+    #[test]
+    fn test_install_per_user() {
+        let mut system = MemorySystem::new(&[
+            r"C:",
+            r"C:\Users\dev\AppData\Local",
+            r"C:\Users\dev\AppData\Roaming\Microsoft\Windows\Start Menu\Programs",
+        ]);
+        system.registry = fresh_registry();
+        system.elevated = false;
+        Key::predef(&system.registry, Hive::CurrentUser)
+            .create_subkey("Software")
+            .unwrap();
+        let mut user = context(&system.registry, Browser::Firefox);
+        user.scope = Scope::User;
+        user.paths.install_dir = String::from(r"C:\Users\dev\AppData\Local\Wedge");
+        user.start_menu_dir =
+            String::from(r"C:\Users\dev\AppData\Roaming\Microsoft\Windows\Start Menu\Programs");
+        let manifest_path = user.paths.manifest_path();
+        let keys_before = system.registry.dump_keys();
+        Runner::new(&system, install_plan(&user))
+            .with_manifest(&manifest_path, Manifest::default())
+            .run(|_| {})
+            .unwrap();
+
+        // Only the current user is registered, and edge itself is left alone
+        let values = system.registry.dump_values();
+        assert!(values
+            .iter()
+            .all(|(key, _, _)| key.starts_with(r"HKEY_CURRENT_USER\")));
+        assert!(values.contains(&(
+            format!(r"HKEY_CURRENT_USER\{}\Wedge", UNINSTALL_KEY),
+            String::from("UninstallString"),
+            RegValue::from(r#""C:\Users\dev\AppData\Local\Wedge\wedge uninstaller.exe" /user"#)
+        )));
+        assert!(values.contains(&(
+            format!(r"HKEY_CURRENT_USER\{}\Wedge", UNINSTALL_KEY),
+            String::from("ModifyPath"),
+            RegValue::from(r#""C:\Users\dev\AppData\Local\Wedge\wedge.exe" repair --user"#)
+        )));
+        assert!(system.path_exists(&user.paths.binary_path()));
+        assert!(!system.path_exists(&stable().proxy_path()));
+
+        Manifest::load(&system, &manifest_path)
+            .unwrap()
+            .undo(&system, |_| {})
+            .unwrap();
+        assert!(system.registry.dump_values().is_empty());
+        assert_eq!(keys_before, system.registry.dump_keys());
+        assert!(!system.path_exists(&user.paths.install_dir));
+    }
+
+    #[test]
+    fn test_install_rollback() {
+        let mut system = fresh_system();
+        system.elevated = false;
+        let dirs_before = system.dump_dirs();
+        let keys_before = system.registry.dump_keys();
+        let values_before = system.registry.dump_values();
+
+        let mut runner = Runner::new(
+            &system,
+            install_plan(&context(&system.registry, Browser::Firefox)),
+        );
+        let error = runner.run(|_| {}).unwrap_err();
+        assert_eq!(
+            "Error on step 32/38 Administrator rights are needed to create symlinks. Run wedge as \
+             administrator.",
+            error.to_string()
+        );
+
+        assert_eq!(dirs_before, system.dump_dirs());
+        assert!(system.dump_files().is_empty());
+        assert_eq!(keys_before, system.registry.dump_keys());
+        assert_eq!(values_before, system.registry.dump_values());
+        assert!(system.opened_urls().is_empty());
+    }
+
+    #[test]
+    fn test_uninstall() {
+        let system = fresh_system();
+        let dirs_before = system.dump_dirs();
+        let keys_before = system.registry.dump_keys();
+
+        // Install twice, as when upgrading, then undo both from the manifest
+        for _ in 0..2 {
+            Runner::new(
+                &system,
+                install_plan(&context(&system.registry, Browser::Firefox)),
+            )
+            .with_manifest(
+                &InstallPaths::default().manifest_path(),
+                Manifest::default(),
+            )
+            .run(|_| {})
+            .unwrap();
+        }
+        let manifest = Manifest::load(&system, &InstallPaths::default().manifest_path()).unwrap();
+        assert!(manifest.undos.contains(&Undo::RestoreValue {
+            hive: Hive::LocalMachine,
+            path: String::from(r"Software\RegisteredApplications"),
+            name: String::from(APP_ID),
+            previous: None,
+        }));
+        manifest.undo(&system, |_| {}).unwrap();
+
+        assert_eq!(dirs_before, system.dump_dirs());
+        assert!(system.dump_files().is_empty());
+        assert_eq!(keys_before, system.registry.dump_keys());
+        assert!(system.registry.dump_values().is_empty());
+
+        // Installs without a manifest only undo the filter they are known to have used
+        let legacy = context(&system.registry, Browser::Firefox);
+        Runner::new(&system, install_plan(&legacy))
+            .run(|_| {})
+            .unwrap();
+        assert!(!system.path_exists(&InstallPaths::default().manifest_path()));
+        legacy_manifest(&legacy.start_menu_dir)
+            .undo(&system, |_| {})
+            .unwrap();
+
+        assert_eq!(dirs_before, system.dump_dirs());
+        assert!(system.dump_files().is_empty());
+        assert!(system.registry.key_exists(Hive::LocalMachine, IFEO_KEY));
+        assert!(!system
+            .registry
+            .key_exists(Hive::LocalMachine, &format!(r"{}\0", IFEO_KEY)));
+        assert_eq!(
+            vec![value(
+                r"Software\Microsoft\Windows NT\CurrentVersion\Image File Execution Options\msedge.exe",
+                "UseFilter",
+                RegValue::Dword(1)
+            )],
+            system.registry.dump_values()
+        );
+
+        // Upgrading them reuses their filter, and uninstalls both
+        Runner::new(&system, install_plan(&legacy))
+            .run(|_| {})
+            .unwrap();
+        let upgrade = context(&system.registry, Browser::Firefox);
+        assert_eq!(vec![(stable(), 0)], upgrade.edges);
+        Runner::new(&system, install_plan(&upgrade))
+            .with_manifest(
+                &InstallPaths::default().manifest_path(),
+                legacy_manifest(&upgrade.start_menu_dir),
+            )
+            .run(|_| {})
+            .unwrap();
+        Manifest::load(&system, &InstallPaths::default().manifest_path())
+            .unwrap()
+            .undo(&system, |_| {})
+            .unwrap();
+
+        assert_eq!(dirs_before, system.dump_dirs());
+        assert!(system.dump_files().is_empty());
+        assert!(!system
+            .registry
+            .key_exists(Hive::LocalMachine, &format!(r"{}\0", IFEO_KEY)));
+    }
 }
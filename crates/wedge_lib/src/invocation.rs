@@ -0,0 +1,211 @@
+use crate::cmdline::CommandLine;
+
+/// Why edge was launched, worked out from its command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum InvocationClass {
+    /// Renderer, gpu, utility and other processes edge spawns itself (`--type=`)
+    ChildProcess,
+    /// Headless or remote debugging sessions used by automation tools
+    Automation,
+    /// Launched by the edge installer or relaunched after an update
+    Updater,
+    /// User clicked one of edge's notifications (`--notification-launch-id`)
+    Notification,
+    /// Progressive web app or site app (`--app-id`, `--app=`)
+    WebApp,
+    /// Startup boost preloading edge in the background (`--no-startup-window`)
+    StartupBoost,
+    /// A `microsoft-edge:` link
+    ProtocolUrl,
+    /// A plain http(s) url, as launched by Outlook, Teams and the like
+    WebUrl,
+    /// Anything else, like the user opening edge directly
+    Other,
+}
+
+/// What to do with an invocation of a given class
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Let edge handle it
+    PassThrough,
+    /// Open any urls in the default browser instead of edge
+    Deflect,
+    /// Don't launch anything
+    Block,
+}
+
+impl InvocationClass {
+    pub const ALL: [InvocationClass; 9] = [
+        InvocationClass::ChildProcess,
+        InvocationClass::Automation,
+        InvocationClass::Updater,
+        InvocationClass::Notification,
+        InvocationClass::WebApp,
+        InvocationClass::StartupBoost,
+        InvocationClass::ProtocolUrl,
+        InvocationClass::WebUrl,
+        InvocationClass::Other,
+    ];
+
+    /// Name used in settings
+    pub fn name(self) -> &'static str {
+        match self {
+            InvocationClass::ChildProcess => "child-process",
+            InvocationClass::Automation => "automation",
+            InvocationClass::Updater => "updater",
+            InvocationClass::Notification => "notification",
+            InvocationClass::WebApp => "web-app",
+            InvocationClass::StartupBoost => "startup-boost",
+            InvocationClass::ProtocolUrl => "protocol-url",
+            InvocationClass::WebUrl => "web-url",
+            InvocationClass::Other => "other",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<InvocationClass> {
+        let name = name.trim().to_lowercase();
+        InvocationClass::ALL
+            .iter()
+            .cloned()
+            .find(|class| class.name() == name)
+    }
+
+    /// Action taken when the user hasn't configured one. Only links are deflected so edge's
+    /// own processes are never broken.
+    pub fn default_action(self) -> Action {
+        match self {
+            InvocationClass::ProtocolUrl | InvocationClass::WebUrl => Action::Deflect,
+            _ => Action::PassThrough,
+        }
+    }
+}
+
+impl Action {
+    pub const ALL: [Action; 3] = [Action::Deflect, Action::PassThrough, Action::Block];
+
+    /// Name used in settings
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::PassThrough => "pass-through",
+            Action::Deflect => "deflect",
+            Action::Block => "block",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Some(match name.trim().to_lowercase().as_str() {
+            "pass-through" | "passthrough" => Action::PassThrough,
+            "deflect" => Action::Deflect,
+            "block" => Action::Block,
+            _ => return None,
+        })
+    }
+}
+
+/// Classifies an edge command line. The first matching class wins, so internal launches
+/// are recognized before anything that might carry a url.
+pub fn classify(edge: &CommandLine) -> InvocationClass {
+    let any = |switches: &[&str]| switches.iter().any(|s| edge.has_switch(s));
+
+    if any(&["type"]) {
+        InvocationClass::ChildProcess
+    } else if any(&[
+        "headless",
+        "remote-debugging-port",
+        "remote-debugging-pipe",
+        "enable-automation",
+    ]) {
+        InvocationClass::Automation
+    } else if any(&["from-installer", "restore-last-session", "uninstall"]) {
+        InvocationClass::Updater
+    } else if any(&["notification-launch-id"]) {
+        InvocationClass::Notification
+    } else if any(&["app-id", "app"]) {
+        InvocationClass::WebApp
+    } else if any(&["no-startup-window"]) {
+        InvocationClass::StartupBoost
+    } else if edge
+        .positionals()
+        .iter()
+        .any(|arg| arg.starts_with("microsoft-edge:"))
+    {
+        InvocationClass::ProtocolUrl
+    } else if edge.positionals().iter().any(|arg| {
+        let arg = arg.to_lowercase();
+        arg.starts_with("http:") || arg.starts_with("https:")
+    }) {
+        InvocationClass::WebUrl
+    } else {
+        InvocationClass::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify_args(args: &[&str]) -> InvocationClass {
+        let mut argv = vec!["msedge.exe"];
+        argv.extend_from_slice(args);
+        classify(&CommandLine::from_args(&argv))
+    }
+
+    #[test]
+    fn test_classify() {
+        use InvocationClass::*;
+        assert_eq!(
+            ChildProcess,
+            classify_args(&["--type=renderer", "--field-trial-handle=1,2"])
+        );
+        assert_eq!(
+            ChildProcess,
+            classify_args(&["--type=crashpad-handler", "https://example.com"])
+        );
+        assert_eq!(Automation, classify_args(&["--headless", "--dump-dom"]));
+        assert_eq!(
+            Automation,
+            classify_args(&["--remote-debugging-port=9222", "about:blank"])
+        );
+        assert_eq!(Updater, classify_args(&["--from-installer"]));
+        assert_eq!(
+            Notification,
+            classify_args(&["--notification-launch-id=1|Default|0|https://x.com/|"])
+        );
+        assert_eq!(WebApp, classify_args(&["--app-id=abcdefg"]));
+        assert_eq!(WebApp, classify_args(&["--app=https://example.com"]));
+        assert_eq!(
+            StartupBoost,
+            classify_args(&["--no-startup-window", "--win-session-start"])
+        );
+        assert_eq!(
+            ProtocolUrl,
+            classify_args(&[
+                "--single-argument",
+                "microsoft-edge:?url=https%3A%2F%2Fexample.com"
+            ])
+        );
+        assert_eq!(
+            WebUrl,
+            classify_args(&["--single-argument", "HTTPS://example.com"])
+        );
+        assert_eq!(Other, classify_args(&[]));
+        assert_eq!(Other, classify_args(&["--profile-directory=Default"]));
+        assert_eq!(Other, classify_args(&["file:///C:/some.pdf"]));
+    }
+
+    #[test]
+    fn test_names() {
+        for class in &InvocationClass::ALL {
+            assert_eq!(Some(*class), InvocationClass::from_name(class.name()));
+        }
+        assert_eq!(
+            Some(InvocationClass::WebUrl),
+            InvocationClass::from_name(" Web-Url")
+        );
+        assert_eq!(None, InvocationClass::from_name("web"));
+        for action in &[Action::PassThrough, Action::Deflect, Action::Block] {
+            assert_eq!(Some(*action), Action::from_name(action.name()));
+        }
+        assert_eq!(None, Action::from_name("open"));
+    }
+}
@@ -0,0 +1,280 @@
+use crate::{
+    browser::{find_prog_id, get_default_prog_id},
+    cmdline::CommandLine,
+    deflect::{Env, Link},
+    error::WedgeError,
+    registry::{Hive, Key, Registry},
+    rules::Target,
+};
+
+/// Placeholders windows replaces with the url in an open command
+const URL_PLACEHOLDERS: [&str; 3] = ["%1", "%L", "%l"];
+
+/// A program to launch with its args
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Launch {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+/// Open command a ProgId registers under `HKCR\<ProgId>\shell\open\command`, such as
+/// `"C:\Program Files\Mozilla Firefox\firefox.exe" -osint -url "%1"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenCommand {
+    program: String,
+    args: Vec<String>,
+}
+
+impl OpenCommand {
+    /// Parses an open command whose environment variables were already expanded
+    pub fn parse(command: &str) -> Option<OpenCommand> {
+        let mut argv = CommandLine::parse(command).argv().into_iter();
+        let program = argv.next().filter(|program| !program.is_empty())?;
+        Some(OpenCommand {
+            program,
+            args: argv.collect(),
+        })
+    }
+
+    /// Reads the open command of a ProgId
+    pub fn resolve(registry: &dyn Registry, prog_id: &str) -> Result<OpenCommand, WedgeError> {
+        let command = Key::predef(registry, Hive::ClassesRoot)
+            .open_subkey(prog_id)?
+            .open_subkey(r"shell\open\command")?
+            .get_string("")?;
+        OpenCommand::parse(&expand_env(&command, |name| std::env::var(name).ok())).ok_or_else(
+            || WedgeError::BrowserDetection(format!("Open command of `{}` is empty", prog_id)),
+        )
+    }
+
+    pub fn program(&self) -> &str { &self.program }
+
+    /// Fills the url into the command. Extra args such as `--new-window` go right after the
+    /// program, since browsers treat everything after `--single-argument` as the url.
+    pub fn launch(&self, url: &str, extra_args: &[String]) -> Launch {
+        let mut args = extra_args.to_vec();
+        let mut has_placeholder = false;
+        for arg in &self.args {
+            let mut arg = arg.clone();
+            for placeholder in &URL_PLACEHOLDERS {
+                if arg.contains(placeholder) {
+                    arg = arg.replace(placeholder, url);
+                    has_placeholder = true;
+                }
+            }
+            args.push(arg);
+        }
+        if !has_placeholder {
+            args.push(String::from(url));
+        }
+
+        Launch {
+            program: self.program.clone(),
+            args,
+        }
+    }
+}
+
+/// Works out how to launch the browser a link is routed to. Fails when the browser can't
+/// be found in the registry, leaving the caller to fall back on `ShellExecute`.
+pub fn launch_link(registry: &dyn Registry, env: &Env, link: &Link) -> Result<Launch, WedgeError> {
+    let mut args = link.args.clone();
+    args.extend(vec![String::from("--single-argument"), link.url.clone()]);
+    let edge = Launch {
+        program: env.msedge_proxy_path.clone(),
+        args,
+    };
+
+    let prog_id = match link.target {
+        Target::Edge => return Ok(edge),
+        Target::DefaultBrowser => get_default_prog_id(registry)?,
+        Target::Browser(browser) => find_prog_id(registry, browser).ok_or_else(|| {
+            WedgeError::BrowserDetection(format!("{:?} isn't installed", browser))
+        })?,
+    };
+    let command = OpenCommand::resolve(registry, &prog_id)?;
+
+    // Launching edge directly would send the url straight back to wedge
+    if command.program().eq_ignore_ascii_case(&env.msedge_path) {
+        return Ok(edge);
+    }
+
+    Ok(command.launch(&link.url, &link.args))
+}
+
+/// Expands `%NAME%` environment variables like a REG_EXPAND_SZ value, leaving anything
+/// that isn't a defined variable (such as `%1`) untouched
+fn expand_env<F: Fn(&str) -> Option<String>>(text: &str, lookup: F) -> String {
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('%') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after
+            .find('%')
+            .and_then(|end| Some((end, lookup(&after[..end]).filter(|_| end > 0)?)))
+        {
+            Some((end, value)) => {
+                expanded.push_str(&value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                expanded.push('%');
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{browser::Browser, install::MSEDGE_PROXY_PATH, registry::MemoryRegistry};
+
+    fn set_open_command(registry: &MemoryRegistry, prog_id: &str, command: &str) {
+        Key::predef(registry, Hive::ClassesRoot)
+            .create_subkey(prog_id)
+            .unwrap()
+            .create_subkey(r"shell\open\command")
+            .unwrap()
+            .set_value("", command)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_expand_env() {
+        let lookup = |name: &str| match name {
+            "ProgramFiles" => Some(String::from(r"C:\Program Files")),
+            _ => None,
+        };
+        assert_eq!(
+            r#""C:\Program Files\app.exe" "%1""#,
+            expand_env(r#""%ProgramFiles%\app.exe" "%1""#, lookup)
+        );
+        assert_eq!("%1 %L %% 100%", expand_env("%1 %L %% 100%", lookup));
+        assert_eq!("%Undefined%", expand_env("%Undefined%", lookup));
+    }
+
+    #[test]
+    fn test_open_command() {
+        let url = "https://example.com/?a=1&b=2";
+
+        let firefox = OpenCommand::parse(
+            r#""C:\Program Files\Mozilla Firefox\firefox.exe" -osint -url "%1""#,
+        )
+        .unwrap();
+        assert_eq!(
+            Launch {
+                program: String::from(r"C:\Program Files\Mozilla Firefox\firefox.exe"),
+                args: vec![
+                    String::from("-private-window"),
+                    String::from("-osint"),
+                    String::from("-url"),
+                    String::from(url),
+                ],
+            },
+            firefox.launch(url, &[String::from("-private-window")])
+        );
+
+        let chrome = OpenCommand::parse(
+            r#""C:\Program Files\Google\Chrome\Application\chrome.exe" --single-argument %1"#,
+        )
+        .unwrap();
+        assert_eq!(vec!["--single-argument", url], chrome.launch(url, &[]).args);
+
+        // Urls are appended when the command has no placeholder
+        let browser = OpenCommand::parse(r"C:\Browser\browser.exe").unwrap();
+        assert_eq!(vec![url], browser.launch(url, &[]).args);
+
+        assert_eq!(None, OpenCommand::parse(""));
+    }
+
+    #[test]
+    fn test_launch_link() {
+        let registry = MemoryRegistry::new();
+        let env = Env::default();
+        let link = Link::new("https://example.com/");
+        assert!(launch_link(&registry, &env, &link).is_err());
+
+        // Any ProgId the user picked works, not just browsers wedge knows about
+        Key::predef(&registry, Hive::CurrentUser)
+            .create_subkey(
+                r"Software\Microsoft\Windows\Shell\Associations\UrlAssociations\http\UserChoice",
+            )
+            .unwrap()
+            .set_value("Progid", "BraveHTML")
+            .unwrap();
+        set_open_command(
+            &registry,
+            "BraveHTML",
+            r#""C:\Brave\brave.exe" --single-argument %1"#,
+        );
+        assert_eq!(
+            Launch {
+                program: String::from(r"C:\Brave\brave.exe"),
+                args: vec![
+                    String::from("--single-argument"),
+                    String::from("https://example.com/"),
+                ],
+            },
+            launch_link(&registry, &env, &link).unwrap()
+        );
+
+        // Specific browsers are found through the clients they register
+        let link = Link {
+            target: Target::Browser(Browser::Firefox),
+            args: vec![String::from("-private-window")],
+            ..Link::new("https://example.com/")
+        };
+        assert!(launch_link(&registry, &env, &link).is_err());
+        Key::predef(&registry, Hive::LocalMachine)
+            .create_subkey(r"Software\Clients\StartMenuInternet\Firefox-308046B0AF4A39CB")
+            .unwrap()
+            .create_subkey(r"Capabilities\URLAssociations")
+            .unwrap()
+            .set_value("http", "FirefoxURL-308046B0AF4A39CB")
+            .unwrap();
+        set_open_command(
+            &registry,
+            "FirefoxURL-308046B0AF4A39CB",
+            r#""C:\Firefox\firefox.exe" -osint -url "%1""#,
+        );
+        assert_eq!(
+            Launch {
+                program: String::from(r"C:\Firefox\firefox.exe"),
+                args: vec![
+                    String::from("-private-window"),
+                    String::from("-osint"),
+                    String::from("-url"),
+                    String::from("https://example.com/"),
+                ],
+            },
+            launch_link(&registry, &env, &link).unwrap()
+        );
+
+        // Edge goes through the proxy so it isn't deflected again
+        let edge = Launch {
+            program: String::from(MSEDGE_PROXY_PATH),
+            args: vec![
+                String::from("--single-argument"),
+                String::from("https://example.com/"),
+            ],
+        };
+        let link = Link {
+            target: Target::Edge,
+            ..Link::new("https://example.com/")
+        };
+        assert_eq!(edge, launch_link(&registry, &env, &link).unwrap());
+        set_open_command(
+            &registry,
+            "BraveHTML",
+            &format!("\"{}\" --single-argument %1", env.msedge_path),
+        );
+        assert_eq!(
+            edge,
+            launch_link(&registry, &env, &Link::new("https://example.com/")).unwrap()
+        );
+    }
+}
@@ -1,5 +1,28 @@
 pub mod browser;
 pub mod build;
+pub mod cli;
+pub mod cmdline;
+#[cfg(windows)]
 mod com;
+pub mod config;
+pub mod deflect;
+pub mod doctor;
+pub mod edge;
+pub mod error;
+pub mod explain;
+pub mod export;
+pub mod fallback;
 pub mod install;
+pub mod invocation;
+pub mod launch;
+pub mod pause;
+pub mod plan;
+pub mod policy;
+pub mod registry;
+pub mod repair;
+pub mod rules;
+pub mod search;
+pub mod system;
+pub mod uri;
+#[cfg(windows)]
 pub mod win32;
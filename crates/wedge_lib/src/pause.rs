@@ -0,0 +1,212 @@
+use crate::{
+    config::SETTINGS_KEY,
+    error::WedgeError,
+    registry::{Hive, Key, Registry},
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Value under `HKCU\Software\Wedge` holding when a pause ends, in seconds since the unix epoch,
+/// or 0 for a pause lasting until `wedge resume`
+pub const PAUSED_UNTIL_VALUE: &str = "PausedUntil";
+
+/// Longest duration `parse_duration` accepts, in seconds. Anything longer is better left until
+/// `wedge resume`.
+pub const MAX_DURATION: u64 = 365 * 24 * 60 * 60;
+
+/// Source of the current time
+pub trait Clock {
+    /// Seconds since the unix epoch
+    fn now(&self) -> u64;
+}
+
+/// The system's clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+/// How long deflection is paused for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pause {
+    /// Until a time, in seconds since the unix epoch
+    Until(u64),
+    UntilResumed,
+}
+
+impl Pause {
+    pub fn describe(self, clock: &dyn Clock) -> String {
+        match self {
+            Pause::Until(end) => format!(
+                "Deflection is paused for {}, or until `wedge resume`",
+                format_duration(end.saturating_sub(clock.now()))
+            ),
+            Pause::UntilResumed => String::from("Deflection is paused until `wedge resume`"),
+        }
+    }
+}
+
+/// Pauses deflection for the current user, for `duration` seconds or until resumed
+pub fn pause(
+    registry: &dyn Registry,
+    clock: &dyn Clock,
+    duration: Option<u64>,
+) -> Result<Pause, WedgeError> {
+    let pause = match duration {
+        Some(duration) => Pause::Until(clock.now().saturating_add(duration)),
+        None => Pause::UntilResumed,
+    };
+    let until = match pause {
+        Pause::Until(end) => end,
+        Pause::UntilResumed => 0,
+    };
+    Key::predef(registry, Hive::CurrentUser)
+        .create_subkey(SETTINGS_KEY)?
+        .set_value(PAUSED_UNTIL_VALUE, until.to_string())?;
+    Ok(pause)
+}
+
+/// Resumes deflection for the current user. Returns whether it was paused.
+pub fn resume(registry: &dyn Registry, clock: &dyn Clock) -> Result<bool, WedgeError> {
+    let paused = current_pause(registry, clock).is_some();
+    if let Ok(settings) = Key::predef(registry, Hive::CurrentUser).open_subkey(SETTINGS_KEY) {
+        if settings.get_value(PAUSED_UNTIL_VALUE).is_ok() {
+            settings.delete_value(PAUSED_UNTIL_VALUE)?;
+        }
+    }
+    Ok(paused)
+}
+
+/// The current user's pause, unless there is none or it has ended
+pub fn current_pause(registry: &dyn Registry, clock: &dyn Clock) -> Option<Pause> {
+    let until: u64 = Key::predef(registry, Hive::CurrentUser)
+        .open_subkey(SETTINGS_KEY)
+        .and_then(|settings| settings.get_string(PAUSED_UNTIL_VALUE))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    match until {
+        0 => Some(Pause::UntilResumed),
+        until if until > clock.now() => Some(Pause::Until(until)),
+        _ => None,
+    }
+}
+
+/// Parses a duration like `45s`, `30m`, `2h` or `1d` into seconds, up to `MAX_DURATION`
+pub fn parse_duration(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let unit = text.chars().last()?;
+    let seconds = match unit.to_ascii_lowercase() {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+    let count: u64 = text[..text.len() - unit.len_utf8()].parse().ok()?;
+    match count.checked_mul(seconds) {
+        Some(duration) if duration > 0 && duration <= MAX_DURATION => Some(duration),
+        _ => None,
+    }
+}
+
+/// Formats seconds like `1d 2h 5m`, leaving out seconds past the first minute
+pub fn format_duration(seconds: u64) -> String {
+    if seconds < 60 {
+        return format!("{}s", seconds);
+    }
+    // Round up so a pause never looks shorter than it is
+    let minutes = (seconds - 1) / 60 + 1;
+    let parts = [
+        (minutes / (24 * 60), "d"),
+        (minutes / 60 % 24, "h"),
+        (minutes % 60, "m"),
+    ];
+    parts
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, unit)| format!("{}{}", count, unit))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::MemoryRegistry;
+    use std::cell::Cell;
+
+    struct FakeClock(Cell<u64>);
+
+    impl Clock for FakeClock {
+        fn now(&self) -> u64 { self.0.get() }
+    }
+
+    #[test]
+    fn test_pause() {
+        let registry = MemoryRegistry::new();
+        let clock = FakeClock(Cell::new(1_000_000));
+        assert_eq!(None, current_pause(&registry, &clock));
+        assert!(!resume(&registry, &clock).unwrap());
+
+        assert_eq!(
+            Pause::Until(1_001_800),
+            pause(&registry, &clock, Some(1800)).unwrap()
+        );
+        assert_eq!(
+            Some(Pause::Until(1_001_800)),
+            current_pause(&registry, &clock)
+        );
+        clock.0.set(1_000_030);
+        assert_eq!(
+            "Deflection is paused for 30m, or until `wedge resume`",
+            current_pause(&registry, &clock).unwrap().describe(&clock)
+        );
+
+        // Pauses end on their own
+        clock.0.set(1_001_800);
+        assert_eq!(None, current_pause(&registry, &clock));
+        assert!(!resume(&registry, &clock).unwrap());
+
+        // Pauses too long to count end as late as can be
+        assert_eq!(
+            Pause::Until(!0),
+            pause(&registry, &clock, Some(!0)).unwrap()
+        );
+        assert_eq!(Some(Pause::Until(!0)), current_pause(&registry, &clock));
+
+        assert_eq!(Pause::UntilResumed, pause(&registry, &clock, None).unwrap());
+        clock.0.set(4_000_000_000);
+        assert_eq!(Some(Pause::UntilResumed), current_pause(&registry, &clock));
+        assert!(resume(&registry, &clock).unwrap());
+        assert_eq!(None, current_pause(&registry, &clock));
+    }
+
+    #[test]
+    fn test_durations() {
+        assert_eq!(Some(45), parse_duration("45s"));
+        assert_eq!(Some(30 * 60), parse_duration("30m"));
+        assert_eq!(Some(2 * 60 * 60), parse_duration(" 2H "));
+        assert_eq!(Some(24 * 60 * 60), parse_duration("1d"));
+        for invalid in &["", "m", "30", "0m", "-5m", "1.5h", "30 m", "1w"] {
+            assert_eq!(None, parse_duration(invalid), "{}", invalid);
+        }
+        assert_eq!(Some(MAX_DURATION), parse_duration("365d"));
+        assert_eq!(None, parse_duration("366d"));
+        assert_eq!(None, parse_duration("999999999999d"));
+        // Too many seconds to count
+        assert_eq!(None, parse_duration("213503982334602d"));
+
+        assert_eq!("45s", format_duration(45));
+        assert_eq!("1m", format_duration(60));
+        assert_eq!("2m", format_duration(61));
+        assert_eq!("1h 30m", format_duration(90 * 60));
+        assert_eq!("1d 2h", format_duration(26 * 60 * 60));
+    }
+}
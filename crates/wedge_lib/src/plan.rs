@@ -0,0 +1,994 @@
+use crate::{
+    error::WedgeError,
+    registry::{hex_bytes, join, Hive, Key, RegValue},
+    system::{parent, System},
+};
+use serde_json::{json, Value};
+use std::io::{Error, ErrorKind};
+
+/// A single change made to the machine while installing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Creates a directory along with any missing parents
+    CreateDir {
+        path: String,
+    },
+    WriteFile {
+        path: String,
+        contents: Vec<u8>,
+    },
+    /// Creates a registry key along with any missing parents
+    CreateKey {
+        hive: Hive,
+        path: String,
+    },
+    /// Creates a registry key other programs may add to, like edge's IFEO key. Undoing deletes
+    /// the keys it created only once nothing is left in them.
+    CreateSharedKey {
+        hive: Hive,
+        path: String,
+    },
+    /// Sets a value of an existing registry key
+    SetValue {
+        hive: Hive,
+        path: String,
+        name: String,
+        value: RegValue,
+    },
+    CreateShortcut {
+        path: String,
+        target: String,
+        description: String,
+    },
+    CreateSymlink {
+        path: String,
+        target: String,
+    },
+    /// Opens a url. This can't be undone, so it belongs at the end of a plan.
+    OpenUrl {
+        url: String,
+    },
+}
+
+impl Action {
+    /// Makes the change, returning how to take it back
+    pub fn apply(&self, system: &dyn System) -> Result<Undo, WedgeError> {
+        Ok(match self {
+            Action::CreateDir { path } => {
+                // Create missing parents top down, remembering the topmost one for undoing
+                let mut missing = vec![];
+                let mut dir = Some(path.as_str());
+                while let Some(path) = dir {
+                    if system.path_exists(path) {
+                        break;
+                    }
+                    missing.push(path);
+                    dir = parent(path);
+                }
+                for dir in missing.iter().rev() {
+                    system.create_dir(dir)?;
+                }
+                match missing.last() {
+                    Some(topmost) => Undo::RemoveDir {
+                        path: String::from(*topmost),
+                    },
+                    None => Undo::Nothing,
+                }
+            }
+            Action::WriteFile { path, contents } => {
+                let undo = if system.path_exists(path) {
+                    Undo::RestoreFile {
+                        path: path.clone(),
+                        contents: system.read_file(path)?,
+                    }
+                } else {
+                    Undo::RemoveFile { path: path.clone() }
+                };
+                system.write_file(path, contents)?;
+                undo
+            }
+            Action::CreateKey { hive, path } | Action::CreateSharedKey { hive, path } => {
+                let registry = system.registry();
+                let mut prefix = String::new();
+                let topmost = path.split('\\').find_map(|component| {
+                    prefix = join(&prefix, component);
+                    if registry.key_exists(*hive, &prefix) {
+                        None
+                    } else {
+                        Some(prefix.clone())
+                    }
+                });
+                Key::predef(registry, *hive).create_subkey(path)?;
+                match (self, topmost) {
+                    (Action::CreateSharedKey { .. }, Some(path)) => {
+                        Undo::DeleteKeyIfEmpty { hive: *hive, path }
+                    }
+                    (_, Some(path)) => Undo::DeleteKey { hive: *hive, path },
+                    (_, None) => Undo::Nothing,
+                }
+            }
+            Action::SetValue {
+                hive,
+                path,
+                name,
+                value,
+            } => {
+                let key = Key::predef(system.registry(), *hive).open_subkey(path)?;
+                let previous = match key.get_value(name) {
+                    Ok(previous) => Some(previous),
+                    Err(ref e) if e.is_not_found() => None,
+                    Err(e) => return Err(e),
+                };
+                key.set_value(name, value.clone())?;
+                Undo::RestoreValue {
+                    hive: *hive,
+                    path: path.clone(),
+                    name: name.clone(),
+                    previous,
+                }
+            }
+            Action::CreateShortcut {
+                path,
+                target,
+                description,
+            } => {
+                system.create_shortcut(path, target, description)?;
+                Undo::RemoveFile { path: path.clone() }
+            }
+            Action::CreateSymlink { path, target } => {
+                system.create_symlink(path, target)?;
+                Undo::RemoveFile { path: path.clone() }
+            }
+            Action::OpenUrl { url } => {
+                system.open_url(url);
+                Undo::Nothing
+            }
+        })
+    }
+
+    /// What the action does, for logging
+    pub fn describe(&self) -> String {
+        match self {
+            Action::CreateDir { path } => format!("Create directory `{}`", path),
+            Action::WriteFile { path, contents } => {
+                format!("Write `{}` ({} bytes)", path, contents.len())
+            }
+            Action::CreateKey { hive, path } | Action::CreateSharedKey { hive, path } => {
+                format!("Create registry key `{}`", join(hive.name(), path))
+            }
+            Action::SetValue {
+                hive,
+                path,
+                name,
+                value,
+            } => format!(
+                "Set registry value `{}` of `{}` to {}",
+                name,
+                join(hive.name(), path),
+                value
+            ),
+            Action::CreateShortcut { path, target, .. } => {
+                format!("Create shortcut `{}` to `{}`", path, target)
+            }
+            Action::CreateSymlink { path, target } => {
+                format!("Create symlink `{}` to `{}`", path, target)
+            }
+            Action::OpenUrl { url } => format!("Open `{}`", url),
+        }
+    }
+
+    /// Describes the action along with what it would replace, without changing anything
+    pub fn preview(&self, system: &dyn System) -> String {
+        let description = self.describe();
+        let registry = system.registry();
+        match self {
+            Action::CreateDir { path } if system.path_exists(path) => {
+                format!("{} (already exists)", description)
+            }
+            Action::CreateKey { hive, path } | Action::CreateSharedKey { hive, path }
+                if registry.key_exists(*hive, path) =>
+            {
+                format!("{} (already exists)", description)
+            }
+            Action::WriteFile { path, .. } if system.path_exists(path) => {
+                match system.read_file(path) {
+                    Ok(old) => format!("{} (replacing {} bytes)", description, old.len()),
+                    Err(_) => format!("{} (replacing what is there)", description),
+                }
+            }
+            Action::CreateShortcut { path, .. } | Action::CreateSymlink { path, .. }
+                if system.path_exists(path) =>
+            {
+                format!("{} (replacing what is there)", description)
+            }
+            Action::SetValue {
+                hive, path, name, ..
+            } => match registry.get_value(*hive, path, name) {
+                Ok(old) => format!("{} (was {})", description, old),
+                Err(_) => format!("{} (not set before)", description),
+            },
+            _ => description,
+        }
+    }
+}
+
+/// Takes back an applied action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Undo {
+    Nothing,
+    RemoveFile {
+        path: String,
+    },
+    RestoreFile {
+        path: String,
+        contents: Vec<u8>,
+    },
+    RemoveDir {
+        path: String,
+    },
+    DeleteKey {
+        hive: Hive,
+        path: String,
+    },
+    /// Deletes a key unless there are values left in it or any of its subkeys
+    DeleteKeyIfEmpty {
+        hive: Hive,
+        path: String,
+    },
+    /// Puts back the previous value, or deletes the value if there wasn't one
+    RestoreValue {
+        hive: Hive,
+        path: String,
+        name: String,
+        previous: Option<RegValue>,
+    },
+}
+
+impl Undo {
+    pub fn apply(&self, system: &dyn System) -> Result<(), WedgeError> {
+        match self {
+            Undo::Nothing => Ok(()),
+            Undo::RemoveFile { path } => system.remove_file(path),
+            Undo::RestoreFile { path, contents } => system.write_file(path, contents),
+            Undo::RemoveDir { path } => system.remove_dir_all(path),
+            Undo::DeleteKey { hive, path } => {
+                Key::predef(system.registry(), *hive).delete_subkey_all(path)
+            }
+            Undo::DeleteKeyIfEmpty { hive, path } => {
+                let hive = Key::predef(system.registry(), *hive);
+                if is_empty(&hive.open_subkey(path)?)? {
+                    hive.delete_subkey_all(path)
+                } else {
+                    Ok(())
+                }
+            }
+            Undo::RestoreValue {
+                hive,
+                path,
+                name,
+                previous,
+            } => {
+                let key = Key::predef(system.registry(), *hive).open_subkey(path)?;
+                match previous {
+                    Some(previous) => key.set_value(name, previous.clone()),
+                    None => key.delete_value(name),
+                }
+            }
+        }
+    }
+
+    /// What undoing does, for logging
+    pub fn describe(&self) -> String {
+        match self {
+            Undo::Nothing => String::from("Nothing to undo"),
+            Undo::RemoveFile { path } => format!("Remove `{}`", path),
+            Undo::RestoreFile { path, .. } => format!("Restore `{}`", path),
+            Undo::RemoveDir { path } => format!("Remove directory `{}`", path),
+            Undo::DeleteKey { hive, path } => {
+                format!("Delete registry key `{}`", join(hive.name(), path))
+            }
+            Undo::DeleteKeyIfEmpty { hive, path } => format!(
+                "Delete registry key `{}` if it is empty",
+                join(hive.name(), path)
+            ),
+            Undo::RestoreValue {
+                hive,
+                path,
+                name,
+                previous: Some(previous),
+            } => format!(
+                "Restore registry value `{}` of `{}` to {}",
+                name,
+                join(hive.name(), path),
+                previous
+            ),
+            Undo::RestoreValue {
+                hive, path, name, ..
+            } => format!(
+                "Delete registry value `{}` of `{}`",
+                name,
+                join(hive.name(), path)
+            ),
+        }
+    }
+
+    /// Describes undoing along with the current value it would replace, or `None` when there is
+    /// nothing left to undo. Changes nothing.
+    pub fn preview(&self, system: &dyn System) -> Option<String> {
+        let registry = system.registry();
+        match self {
+            Undo::Nothing => None,
+            Undo::RemoveFile { path }
+            | Undo::RestoreFile { path, .. }
+            | Undo::RemoveDir { path }
+                if !system.path_exists(path) =>
+            {
+                None
+            }
+            Undo::DeleteKey { hive, path }
+            | Undo::DeleteKeyIfEmpty { hive, path }
+            | Undo::RestoreValue { hive, path, .. }
+                if !registry.key_exists(*hive, path) =>
+            {
+                None
+            }
+            Undo::RestoreValue {
+                hive,
+                path,
+                name,
+                previous,
+            } => match (registry.get_value(*hive, path, name), previous) {
+                (Ok(current), _) => Some(format!("{} (currently {})", self.describe(), current)),
+                (Err(_), Some(_)) => Some(format!("{} (currently not set)", self.describe())),
+                (Err(_), None) => None,
+            },
+            _ => Some(self.describe()),
+        }
+    }
+}
+
+impl Undo {
+    /// Entry of the undo in an install manifest. Files wedge writes are its own, so the manifest
+    /// has them removed rather than keeping their previous contents around.
+    fn to_json(&self) -> Option<Value> {
+        Some(match self {
+            Undo::Nothing => return None,
+            Undo::RemoveFile { path } | Undo::RestoreFile { path, .. } => {
+                json!({ "kind": "remove-file", "path": path })
+            }
+            Undo::RemoveDir { path } => json!({ "kind": "remove-dir", "path": path }),
+            Undo::DeleteKey { hive, path } => {
+                json!({ "kind": "delete-key", "hive": hive.name(), "path": path })
+            }
+            Undo::DeleteKeyIfEmpty { hive, path } => {
+                json!({ "kind": "delete-key-if-empty", "hive": hive.name(), "path": path })
+            }
+            Undo::RestoreValue {
+                hive,
+                path,
+                name,
+                previous,
+            } => json!({
+                "kind": "restore-value",
+                "hive": hive.name(),
+                "path": path,
+                "name": name,
+                "previous": match previous {
+                    Some(RegValue::String(value)) => json!({ "string": value }),
+                    Some(RegValue::Dword(value)) => json!({ "dword": value }),
+                    Some(RegValue::Raw { kind, bytes }) => {
+                        json!({ "type": kind, "bytes": hex_bytes(bytes) })
+                    }
+                    None => Value::Null,
+                },
+            }),
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Undo> {
+        let string = |name: &str| value.get(name)?.as_str().map(String::from);
+        let hive = || Hive::from_name(value.get("hive")?.as_str()?);
+        Some(match value.get("kind")?.as_str()? {
+            "remove-file" => Undo::RemoveFile {
+                path: string("path")?,
+            },
+            "remove-dir" => Undo::RemoveDir {
+                path: string("path")?,
+            },
+            "delete-key" => Undo::DeleteKey {
+                hive: hive()?,
+                path: string("path")?,
+            },
+            "delete-key-if-empty" => Undo::DeleteKeyIfEmpty {
+                hive: hive()?,
+                path: string("path")?,
+            },
+            "restore-value" => Undo::RestoreValue {
+                hive: hive()?,
+                path: string("path")?,
+                name: string("name")?,
+                previous: match value.get("previous")? {
+                    Value::Null => None,
+                    previous => Some(if let Some(value) = previous.get("string") {
+                        RegValue::from(value.as_str()?)
+                    } else if let Some(value) = previous.get("dword") {
+                        RegValue::Dword(value.as_u64()? as u32)
+                    } else {
+                        RegValue::Raw {
+                            kind: previous.get("type")?.as_u64()? as u32,
+                            bytes: previous
+                                .get("bytes")?
+                                .as_str()?
+                                .split(',')
+                                .filter(|byte| !byte.is_empty())
+                                .map(|byte| u8::from_str_radix(byte, 16).ok())
+                                .collect::<Option<Vec<u8>>>()?,
+                        }
+                    }),
+                },
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// Whether a key has no values, and neither do any of its subkeys
+fn is_empty(key: &Key) -> Result<bool, WedgeError> {
+    if !key.enum_values()?.is_empty() {
+        return Ok(false);
+    }
+    for name in key.enum_keys()? {
+        if !is_empty(&key.open_subkey(&name)?)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// A file an install wrote, so it can be checked for changes later
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileRecord {
+    pub path: String,
+    pub size: u64,
+    /// 64 bit FNV-1a hash of the contents
+    pub hash: u64,
+}
+
+impl FileRecord {
+    pub fn new(path: &str, contents: &[u8]) -> FileRecord {
+        let hash = contents
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+                (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+            });
+        FileRecord {
+            path: String::from(path),
+            size: contents.len() as u64,
+            hash,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({ "path": self.path, "size": self.size, "fnv1a": format!("{:016x}", self.hash) })
+    }
+
+    fn from_json(value: &Value) -> Option<FileRecord> {
+        Some(FileRecord {
+            path: String::from(value.get("path")?.as_str()?),
+            size: value.get("size")?.as_u64()?,
+            hash: u64::from_str_radix(value.get("fnv1a")?.as_str()?, 16).ok()?,
+        })
+    }
+}
+
+/// Record of everything an install changed, kept on disk so uninstalling can undo exactly that
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// How to undo each change, oldest first
+    pub undos: Vec<Undo>,
+    /// Files written by the latest install
+    pub files: Vec<FileRecord>,
+}
+
+impl Manifest {
+    pub fn load(system: &dyn System, path: &str) -> Result<Manifest, WedgeError> {
+        let contents = system.read_file(path)?;
+        Manifest::from_json(&String::from_utf8_lossy(&contents)).map_err(|message| {
+            WedgeError::filesystem(path)(Error::new(ErrorKind::InvalidData, message))
+        })
+    }
+
+    pub fn from_json(text: &str) -> Result<Manifest, String> {
+        let json: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let undos = json
+            .get("undo")
+            .and_then(Value::as_array)
+            .ok_or("Manifest has no undo list")?;
+        // Manifests from before files were recorded have none
+        let files = match json.get("files") {
+            Some(files) => files
+                .as_array()
+                .ok_or("Manifest files aren't a list")?
+                .clone(),
+            None => vec![],
+        };
+        Ok(Manifest {
+            undos: undos
+                .iter()
+                .map(|undo| Undo::from_json(undo).ok_or(format!("Invalid manifest entry {}", undo)))
+                .collect::<Result<_, _>>()?,
+            files: files
+                .iter()
+                .map(|file| {
+                    FileRecord::from_json(file).ok_or(format!("Invalid manifest file {}", file))
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        let undos: Vec<Value> = self.undos.iter().filter_map(Undo::to_json).collect();
+        let files: Vec<Value> = self.files.iter().map(FileRecord::to_json).collect();
+        serde_json::to_string_pretty(&json!({ "version": 1, "undo": undos, "files": files }))
+            .unwrap()
+    }
+
+    /// What undoing would do, newest first, without changing anything
+    pub fn preview(&self, system: &dyn System) -> Vec<String> {
+        self.undos
+            .iter()
+            .rev()
+            .filter_map(|undo| undo.preview(system))
+            .collect()
+    }
+
+    /// Undoes every change, newest first, passing a log line along for each. Changes that are
+    /// already gone are skipped, and undoing carries on past failures, returning the first.
+    pub fn undo<F: FnMut(&str)>(&self, system: &dyn System, mut log: F) -> Result<(), WedgeError> {
+        let mut result = Ok(());
+        for undo in self.undos.iter().rev() {
+            match undo.apply(system) {
+                Ok(()) => log(&undo.describe()),
+                Err(ref e) if e.is_not_found() => {}
+                Err(e) => {
+                    log(&format!("Failed to undo: {} ({})", undo.describe(), e));
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Applies a plan one action at a time, and takes back everything applied if an action fails
+pub struct Runner<'a> {
+    system: &'a dyn System,
+    actions: Vec<Action>,
+    /// How to undo each action applied so far
+    undos: Vec<Undo>,
+    /// Where the manifest is written once every action is applied
+    manifest: Option<String>,
+    /// What earlier installs changed, for when they didn't keep a manifest
+    previous: Manifest,
+}
+
+impl<'a> Runner<'a> {
+    pub fn new(system: &'a dyn System, actions: Vec<Action>) -> Runner<'a> {
+        Runner {
+            system,
+            actions,
+            undos: vec![],
+            manifest: None,
+            previous: Manifest::default(),
+        }
+    }
+
+    /// Writes a manifest of the changes to the path as a last step. An existing manifest is
+    /// added to so upgrading keeps what earlier installs changed, otherwise the changes are
+    /// added to `previous`.
+    pub fn with_manifest(self, path: &str, previous: Manifest) -> Runner<'a> {
+        Runner {
+            manifest: Some(String::from(path)),
+            previous,
+            ..self
+        }
+    }
+
+    /// Number of steps, counting writing the manifest
+    pub fn step_count(&self) -> usize { self.actions.len() + self.manifest.iter().count() }
+
+    /// Number of actions applied so far
+    pub fn steps_done(&self) -> usize { self.undos.len() }
+
+    /// Applies the next action and returns its description, or `None` once every action is
+    /// applied. Call `rollback` when this fails.
+    pub fn step(&mut self) -> Result<Option<String>, WedgeError> {
+        let step = self.undos.len();
+        if step == self.step_count() {
+            return Ok(None);
+        }
+        let system = self.system;
+        let (description, undo) = self
+            .action(step)
+            .and_then(|action| Ok((action.describe(), action.apply(system)?)))
+            .map_err(|source| WedgeError::Step {
+                step: step + 1,
+                steps: self.step_count(),
+                source: Box::new(source),
+            })?;
+        self.undos.push(undo);
+        Ok(Some(description))
+    }
+
+    /// What the remaining steps would do, without changing anything
+    pub fn preview(&self) -> Vec<String> {
+        (self.steps_done()..self.step_count())
+            .map(|step| {
+                let description = match self.actions.get(step) {
+                    Some(action) => action.preview(self.system),
+                    None => format!(
+                        "Write install manifest `{}`",
+                        self.manifest.as_ref().unwrap()
+                    ),
+                };
+                format!("Step {}/{} {}", step + 1, self.step_count(), description)
+            })
+            .collect()
+    }
+
+    /// Action of a step, where the step after the plan writes the manifest
+    fn action(&self, step: usize) -> Result<Action, WedgeError> {
+        if let Some(action) = self.actions.get(step) {
+            return Ok(action.clone());
+        }
+        let path = self.manifest.clone().unwrap_or_default();
+        let mut manifest = match Manifest::load(self.system, &path) {
+            Ok(manifest) => manifest,
+            Err(ref e) if e.is_not_found() => self.previous.clone(),
+            Err(e) => return Err(e),
+        };
+        let applied = self.manifest();
+        manifest.undos.extend(applied.undos);
+        manifest.undos.push(Undo::RemoveFile { path: path.clone() });
+        for file in applied.files {
+            manifest
+                .files
+                .retain(|f| !f.path.eq_ignore_ascii_case(&file.path));
+            manifest.files.push(file);
+        }
+        Ok(Action::WriteFile {
+            path,
+            contents: manifest.to_json().into_bytes(),
+        })
+    }
+
+    /// Manifest of the actions applied so far
+    pub fn manifest(&self) -> Manifest {
+        let applied = self.actions.iter().take(self.undos.len());
+        Manifest {
+            undos: self
+                .undos
+                .iter()
+                .take(self.actions.len())
+                .filter(|undo| **undo != Undo::Nothing)
+                .cloned()
+                .collect(),
+            files: applied
+                .filter_map(|action| match action {
+                    Action::WriteFile { path, contents } => Some(FileRecord::new(path, contents)),
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Undoes every applied action in reverse order, returning a log line for each. Undoing
+    /// carries on past failures so as much as possible gets cleaned up.
+    pub fn rollback(&mut self) -> Vec<String> {
+        let mut log = vec![];
+        while let Some(undo) = self.undos.pop() {
+            if undo == Undo::Nothing {
+                continue;
+            }
+            log.push(match undo.apply(self.system) {
+                Ok(()) => format!("Rolled back: {}", undo.describe()),
+                Err(e) => format!("Failed to roll back: {} ({})", undo.describe(), e),
+            });
+        }
+        log
+    }
+
+    /// Applies every action, passing log lines along as it goes. Rolls back on failure.
+    pub fn run<F: FnMut(&str)>(&mut self, mut log: F) -> Result<(), WedgeError> {
+        loop {
+            match self.step() {
+                Ok(Some(description)) => log(&format!(
+                    "Step {}/{} {}",
+                    self.steps_done(),
+                    self.step_count(),
+                    description
+                )),
+                Ok(None) => return Ok(()),
+                Err(e) => {
+                    log(&e.to_string());
+                    for line in self.rollback() {
+                        log(&line);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        registry::REG_BINARY,
+        system::{MemoryFile, MemorySystem},
+    };
+
+    fn set_value(path: &str, name: &str, value: &str) -> Action {
+        Action::SetValue {
+            hive: Hive::CurrentUser,
+            path: String::from(path),
+            name: String::from(name),
+            value: RegValue::from(value),
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        let system = MemorySystem::new(&[r"C:", r"C:\Apps"]);
+        let actions = vec![
+            Action::CreateDir {
+                path: String::from(r"C:\Apps\Wedge\bin"),
+            },
+            Action::WriteFile {
+                path: String::from(r"C:\Apps\Wedge\bin\wedge.exe"),
+                contents: b"MZ".to_vec(),
+            },
+            Action::CreateKey {
+                hive: Hive::CurrentUser,
+                path: String::from(r"Software\Wedge"),
+            },
+            set_value(r"Software\Wedge", "SearchEngine", "Google"),
+            Action::OpenUrl {
+                url: String::from("https://example.com/"),
+            },
+        ];
+        let mut log = vec![];
+        Runner::new(&system, actions)
+            .run(|line| log.push(String::from(line)))
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                r"Step 1/5 Create directory `C:\Apps\Wedge\bin`",
+                r"Step 2/5 Write `C:\Apps\Wedge\bin\wedge.exe` (2 bytes)",
+                r"Step 3/5 Create registry key `HKEY_CURRENT_USER\Software\Wedge`",
+                r#"Step 4/5 Set registry value `SearchEngine` of `HKEY_CURRENT_USER\Software\Wedge` to "Google""#,
+                r"Step 5/5 Open `https://example.com/`",
+            ],
+            log
+        );
+        assert_eq!(
+            vec![r"C:", r"C:\Apps", r"C:\Apps\Wedge", r"C:\Apps\Wedge\bin"],
+            system.dump_dirs()
+        );
+        assert_eq!(vec!["https://example.com/"], system.opened_urls());
+    }
+
+    #[test]
+    fn test_preview() {
+        let system = MemorySystem::new(&[r"C:", r"C:\Apps", r"C:\Apps\Wedge"]);
+        system.write_file(r"C:\Apps\Wedge\LICENSE", b"old").unwrap();
+        Key::predef(&system.registry, Hive::CurrentUser)
+            .create_subkey(r"Software\Wedge")
+            .unwrap()
+            .set_value("SearchEngine", "Bing")
+            .unwrap();
+        let files_before = system.dump_files();
+        let values_before = system.registry.dump_values();
+
+        let mut runner = Runner::new(&system, vec![
+            Action::CreateDir {
+                path: String::from(r"C:\Apps\Wedge"),
+            },
+            Action::WriteFile {
+                path: String::from(r"C:\Apps\Wedge\LICENSE"),
+                contents: b"new!".to_vec(),
+            },
+            Action::CreateKey {
+                hive: Hive::CurrentUser,
+                path: String::from(r"Software\Wedge\Rules\1"),
+            },
+            set_value(r"Software\Wedge", "SearchEngine", "Google"),
+            set_value(r"Software\Wedge\Rules\1", "Target", "edge"),
+        ])
+        .with_manifest(r"C:\Apps\Wedge\manifest.json", Manifest::default());
+        assert_eq!(
+            vec![
+                r"Step 1/6 Create directory `C:\Apps\Wedge` (already exists)",
+                r"Step 2/6 Write `C:\Apps\Wedge\LICENSE` (4 bytes) (replacing 3 bytes)",
+                r"Step 3/6 Create registry key `HKEY_CURRENT_USER\Software\Wedge\Rules\1`",
+                r#"Step 4/6 Set registry value `SearchEngine` of `HKEY_CURRENT_USER\Software\Wedge` to "Google" (was "Bing")"#,
+                r#"Step 5/6 Set registry value `Target` of `HKEY_CURRENT_USER\Software\Wedge\Rules\1` to "edge" (not set before)"#,
+                r"Step 6/6 Write install manifest `C:\Apps\Wedge\manifest.json`",
+            ],
+            runner.preview()
+        );
+        assert_eq!(files_before, system.dump_files());
+        assert_eq!(values_before, system.registry.dump_values());
+
+        runner.run(|_| {}).unwrap();
+        let manifest = Manifest::load(&system, r"C:\Apps\Wedge\manifest.json").unwrap();
+        assert_eq!(
+            vec![
+                r"Remove `C:\Apps\Wedge\manifest.json`",
+                r#"Delete registry value `Target` of `HKEY_CURRENT_USER\Software\Wedge\Rules\1` (currently "edge")"#,
+                r#"Restore registry value `SearchEngine` of `HKEY_CURRENT_USER\Software\Wedge` to "Bing" (currently "Google")"#,
+                r"Delete registry key `HKEY_CURRENT_USER\Software\Wedge\Rules`",
+                r"Remove `C:\Apps\Wedge\LICENSE`",
+            ],
+            manifest.preview(&system)
+        );
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut system = MemorySystem::new(&[r"C:", r"C:\Apps", r"C:\Apps\Wedge"]);
+        system.elevated = false;
+        system.write_file(r"C:\Apps\Wedge\LICENSE", b"old").unwrap();
+        let settings = Key::predef(&system.registry, Hive::CurrentUser)
+            .create_subkey(r"Software\Wedge")
+            .unwrap();
+        settings.set_value("SearchEngine", "Bing").unwrap();
+        let keys_before = system.registry.dump_keys();
+        let values_before = system.registry.dump_values();
+
+        let actions = vec![
+            Action::CreateDir {
+                path: String::from(r"C:\Apps\Wedge\bin"),
+            },
+            Action::WriteFile {
+                path: String::from(r"C:\Apps\Wedge\LICENSE"),
+                contents: b"new".to_vec(),
+            },
+            Action::CreateKey {
+                hive: Hive::CurrentUser,
+                path: String::from(r"Software\Wedge\Rules\1"),
+            },
+            set_value(r"Software\Wedge", "SearchEngine", "Google"),
+            set_value(r"Software\Wedge\Rules\1", "Target", "edge"),
+            Action::CreateSymlink {
+                path: String::from(r"C:\Apps\Wedge\proxy.exe"),
+                target: String::from(r"C:\Apps\Edge\msedge.exe"),
+            },
+            Action::OpenUrl {
+                url: String::from("https://example.com/"),
+            },
+        ];
+        let mut runner = Runner::new(&system, actions);
+        let mut log = vec![];
+        let error = runner.run(|line| log.push(String::from(line))).unwrap_err();
+
+        assert_eq!(
+            "Error on step 6/7 Administrator rights are needed to create symlinks. Run wedge as \
+             administrator.",
+            error.to_string()
+        );
+        assert_eq!(
+            vec![
+                r"Rolled back: Delete registry value `Target` of `HKEY_CURRENT_USER\Software\Wedge\Rules\1`",
+                r#"Rolled back: Restore registry value `SearchEngine` of `HKEY_CURRENT_USER\Software\Wedge` to "Bing""#,
+                r"Rolled back: Delete registry key `HKEY_CURRENT_USER\Software\Wedge\Rules`",
+                r"Rolled back: Restore `C:\Apps\Wedge\LICENSE`",
+                r"Rolled back: Remove directory `C:\Apps\Wedge\bin`",
+            ],
+            log[6..].to_vec()
+        );
+        assert_eq!(0, runner.steps_done());
+        assert_eq!(keys_before, system.registry.dump_keys());
+        assert_eq!(values_before, system.registry.dump_values());
+        assert_eq!(
+            vec![r"C:", r"C:\Apps", r"C:\Apps\Wedge"],
+            system.dump_dirs()
+        );
+        assert_eq!(
+            vec![(
+                String::from(r"C:\Apps\Wedge\LICENSE"),
+                MemoryFile::File(b"old".to_vec())
+            )],
+            system.dump_files()
+        );
+        assert!(system.opened_urls().is_empty());
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest = Manifest {
+            undos: vec![
+                Undo::RemoveDir {
+                    path: String::from(r"C:\Apps\Wedge"),
+                },
+                Undo::DeleteKey {
+                    hive: Hive::LocalMachine,
+                    path: String::from(r"Software\Wedge"),
+                },
+                Undo::RestoreValue {
+                    hive: Hive::CurrentUser,
+                    path: String::from(r"Software\Wedge"),
+                    name: String::from("SearchEngine"),
+                    previous: Some(RegValue::from("Bing")),
+                },
+                Undo::RestoreValue {
+                    hive: Hive::CurrentUser,
+                    path: String::from(r"Software\Wedge"),
+                    name: String::from("Count"),
+                    previous: Some(RegValue::Dword(3)),
+                },
+                Undo::RestoreValue {
+                    hive: Hive::CurrentUser,
+                    path: String::from(r"Software\Wedge"),
+                    name: String::from("Data"),
+                    previous: Some(RegValue::Raw {
+                        kind: REG_BINARY,
+                        bytes: vec![0, 0x7f, 0xff],
+                    }),
+                },
+                Undo::RestoreValue {
+                    hive: Hive::CurrentUser,
+                    path: String::from(r"Software\Wedge"),
+                    name: String::from("Empty"),
+                    previous: Some(RegValue::Raw {
+                        kind: 0,
+                        bytes: vec![],
+                    }),
+                },
+                Undo::RestoreValue {
+                    hive: Hive::CurrentUser,
+                    path: String::from(r"Software\Wedge"),
+                    name: String::from("Target"),
+                    previous: None,
+                },
+                Undo::RemoveFile {
+                    path: String::from(r"C:\Apps\Wedge\wedge.exe"),
+                },
+            ],
+            files: vec![FileRecord::new(r"C:\Apps\Wedge\wedge.exe", b"MZ")],
+        };
+        assert_eq!(
+            Ok(manifest.clone()),
+            Manifest::from_json(&manifest.to_json())
+        );
+
+        // Previous file contents aren't kept
+        let manifest = Manifest {
+            undos: vec![Undo::Nothing, Undo::RestoreFile {
+                path: String::from(r"C:\Apps\Wedge\LICENSE"),
+                contents: b"old".to_vec(),
+            }],
+            ..Manifest::default()
+        };
+        assert_eq!(
+            Ok(vec![Undo::RemoveFile {
+                path: String::from(r"C:\Apps\Wedge\LICENSE"),
+            }]),
+            Manifest::from_json(&manifest.to_json()).map(|manifest| manifest.undos)
+        );
+
+        // Files weren't always recorded
+        assert_eq!(
+            Ok(Manifest::default()),
+            Manifest::from_json(r#"{"version": 1, "undo": []}"#)
+        );
+        assert_eq!(0xaf63_dc4c_8601_ec8c, FileRecord::new("a", b"a").hash);
+
+        assert!(Manifest::from_json("{}").is_err());
+        assert!(Manifest::from_json(r#"{"undo": [{"kind": "format-disk"}]}"#).is_err());
+    }
+}
@@ -0,0 +1,508 @@
+use crate::{
+    browser::BrowserInfo,
+    config::{insert_setting, ConfigError, ConfigFile, CONFIG_VERSION},
+    error::WedgeError,
+    invocation::{Action, InvocationClass},
+    registry::{join, Hive, Key, RegValue, Registry},
+    system::System,
+};
+use serde_json::{json, Value};
+
+/// Key group policy is read from, under HKLM for every user and HKCU for a single user
+pub const POLICY_KEY: &str = r"Software\Policies\Wedge";
+
+/// Namespace the policy templates define their policies in
+const NAMESPACE: &str = "MarcGuiselin.Policies.Wedge";
+
+/// Kind of value a setting takes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SettingKind {
+    /// One of a list of names, stored as a string
+    Choice(Vec<&'static str>),
+    /// Free text, stored as a string
+    Text,
+    /// On or off, stored as a DWORD of 1 or 0
+    Flag,
+}
+
+/// Category a policy is listed under in the group policy editor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Wedge,
+    Actions,
+}
+
+impl Category {
+    fn id(self) -> &'static str {
+        match self {
+            Category::Wedge => "Wedge",
+            Category::Actions => "Actions",
+        }
+    }
+}
+
+/// A setting that can be enforced by group policy. Both policy lookup and the policy templates
+/// are generated from these.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setting {
+    /// Name of the policy, which its strings in the templates are named after
+    pub id: String,
+    /// Key of the setting in config files, dotted when nested
+    pub key: String,
+    /// Subkey of the policy key the value is in, if it isn't in the policy key itself
+    pub subkey: Option<&'static str>,
+    /// Name of the registry value
+    pub value_name: String,
+    pub category: Category,
+    pub title: String,
+    /// Text explaining the policy in the group policy editor
+    pub explain: String,
+    pub kind: SettingKind,
+}
+
+impl Setting {
+    /// Path of the key the value is in, relative to the hive
+    pub fn key_path(&self) -> String {
+        match self.subkey {
+            Some(subkey) => join(POLICY_KEY, subkey),
+            None => String::from(POLICY_KEY),
+        }
+    }
+
+    /// Converts a policy value to its JSON value in a config file
+    fn to_json(&self, value: RegValue) -> Result<Value, ConfigError> {
+        match (&self.kind, value) {
+            (SettingKind::Flag, RegValue::Dword(value)) => Ok(Value::Bool(value != 0)),
+            (SettingKind::Choice(_), RegValue::String(value))
+            | (SettingKind::Text, RegValue::String(value)) => Ok(Value::String(value)),
+            (kind, value) => Err(ConfigError {
+                line: 0,
+                column: 0,
+                message: format!(
+                    "expected a {}, found {}",
+                    if *kind == SettingKind::Flag {
+                        "DWORD"
+                    } else {
+                        "string"
+                    },
+                    value
+                ),
+            }),
+        }
+    }
+}
+
+/// Every setting that can be enforced by group policy
+pub fn settings() -> Vec<Setting> {
+    let mut browsers = vec!["default"];
+    browsers.extend(BrowserInfo::all().iter().map(|info| info.name));
+
+    let mut settings = vec![
+        Setting {
+            id: String::from("Browser"),
+            key: String::from("browser"),
+            subkey: None,
+            value_name: String::from("Browser"),
+            category: Category::Wedge,
+            title: String::from("Browser deflected links open in"),
+            explain: String::from(
+                "Sets the browser deflected links open in when no rule picks one, which is the \
+                 default browser unless set.",
+            ),
+            kind: SettingKind::Choice(browsers),
+        },
+        Setting {
+            id: String::from("SearchEngine"),
+            key: String::from("search-engine"),
+            subkey: None,
+            value_name: String::from("SearchEngine"),
+            category: Category::Wedge,
+            title: String::from("Search engine Bing searches are sent to"),
+            explain: String::from(
+                "Rewrites Bing searches to another search engine: google, duckduckgo, startpage, \
+                 kagi or a url containing {searchTerms}.",
+            ),
+            kind: SettingKind::Text,
+        },
+        Setting {
+            id: String::from("Logging"),
+            key: String::from("logging"),
+            subkey: None,
+            value_name: String::from("Logging"),
+            category: Category::Wedge,
+            title: String::from("Log failed launches"),
+            explain: String::from(
+                "Logs links Wedge fails to open to wedge.log in the temp folder. Logging is on \
+                 unless this policy is disabled.",
+            ),
+            kind: SettingKind::Flag,
+        },
+    ];
+
+    for class in &InvocationClass::ALL {
+        settings.push(Setting {
+            id: format!("Action_{}", class.name().replace('-', "_")),
+            key: format!("actions.{}", class.name()),
+            subkey: Some("Actions"),
+            value_name: String::from(class.name()),
+            category: Category::Actions,
+            title: format!("Action for {} launches", class.name()),
+            explain: format!(
+                "Sets what Wedge does when Edge is launched as {}, which is {} by default. \
+                 Deflect opens any links in another browser, pass-through lets Edge start as \
+                 usual and block starts nothing.",
+                class.name(),
+                class.default_action().name()
+            ),
+            kind: SettingKind::Choice(Action::ALL.iter().map(|action| action.name()).collect()),
+        });
+    }
+    settings
+}
+
+/// Reads the settings group policy enforces, machine policy going over user policy. Values that
+/// aren't valid are skipped, passing why to `warn`.
+pub fn load_policies(registry: &dyn Registry, warn: &mut dyn FnMut(&str)) -> ConfigFile {
+    let mut json = json!({ "version": CONFIG_VERSION });
+    for hive in &[Hive::CurrentUser, Hive::LocalMachine] {
+        let root = Key::predef(registry, *hive);
+        for setting in settings() {
+            let value = match root
+                .open_subkey(&setting.key_path())
+                .and_then(|key| key.get_value(&setting.value_name))
+            {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+
+            // Each value is checked on its own so a bad one doesn't take the others with it
+            let checked = setting.to_json(value).and_then(|value| {
+                let mut file = json!({ "version": CONFIG_VERSION });
+                insert_setting(&mut file, &setting.key, value.clone())?;
+                serde_json::from_value::<ConfigFile>(file)?;
+                insert_setting(&mut json, &setting.key, value)
+            });
+            if let Err(e) = checked {
+                warn(&format!(
+                    "Ignoring policy {}: {}",
+                    join(&join(hive.name(), &setting.key_path()), &setting.value_name),
+                    e
+                ));
+            }
+        }
+    }
+    serde_json::from_value(json).unwrap_or_default()
+}
+
+/// Renders the ADMX template defining a policy for every setting
+pub fn admx() -> String {
+    let mut lines = vec![
+        String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#),
+        String::from(
+            r#"<policyDefinitions xmlns:xsd="http://www.w3.org/2001/XMLSchema" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" revision="1.0" schemaVersion="1.0" xmlns="http://schemas.microsoft.com/GroupPolicy/2006/07/PolicyDefinitions">"#,
+        ),
+        String::from("  <policyNamespaces>"),
+        format!(r#"    <target prefix="wedge" namespace="{}" />"#, NAMESPACE),
+        String::from("  </policyNamespaces>"),
+        String::from(r#"  <resources minRequiredRevision="1.0" />"#),
+        String::from("  <supportedOn>"),
+        String::from("    <definitions>"),
+        String::from(
+            r#"      <definition name="SUPPORTED_Wedge" displayName="$(string.SUPPORTED_Wedge)" />"#,
+        ),
+        String::from("    </definitions>"),
+        String::from("  </supportedOn>"),
+        String::from("  <categories>"),
+        String::from(r#"    <category name="Wedge" displayName="$(string.Wedge)" />"#),
+        String::from(r#"    <category name="Actions" displayName="$(string.Actions)">"#),
+        String::from(r#"      <parentCategory ref="Wedge" />"#),
+        String::from("    </category>"),
+        String::from("  </categories>"),
+        String::from("  <policies>"),
+    ];
+
+    for setting in settings() {
+        let id = &setting.id;
+        let mut policy = format!(
+            r#"    <policy name="{0}" class="Both" displayName="$(string.{0})" explainText="$(string.{0}_Explain)" key="{1}""#,
+            id,
+            xml_escape(&setting.key_path())
+        );
+        if setting.kind == SettingKind::Flag {
+            policy.push_str(&format!(
+                r#" valueName="{}">"#,
+                xml_escape(&setting.value_name)
+            ));
+        } else {
+            policy.push_str(&format!(r#" presentation="$(presentation.{})">"#, id));
+        }
+        lines.push(policy);
+        lines.push(format!(
+            r#"      <parentCategory ref="{}" />"#,
+            setting.category.id()
+        ));
+        lines.push(String::from(
+            r#"      <supportedOn ref="SUPPORTED_Wedge" />"#,
+        ));
+
+        match &setting.kind {
+            SettingKind::Flag => {
+                lines.push(String::from(
+                    r#"      <enabledValue><decimal value="1" /></enabledValue>"#,
+                ));
+                lines.push(String::from(
+                    r#"      <disabledValue><decimal value="0" /></disabledValue>"#,
+                ));
+            }
+            SettingKind::Text => {
+                lines.push(String::from("      <elements>"));
+                lines.push(format!(
+                    r#"        <text id="{}" valueName="{}" required="true" />"#,
+                    id,
+                    xml_escape(&setting.value_name)
+                ));
+                lines.push(String::from("      </elements>"));
+            }
+            SettingKind::Choice(choices) => {
+                lines.push(String::from("      <elements>"));
+                lines.push(format!(
+                    r#"        <enum id="{}" valueName="{}" required="true">"#,
+                    id,
+                    xml_escape(&setting.value_name)
+                ));
+                for choice in choices {
+                    lines.push(format!(
+                        r#"          <item displayName="$(string.{})"><value><string>{}</string></value></item>"#,
+                        choice_id(id, choice),
+                        xml_escape(choice)
+                    ));
+                }
+                lines.push(String::from("        </enum>"));
+                lines.push(String::from("      </elements>"));
+            }
+        }
+        lines.push(String::from("    </policy>"));
+    }
+
+    lines.push(String::from("  </policies>"));
+    lines.push(String::from("</policyDefinitions>"));
+    lines.push(String::new());
+    lines.join("\r\n")
+}
+
+/// Renders the English ADML template holding the strings of the ADMX template
+pub fn adml() -> String {
+    let string = |id: &str, text: &str| {
+        format!(r#"      <string id="{}">{}</string>"#, id, xml_escape(text))
+    };
+    let mut strings = vec![
+        string("Wedge", "Wedge"),
+        string("Actions", "Launch actions"),
+        string("SUPPORTED_Wedge", "Wedge with group policy support"),
+    ];
+    let mut presentations = vec![];
+    for setting in settings() {
+        let id = &setting.id;
+        strings.push(string(id, &setting.title));
+        strings.push(string(
+            &format!("{}_Explain", id),
+            &format!(
+                "{} This policy goes over the user's own settings and config files.",
+                setting.explain
+            ),
+        ));
+        match &setting.kind {
+            SettingKind::Flag => continue,
+            SettingKind::Text => presentations.push(format!(
+                r#"      <presentation id="{0}"><textBox refId="{0}"><label>{1}</label></textBox></presentation>"#,
+                id,
+                xml_escape(&setting.title)
+            )),
+            SettingKind::Choice(choices) => {
+                for choice in choices {
+                    strings.push(string(&choice_id(id, choice), choice));
+                }
+                presentations.push(format!(
+                    r#"      <presentation id="{0}"><dropdownList refId="{0}" noSort="true">{1}</dropdownList></presentation>"#,
+                    id,
+                    xml_escape(&setting.title)
+                ));
+            }
+        }
+    }
+
+    let mut lines = vec![
+        String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#),
+        String::from(
+            r#"<policyDefinitionResources xmlns:xsd="http://www.w3.org/2001/XMLSchema" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" revision="1.0" schemaVersion="1.0" xmlns="http://schemas.microsoft.com/GroupPolicy/2006/07/PolicyDefinitions">"#,
+        ),
+        String::from("  <displayName>Wedge</displayName>"),
+        String::from("  <description>Settings for Wedge, the Edge deflector</description>"),
+        String::from("  <resources>"),
+        String::from("    <stringTable>"),
+    ];
+    lines.extend(strings);
+    lines.push(String::from("    </stringTable>"));
+    lines.push(String::from("    <presentationTable>"));
+    lines.extend(presentations);
+    lines.push(String::from("    </presentationTable>"));
+    lines.push(String::from("  </resources>"));
+    lines.push(String::from("</policyDefinitionResources>"));
+    lines.push(String::new());
+    lines.join("\r\n")
+}
+
+/// Id of the string naming one of the choices of a policy
+fn choice_id(id: &str, choice: &str) -> String { format!("{}_{}", id, choice.replace('-', "_")) }
+
+/// Writes the ADMX template and its English ADML to a folder laid out like
+/// `C:\Windows\PolicyDefinitions`, creating the folder when its parent exists. Returns the paths
+/// written.
+pub fn write_templates(system: &dyn System, dir: &str) -> Result<Vec<String>, WedgeError> {
+    let dir = dir.trim_end_matches('\\');
+    let language_dir = format!(r"{}\en-US", dir);
+    for dir in &[dir, &language_dir] {
+        if !system.path_exists(dir) {
+            system.create_dir(dir)?;
+        }
+    }
+    let files = vec![
+        (format!(r"{}\wedge.admx", dir), admx()),
+        (format!(r"{}\wedge.adml", language_dir), adml()),
+    ];
+    let mut written = vec![];
+    for (path, contents) in files {
+        system.write_file(&path, contents.as_bytes())?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Escapes text for XML attributes and elements
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        browser::Browser,
+        export::tests::check_golden,
+        registry::MemoryRegistry,
+        rules::Target,
+        search::SearchEngine,
+        system::{MemoryFile, MemorySystem},
+    };
+
+    fn policy_key(registry: &MemoryRegistry, hive: Hive) -> Key<'_> {
+        Key::predef(registry, hive)
+            .create_subkey(POLICY_KEY)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_load_policies() {
+        let registry = MemoryRegistry::new();
+        let mut warnings = vec![];
+        assert_eq!(
+            ConfigFile {
+                version: CONFIG_VERSION,
+                ..ConfigFile::default()
+            },
+            load_policies(&registry, &mut |line| warnings.push(String::from(line)))
+        );
+
+        let user = policy_key(&registry, Hive::CurrentUser);
+        user.set_value("Browser", "firefox").unwrap();
+        user.set_value("SearchEngine", "DuckDuckGo").unwrap();
+        user.set_value("Logging", 0u32).unwrap();
+        let user_actions = user.create_subkey("Actions").unwrap();
+        user_actions.set_value("notification", "block").unwrap();
+        user_actions.set_value("web-app", "nothing").unwrap();
+        let machine = policy_key(&registry, Hive::LocalMachine);
+        machine.set_value("Browser", "chrome").unwrap();
+        machine.set_value("SearchEngine", 1u32).unwrap();
+        machine
+            .create_subkey("Actions")
+            .unwrap()
+            .set_value("updater", "block")
+            .unwrap();
+
+        // Machine policy goes over user policy, and bad values are skipped
+        let file = load_policies(&registry, &mut |line| warnings.push(String::from(line)));
+        assert_eq!(Some(Target::Browser(Browser::Chrome)), file.browser);
+        assert_eq!(Some(SearchEngine::DuckDuckGo), file.search_engine);
+        assert_eq!(Some(false), file.logging);
+        assert_eq!(
+            vec![
+                (InvocationClass::Updater, Action::Block),
+                (InvocationClass::Notification, Action::Block)
+            ],
+            file.actions.into_iter().collect::<Vec<_>>()
+        );
+        assert_eq!(None, file.rules);
+        assert_eq!(
+            vec![
+                String::from(
+                    r"Ignoring policy HKEY_CURRENT_USER\Software\Policies\Wedge\Actions\web-app: unknown action `nothing`"
+                ),
+                String::from(
+                    r"Ignoring policy HKEY_LOCAL_MACHINE\Software\Policies\Wedge\SearchEngine: expected a string, found dword:1"
+                ),
+            ],
+            warnings
+        );
+    }
+
+    #[test]
+    fn test_templates() {
+        let admx = admx();
+        let adml = adml();
+        check_golden("wedge.admx", &admx);
+        check_golden("wedge.adml", &adml);
+
+        // Every string and presentation the ADMX uses is defined by the ADML
+        for (prefix, defined) in &[
+            ("$(string.", "<string id=\""),
+            ("$(presentation.", "<presentation id=\""),
+        ] {
+            for reference in admx.split(prefix).skip(1) {
+                let id = &reference[..reference.find(')').unwrap()];
+                assert!(adml.contains(&format!("{}{}\"", defined, id)), "{}", id);
+            }
+        }
+        assert_eq!(12, admx.matches("<policy ").count());
+    }
+    #[test]
+    fn test_write_templates() {
+        let system = MemorySystem::new(&[r"C:\Build"]);
+        assert_eq!(
+            vec![
+                String::from(r"C:\Build\policy\wedge.admx"),
+                String::from(r"C:\Build\policy\en-US\wedge.adml"),
+            ],
+            write_templates(&system, r"C:\Build\policy\").unwrap()
+        );
+        assert_eq!(
+            vec![
+                (
+                    String::from(r"C:\Build\policy\en-US\wedge.adml"),
+                    MemoryFile::File(adml().into_bytes())
+                ),
+                (
+                    String::from(r"C:\Build\policy\wedge.admx"),
+                    MemoryFile::File(admx().into_bytes())
+                ),
+            ],
+            system.dump_files()
+        );
+
+        // Writing again replaces the templates
+        assert!(write_templates(&system, r"C:\Build\policy").is_ok());
+        assert!(write_templates(&system, r"C:\Missing\policy").is_err());
+    }
+}
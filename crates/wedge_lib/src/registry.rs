@@ -0,0 +1,626 @@
+use crate::error::WedgeError;
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt,
+    io::{Error, ErrorKind},
+};
+
+/// Root registry keys wedge reads and writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Hive {
+    ClassesRoot,
+    CurrentUser,
+    LocalMachine,
+}
+
+impl Hive {
+    /// Full name of the hive as shown by regedit
+    pub fn name(self) -> &'static str {
+        match self {
+            Hive::ClassesRoot => "HKEY_CLASSES_ROOT",
+            Hive::CurrentUser => "HKEY_CURRENT_USER",
+            Hive::LocalMachine => "HKEY_LOCAL_MACHINE",
+        }
+    }
+
+    /// Parses a hive from its full or short name
+    pub fn from_name(name: &str) -> Option<Hive> {
+        Some(match name.to_uppercase().as_str() {
+            "HKEY_CLASSES_ROOT" | "HKCR" => Hive::ClassesRoot,
+            "HKEY_CURRENT_USER" | "HKCU" => Hive::CurrentUser,
+            "HKEY_LOCAL_MACHINE" | "HKLM" => Hive::LocalMachine,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for Hive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str(self.name()) }
+}
+
+/// Typed registry value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegValue {
+    /// REG_SZ
+    String(String),
+    /// REG_DWORD
+    Dword(u32),
+    /// Any other type, like REG_BINARY or REG_QWORD, as its type number and raw bytes
+    Raw { kind: u32, bytes: Vec<u8> },
+}
+
+/// Type number of REG_BINARY values
+pub const REG_BINARY: u32 = 3;
+
+impl From<&str> for RegValue {
+    fn from(value: &str) -> RegValue { RegValue::String(String::from(value)) }
+}
+
+impl From<String> for RegValue {
+    fn from(value: String) -> RegValue { RegValue::String(value) }
+}
+
+impl From<&String> for RegValue {
+    fn from(value: &String) -> RegValue { RegValue::String(value.clone()) }
+}
+
+impl From<u32> for RegValue {
+    fn from(value: u32) -> RegValue { RegValue::Dword(value) }
+}
+
+impl fmt::Display for RegValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegValue::String(value) => write!(f, "{:?}", value),
+            RegValue::Dword(value) => write!(f, "dword:{}", value),
+            RegValue::Raw { kind, bytes } => {
+                if *kind == REG_BINARY {
+                    f.write_str("hex:")?;
+                } else {
+                    write!(f, "hex({:x}):", kind)?;
+                }
+                f.write_str(&hex_bytes(bytes))
+            }
+        }
+    }
+}
+
+/// Bytes as comma separated hex pairs, like in `.reg` files
+pub fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Access to a windows style registry. Paths are relative to a hive, separated by `\` and
+/// case-insensitive. Value name `""` is the key's default value.
+pub trait Registry {
+    /// Does the key exist?
+    fn key_exists(&self, hive: Hive, path: &str) -> bool;
+
+    /// Creates a key along with any missing parent keys
+    fn create_subkey(&self, hive: Hive, path: &str) -> Result<(), Error>;
+
+    /// Deletes a key and all of its subkeys
+    fn delete_subkey_all(&self, hive: Hive, path: &str) -> Result<(), Error>;
+
+    /// Names of a key's direct subkeys
+    fn enum_keys(&self, hive: Hive, path: &str) -> Result<Vec<String>, Error>;
+
+    /// Names and values of a key's values
+    fn enum_values(&self, hive: Hive, path: &str) -> Result<Vec<(String, RegValue)>, Error>;
+
+    fn get_value(&self, hive: Hive, path: &str, name: &str) -> Result<RegValue, Error>;
+
+    fn set_value(&self, hive: Hive, path: &str, name: &str, value: &RegValue) -> Result<(), Error>;
+
+    fn delete_value(&self, hive: Hive, path: &str, name: &str) -> Result<(), Error>;
+}
+
+/// Joins two registry paths
+pub fn join(path: &str, subpath: &str) -> String {
+    if path.is_empty() {
+        String::from(subpath)
+    } else if subpath.is_empty() {
+        String::from(path)
+    } else {
+        format!(r"{}\{}", path, subpath)
+    }
+}
+
+/// Handle to a registry key, used much like winreg's `RegKey`
+#[derive(Clone)]
+pub struct Key<'a> {
+    registry: &'a dyn Registry,
+    hive: Hive,
+    path: String,
+}
+
+impl<'a> Key<'a> {
+    /// Opens one of the root keys
+    pub fn predef(registry: &'a dyn Registry, hive: Hive) -> Key<'a> {
+        Key {
+            registry,
+            hive,
+            path: String::new(),
+        }
+    }
+
+    pub fn hive(&self) -> Hive { self.hive }
+
+    pub fn path(&self) -> &str { &self.path }
+
+    fn subkey(&self, path: &str) -> Key<'a> {
+        Key {
+            registry: self.registry,
+            hive: self.hive,
+            path: join(&self.path, path),
+        }
+    }
+
+    pub fn open_subkey(&self, path: &str) -> Result<Key<'a>, WedgeError> {
+        let key = self.subkey(path);
+        if self.registry.key_exists(key.hive, &key.path) {
+            Ok(key)
+        } else {
+            Err(key.error(None)(not_found()))
+        }
+    }
+
+    pub fn create_subkey(&self, path: &str) -> Result<Key<'a>, WedgeError> {
+        let key = self.subkey(path);
+        self.registry
+            .create_subkey(key.hive, &key.path)
+            .map_err(key.error(None))?;
+        Ok(key)
+    }
+
+    pub fn delete_subkey_all(&self, path: &str) -> Result<(), WedgeError> {
+        let key = self.subkey(path);
+        self.registry
+            .delete_subkey_all(key.hive, &key.path)
+            .map_err(key.error(None))
+    }
+
+    pub fn enum_keys(&self) -> Result<Vec<String>, WedgeError> {
+        self.registry
+            .enum_keys(self.hive, &self.path)
+            .map_err(self.error(None))
+    }
+
+    pub fn enum_values(&self) -> Result<Vec<(String, RegValue)>, WedgeError> {
+        self.registry
+            .enum_values(self.hive, &self.path)
+            .map_err(self.error(None))
+    }
+
+    pub fn get_value(&self, name: &str) -> Result<RegValue, WedgeError> {
+        self.registry
+            .get_value(self.hive, &self.path, name)
+            .map_err(self.error(Some(name)))
+    }
+
+    /// Gets a REG_SZ value
+    pub fn get_string(&self, name: &str) -> Result<String, WedgeError> {
+        match self.get_value(name)? {
+            RegValue::String(value) => Ok(value),
+            _ => Err(self.error(Some(name))(Error::new(
+                ErrorKind::InvalidData,
+                "Value is not a string",
+            ))),
+        }
+    }
+
+    /// Gets a REG_DWORD value
+    pub fn get_u32(&self, name: &str) -> Result<u32, WedgeError> {
+        match self.get_value(name)? {
+            RegValue::Dword(value) => Ok(value),
+            _ => Err(self.error(Some(name))(Error::new(
+                ErrorKind::InvalidData,
+                "Value is not a dword",
+            ))),
+        }
+    }
+
+    pub fn set_value<V: Into<RegValue>>(&self, name: &str, value: V) -> Result<(), WedgeError> {
+        self.registry
+            .set_value(self.hive, &self.path, name, &value.into())
+            .map_err(self.error(Some(name)))
+    }
+
+    pub fn delete_value(&self, name: &str) -> Result<(), WedgeError> {
+        self.registry
+            .delete_value(self.hive, &self.path, name)
+            .map_err(self.error(Some(name)))
+    }
+
+    /// Wraps backend errors with this key's path and the value involved, for use with
+    /// `map_err`
+    fn error(&self, value: Option<&str>) -> impl FnOnce(Error) -> WedgeError {
+        let key = join(self.hive.name(), &self.path);
+        let value = value.map(String::from);
+        move |source| WedgeError::Registry { key, value, source }
+    }
+}
+
+/// Missing keys and values, worded like windows does
+fn not_found() -> Error {
+    Error::new(
+        ErrorKind::NotFound,
+        "The system cannot find the file specified.",
+    )
+}
+
+#[derive(Debug, Clone, Default)]
+struct MemoryKey {
+    /// Key name with its original casing
+    name: String,
+    /// Values by lowercase name
+    values: BTreeMap<String, (String, RegValue)>,
+}
+
+/// Registry kept in memory, usable on any OS
+#[derive(Debug, Default)]
+pub struct MemoryRegistry {
+    /// Keys by hive and lowercase path
+    keys: RefCell<BTreeMap<(Hive, String), MemoryKey>>,
+}
+
+impl MemoryRegistry {
+    pub fn new() -> MemoryRegistry { MemoryRegistry::default() }
+
+    /// Full paths of every key in the registry, sorted
+    pub fn dump_keys(&self) -> Vec<String> {
+        let keys = self.keys.borrow();
+        let mut paths: Vec<String> = keys
+            .keys()
+            .map(|(hive, path)| join(hive.name(), &original_path(&keys, *hive, path)))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Full paths, names and values of every value in the registry, sorted
+    pub fn dump_values(&self) -> Vec<(String, String, RegValue)> {
+        let keys = self.keys.borrow();
+        let mut values: Vec<(String, String, RegValue)> = keys
+            .iter()
+            .flat_map(|((hive, path), key)| {
+                let full_path = join(hive.name(), &original_path(&keys, *hive, path));
+                key.values
+                    .values()
+                    .map(move |(name, value)| (full_path.clone(), name.clone(), value.clone()))
+            })
+            .collect();
+        values.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        values
+    }
+}
+
+/// Rebuilds the original casing of a path from the names of each of its keys
+fn original_path(keys: &BTreeMap<(Hive, String), MemoryKey>, hive: Hive, path: &str) -> String {
+    let mut prefix = String::new();
+    path.split('\\')
+        .map(|component| {
+            prefix = join(&prefix, component);
+            keys.get(&(hive, prefix.clone()))
+                .map(|key| key.name.clone())
+                .unwrap_or_else(|| String::from(component))
+        })
+        .collect::<Vec<String>>()
+        .join("\\")
+}
+
+impl Registry for MemoryRegistry {
+    fn key_exists(&self, hive: Hive, path: &str) -> bool {
+        path.is_empty()
+            || self
+                .keys
+                .borrow()
+                .contains_key(&(hive, path.to_lowercase()))
+    }
+
+    fn create_subkey(&self, hive: Hive, path: &str) -> Result<(), Error> {
+        let mut keys = self.keys.borrow_mut();
+        let mut prefix = String::new();
+        for component in path.split('\\').filter(|c| !c.is_empty()) {
+            prefix = join(&prefix, component);
+            keys.entry((hive, prefix.to_lowercase()))
+                .or_insert_with(|| MemoryKey {
+                    name: String::from(component),
+                    values: BTreeMap::new(),
+                });
+        }
+        Ok(())
+    }
+
+    fn delete_subkey_all(&self, hive: Hive, path: &str) -> Result<(), Error> {
+        if !self.key_exists(hive, path) {
+            return Err(not_found());
+        }
+        let path = path.to_lowercase();
+        let children = format!(r"{}\", path);
+        let mut keys = self.keys.borrow_mut();
+        let doomed: Vec<_> = keys
+            .keys()
+            .filter(|(h, p)| *h == hive && (*p == path || p.starts_with(&children)))
+            .cloned()
+            .collect();
+        for key in doomed {
+            keys.remove(&key);
+        }
+        Ok(())
+    }
+
+    fn enum_keys(&self, hive: Hive, path: &str) -> Result<Vec<String>, Error> {
+        if !self.key_exists(hive, path) {
+            return Err(not_found());
+        }
+        let children = if path.is_empty() {
+            String::new()
+        } else {
+            format!(r"{}\", path.to_lowercase())
+        };
+        Ok(self
+            .keys
+            .borrow()
+            .iter()
+            .filter(|((h, p), _)| {
+                *h == hive && p.starts_with(&children) && !p[children.len()..].contains('\\')
+            })
+            .map(|(_, key)| key.name.clone())
+            .collect())
+    }
+
+    fn enum_values(&self, hive: Hive, path: &str) -> Result<Vec<(String, RegValue)>, Error> {
+        match self.keys.borrow().get(&(hive, path.to_lowercase())) {
+            Some(key) => Ok(key.values.values().cloned().collect()),
+            None if path.is_empty() => Ok(vec![]),
+            None => Err(not_found()),
+        }
+    }
+
+    fn get_value(&self, hive: Hive, path: &str, name: &str) -> Result<RegValue, Error> {
+        match self.keys.borrow().get(&(hive, path.to_lowercase())) {
+            Some(key) => key
+                .values
+                .get(&name.to_lowercase())
+                .map(|(_, value)| value.clone())
+                .ok_or_else(not_found),
+            None => Err(not_found()),
+        }
+    }
+
+    fn set_value(&self, hive: Hive, path: &str, name: &str, value: &RegValue) -> Result<(), Error> {
+        match self.keys.borrow_mut().get_mut(&(hive, path.to_lowercase())) {
+            Some(key) => {
+                key.values
+                    .insert(name.to_lowercase(), (String::from(name), value.clone()));
+                Ok(())
+            }
+            None => Err(not_found()),
+        }
+    }
+
+    fn delete_value(&self, hive: Hive, path: &str, name: &str) -> Result<(), Error> {
+        match self.keys.borrow_mut().get_mut(&(hive, path.to_lowercase())) {
+            Some(key) => key
+                .values
+                .remove(&name.to_lowercase())
+                .map(|_| ())
+                .ok_or_else(not_found),
+            None => Err(not_found()),
+        }
+    }
+}
+
+/// The windows registry of this machine
+#[cfg(windows)]
+pub struct SystemRegistry;
+
+#[cfg(windows)]
+mod system {
+    use super::*;
+    use winreg::{
+        enums::{RegType, HKEY_CLASSES_ROOT, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, KEY_SET_VALUE},
+        types::FromRegValue,
+        RegKey,
+    };
+
+    fn predef(hive: Hive) -> RegKey {
+        RegKey::predef(match hive {
+            Hive::ClassesRoot => HKEY_CLASSES_ROOT,
+            Hive::CurrentUser => HKEY_CURRENT_USER,
+            Hive::LocalMachine => HKEY_LOCAL_MACHINE,
+        })
+    }
+
+    /// Every value type in the order of their type numbers
+    const TYPES: [RegType; 12] = [
+        RegType::REG_NONE,
+        RegType::REG_SZ,
+        RegType::REG_EXPAND_SZ,
+        RegType::REG_BINARY,
+        RegType::REG_DWORD,
+        RegType::REG_DWORD_BIG_ENDIAN,
+        RegType::REG_LINK,
+        RegType::REG_MULTI_SZ,
+        RegType::REG_RESOURCE_LIST,
+        RegType::REG_FULL_RESOURCE_DESCRIPTOR,
+        RegType::REG_RESOURCE_REQUIREMENTS_LIST,
+        RegType::REG_QWORD,
+    ];
+
+    /// Converts the value types wedge understands, and keeps the bytes of any other
+    fn convert(value: winreg::RegValue) -> RegValue {
+        let converted = match value.vtype {
+            RegType::REG_SZ | RegType::REG_EXPAND_SZ => {
+                String::from_reg_value(&value).ok().map(RegValue::String)
+            }
+            RegType::REG_DWORD => u32::from_reg_value(&value).ok().map(RegValue::Dword),
+            _ => None,
+        };
+        converted.unwrap_or_else(|| RegValue::Raw {
+            kind: value.vtype.clone() as u32,
+            bytes: value.bytes,
+        })
+    }
+
+    impl Registry for SystemRegistry {
+        fn key_exists(&self, hive: Hive, path: &str) -> bool {
+            predef(hive).open_subkey(path).is_ok()
+        }
+
+        fn create_subkey(&self, hive: Hive, path: &str) -> Result<(), Error> {
+            predef(hive).create_subkey(path).map(|_| ())
+        }
+
+        fn delete_subkey_all(&self, hive: Hive, path: &str) -> Result<(), Error> {
+            predef(hive).delete_subkey_all(path)
+        }
+
+        fn enum_keys(&self, hive: Hive, path: &str) -> Result<Vec<String>, Error> {
+            predef(hive).open_subkey(path)?.enum_keys().collect()
+        }
+
+        fn enum_values(&self, hive: Hive, path: &str) -> Result<Vec<(String, RegValue)>, Error> {
+            let mut values = vec![];
+            for value in predef(hive).open_subkey(path)?.enum_values() {
+                let (name, value) = value?;
+                values.push((name, convert(value)));
+            }
+            Ok(values)
+        }
+
+        fn get_value(&self, hive: Hive, path: &str, name: &str) -> Result<RegValue, Error> {
+            Ok(convert(
+                predef(hive).open_subkey(path)?.get_raw_value(name)?,
+            ))
+        }
+
+        fn set_value(
+            &self,
+            hive: Hive,
+            path: &str,
+            name: &str,
+            value: &RegValue,
+        ) -> Result<(), Error> {
+            let key = predef(hive).open_subkey_with_flags(path, KEY_SET_VALUE)?;
+            match value {
+                RegValue::String(value) => key.set_value(name, value),
+                RegValue::Dword(value) => key.set_value(name, value),
+                RegValue::Raw { kind, bytes } => {
+                    let vtype = TYPES.get(*kind as usize).cloned().ok_or_else(|| {
+                        Error::new(ErrorKind::InvalidInput, "Value has an unknown type")
+                    })?;
+                    key.set_raw_value(name, &winreg::RegValue {
+                        bytes: bytes.clone(),
+                        vtype,
+                    })
+                }
+            }
+        }
+
+        fn delete_value(&self, hive: Hive, path: &str, name: &str) -> Result<(), Error> {
+            predef(hive)
+                .open_subkey_with_flags(path, KEY_SET_VALUE)?
+                .delete_value(name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_registry_keys() {
+        let registry = MemoryRegistry::new();
+        let hklm = Key::predef(&registry, Hive::LocalMachine);
+
+        let error = hklm.open_subkey(r"Software\Wedge").err().unwrap();
+        assert!(error.is_not_found());
+        assert_eq!(
+            r"Registry key `HKEY_LOCAL_MACHINE\Software\Wedge`: The system cannot find the file specified.",
+            error.to_string()
+        );
+        hklm.create_subkey(r"Software\Wedge\Sub").unwrap();
+        hklm.create_subkey(r"SOFTWARE\Other").unwrap();
+        assert!(hklm.open_subkey(r"software\wedge").is_ok());
+        assert!(Key::predef(&registry, Hive::CurrentUser)
+            .open_subkey(r"Software\Wedge")
+            .is_err());
+        assert_eq!(
+            vec![
+                r"HKEY_LOCAL_MACHINE\Software",
+                r"HKEY_LOCAL_MACHINE\Software\Other",
+                r"HKEY_LOCAL_MACHINE\Software\Wedge",
+                r"HKEY_LOCAL_MACHINE\Software\Wedge\Sub",
+            ],
+            registry.dump_keys()
+        );
+        assert_eq!(
+            vec!["Other", "Wedge"],
+            hklm.open_subkey("Software").unwrap().enum_keys().unwrap()
+        );
+
+        hklm.delete_subkey_all(r"Software\wedge").unwrap();
+        assert!(hklm.delete_subkey_all(r"Software\Wedge").is_err());
+        assert_eq!(
+            vec![
+                r"HKEY_LOCAL_MACHINE\Software",
+                r"HKEY_LOCAL_MACHINE\Software\Other",
+            ],
+            registry.dump_keys()
+        );
+    }
+
+    #[test]
+    fn test_memory_registry_values() {
+        let registry = MemoryRegistry::new();
+        let hkcu = Key::predef(&registry, Hive::CurrentUser);
+        let key = hkcu.create_subkey(r"Software\Wedge").unwrap();
+
+        key.set_value("", "default").unwrap();
+        key.set_value("Count", 3u32).unwrap();
+        key.set_value("count", 4u32).unwrap();
+        assert_eq!("default", key.get_string("").unwrap());
+        assert_eq!(4, key.get_u32("COUNT").unwrap());
+        assert!(key.get_string("Count").is_err());
+        assert!(key.get_value("Missing").is_err());
+        assert_eq!(
+            vec![
+                (String::from(""), RegValue::from("default")),
+                (String::from("count"), RegValue::Dword(4)),
+            ],
+            key.enum_values().unwrap()
+        );
+
+        let binary = RegValue::Raw {
+            kind: REG_BINARY,
+            bytes: vec![0, 1, 0xff],
+        };
+        key.set_value("Binary", binary.clone()).unwrap();
+        assert_eq!(binary, key.get_value("binary").unwrap());
+        assert!(key.get_u32("Binary").is_err());
+        assert_eq!("hex:00,01,ff", binary.to_string());
+        assert_eq!(
+            "hex(b):00,01,00,00,00,00,00,00",
+            RegValue::Raw {
+                kind: 11,
+                bytes: vec![0, 1, 0, 0, 0, 0, 0, 0],
+            }
+            .to_string()
+        );
+
+        key.delete_value("Count").unwrap();
+        assert!(key.delete_value("Count").is_err());
+        assert!(Key::predef(&registry, Hive::CurrentUser)
+            .subkey("Missing")
+            .set_value("", 1u32)
+            .is_err());
+    }
+}
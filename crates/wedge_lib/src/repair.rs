@@ -0,0 +1,225 @@
+#[cfg(windows)]
+use crate::{
+    error::WedgeError,
+    install::{
+        gather_context, legacy_manifest, InstallPaths, Scope, BINARY_NAME, LICENSE_NAME,
+        UNINSTALLER_NAME,
+    },
+    plan::Runner,
+    registry::join,
+    system::WindowsSystem,
+};
+use crate::{
+    install::{install_plan, InstallContext},
+    plan::{Action, Manifest, Undo},
+    system::System,
+};
+#[cfg(windows)]
+use std::io::{Error, ErrorKind};
+
+/// Is the change an install action makes still in place?
+fn in_effect(system: &dyn System, action: &Action) -> bool {
+    let registry = system.registry();
+    match action {
+        Action::CreateDir { path } | Action::CreateShortcut { path, .. } => {
+            system.path_exists(path)
+        }
+        Action::CreateKey { hive, path } | Action::CreateSharedKey { hive, path } => {
+            registry.key_exists(*hive, path)
+        }
+        Action::SetValue {
+            hive,
+            path,
+            name,
+            value,
+        } => registry.get_value(*hive, path, name).ok().as_ref() == Some(value),
+        Action::CreateSymlink { path, target } => match system.read_link(path) {
+            Ok(actual) => actual.eq_ignore_ascii_case(target) && system.path_exists(target),
+            Err(_) => false,
+        },
+        // Files only come with the installer, and notifying again isn't wanted
+        Action::WriteFile { .. } | Action::OpenUrl { .. } => true,
+    }
+}
+
+/// The parts of an install that have drifted, such as the proxy and IFEO filter once an edge
+/// update replaces its Application folder. Installed files are left as they are.
+pub fn repair_plan(system: &dyn System, context: &InstallContext) -> Vec<Action> {
+    install_plan(context)
+        .into_iter()
+        .filter(|action| !in_effect(system, action))
+        .collect()
+}
+
+/// Whether the install with the manifest at `manifest_path` made the edge proxy at `proxy_path`.
+/// Missing proxies it didn't make were never meant to be there, like for per-user installs and
+/// channels installed later.
+pub fn installed_proxy(system: &dyn System, manifest_path: &str, proxy_path: &str) -> bool {
+    match Manifest::load(system, manifest_path) {
+        Ok(manifest) => manifest.undos.iter().any(|undo| match undo {
+            Undo::RemoveFile { path } => path.eq_ignore_ascii_case(proxy_path),
+            _ => false,
+        }),
+        Err(_) => false,
+    }
+}
+
+/// Repairs the install for `scope`, returning what was changed. Repairs are added to the
+/// install manifest so uninstalling takes them back too.
+#[cfg(windows)]
+pub fn repair(scope: Scope) -> Result<Vec<String>, WedgeError> {
+    let paths = InstallPaths::for_scope(scope)?;
+    if !WindowsSystem.path_exists(&paths.binary_path()) {
+        return Err(WedgeError::filesystem(paths.binary_path())(Error::new(
+            ErrorKind::NotFound,
+            "Wedge isn't installed",
+        )));
+    }
+
+    // The installed files stand in for the installer's, which only matter for their size
+    let files = [BINARY_NAME, UNINSTALLER_NAME, LICENSE_NAME]
+        .iter()
+        .filter_map(|name| {
+            let contents = WindowsSystem
+                .read_file(&join(&paths.install_dir, name))
+                .ok()?;
+            Some((*name, contents))
+        })
+        .collect();
+    let context = gather_context(scope, files)?;
+    let actions = repair_plan(&WindowsSystem, &context);
+    if actions.is_empty() {
+        return Ok(vec![]);
+    }
+
+    // Installs from before manifests existed were always for every user
+    let previous = match scope {
+        Scope::Machine => legacy_manifest(&context.start_menu_dir),
+        Scope::User => Manifest::default(),
+    };
+    let mut log = vec![];
+    Runner::new(&WindowsSystem, actions)
+        .with_manifest(&context.paths.manifest_path(), previous)
+        .run(|line| log.push(String::from(line)))?;
+    Ok(log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        browser::Browser,
+        doctor::diagnose,
+        edge::EdgeInstall,
+        install::{
+            ifeo_filters, InstallPaths, Scope, BINARY_NAME, IFEO_KEY, MSEDGE_PATH, UNINSTALLER_NAME,
+        },
+        plan::Runner,
+        registry::{Hive, Key},
+        system::MemorySystem,
+    };
+
+    const START_MENU: &str = r"C:\ProgramData\Microsoft\Windows\Start Menu\Programs";
+
+    fn context(system: &MemorySystem) -> InstallContext {
+        let paths = InstallPaths::default();
+        InstallContext {
+            scope: Scope::Machine,
+            files: vec![
+                (BINARY_NAME, vec![1; 2048]),
+                (UNINSTALLER_NAME, vec![2; 1024]),
+            ],
+            start_menu_dir: String::from(START_MENU),
+            default_browser: Browser::Firefox,
+            edges: ifeo_filters(&system.registry, &paths, vec![EdgeInstall::from_path(
+                MSEDGE_PATH,
+            )]),
+            paths,
+        }
+    }
+
+    #[test]
+    fn test_repair_after_edge_update() {
+        let system = MemorySystem::new(&[
+            r"C:",
+            r"C:\Program Files (x86)",
+            r"C:\Program Files (x86)\Microsoft\Edge\Application",
+            START_MENU,
+        ]);
+        system.write_file(MSEDGE_PATH, b"edge").unwrap();
+        let manifest_path = InstallPaths::default().manifest_path();
+        Runner::new(&system, install_plan(&context(&system)))
+            .with_manifest(&manifest_path, Manifest::default())
+            .run(|_| {})
+            .unwrap();
+        assert!(repair_plan(&system, &context(&system)).is_empty());
+        let edge = EdgeInstall::from_path(MSEDGE_PATH);
+        assert!(installed_proxy(&system, &manifest_path, &edge.proxy_path()));
+        assert!(!installed_proxy(
+            &system,
+            &manifest_path,
+            r"C:\Program Files (x86)\Microsoft\Edge Beta\Application\msedge-wedge-proxy.exe"
+        ));
+        assert!(!installed_proxy(
+            &system,
+            r"C:\Wedge\manifest.json",
+            &edge.proxy_path()
+        ));
+
+        // Updating replaces the Application folder, and the filter was taken out meanwhile
+        system.remove_file(&edge.proxy_path()).unwrap();
+        Key::predef(&system.registry, Hive::LocalMachine)
+            .delete_subkey_all(&format!(r"{}\0", IFEO_KEY))
+            .unwrap();
+        let actions = repair_plan(&system, &context(&system));
+        assert_eq!(
+            vec![
+                format!(
+                    r"Create symlink `{}` to `{}`",
+                    edge.proxy_path(),
+                    MSEDGE_PATH
+                ),
+                format!(r"Create registry key `HKEY_LOCAL_MACHINE\{}\0`", IFEO_KEY),
+                format!(
+                    r"Set registry value `Debugger` of `HKEY_LOCAL_MACHINE\{}\0` to {:?}",
+                    IFEO_KEY,
+                    format!("\"{}\"", InstallPaths::default().binary_path())
+                ),
+                format!(
+                    r"Set registry value `FilterFullPath` of `HKEY_LOCAL_MACHINE\{}\0` to {:?}",
+                    IFEO_KEY, MSEDGE_PATH
+                ),
+            ],
+            actions.iter().map(Action::describe).collect::<Vec<_>>()
+        );
+
+        Runner::new(&system, actions)
+            .with_manifest(&manifest_path, Manifest::default())
+            .run(|_| {})
+            .unwrap();
+        assert!(repair_plan(&system, &context(&system)).is_empty());
+        let report = diagnose(
+            &system,
+            Scope::Machine,
+            &InstallPaths::default(),
+            START_MENU,
+            vec![edge],
+        );
+        assert_eq!(0, report.exit_code());
+
+        // Uninstalling still takes everything back
+        Manifest::load(&system, &manifest_path)
+            .unwrap()
+            .undo(&system, |_| {})
+            .unwrap();
+        assert!(system.registry.dump_values().is_empty());
+        assert_eq!(
+            vec![String::from(MSEDGE_PATH)],
+            system
+                .dump_files()
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>()
+        );
+    }
+}
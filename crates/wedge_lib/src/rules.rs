@@ -0,0 +1,264 @@
+use crate::browser::Browser;
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer};
+use url::Url;
+
+/// Where a deflected url is opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// The system's default browser
+    DefaultBrowser,
+    /// Edge itself, as if wedge wasn't installed
+    Edge,
+    /// A specific browser
+    Browser(Browser),
+}
+
+impl Target {
+    /// Parses target from its name as used in settings
+    pub fn from_name(name: &str) -> Option<Target> {
+        Some(match name.trim().to_lowercase().as_str() {
+            "default" | "default-browser" => Target::DefaultBrowser,
+            "edge" => Target::Edge,
+            name => match Browser::from_name(name)? {
+                Browser::Edge => Target::Edge,
+                browser => Target::Browser(browser),
+            },
+        })
+    }
+
+    /// Name used in settings
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::DefaultBrowser => "default",
+            Target::Edge => "edge",
+            Target::Browser(browser) => browser.info().map_or("unknown", |info| info.name),
+        }
+    }
+}
+
+/// Routes urls matching all of its conditions to a target. Conditions left unset match
+/// anything.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Rule {
+    /// Host glob such as `*.sharepoint.com`, matched case-insensitively
+    pub host: Option<String>,
+    /// Path must start with this
+    pub path_prefix: Option<String>,
+    /// Regular expression the path must match
+    #[serde(default, deserialize_with = "deserialize_regex")]
+    pub path_regex: Option<String>,
+    /// Scheme such as `https`
+    pub scheme: Option<String>,
+    pub target: Target,
+    /// Args the target browser is launched with before the url, like `-private-window`
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl Rule {
+    pub fn new(target: Target) -> Rule {
+        Rule {
+            host: None,
+            path_prefix: None,
+            path_regex: None,
+            scheme: None,
+            target,
+            args: vec![],
+        }
+    }
+
+    /// Checks the rule can be used, returning why not if it can't
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(pattern) = &self.path_regex {
+            Regex::new(pattern).map_err(|e| format!("Invalid path regex: {}", e))?;
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, url: &str) -> bool {
+        let url = match Url::parse(url) {
+            Ok(url) => url,
+            Err(_) => return false,
+        };
+
+        if let Some(scheme) = &self.scheme {
+            if !scheme.eq_ignore_ascii_case(url.scheme()) {
+                return false;
+            }
+        }
+        if let Some(host) = &self.host {
+            if !glob_match(&host.to_lowercase(), url.host_str().unwrap_or_default()) {
+                return false;
+            }
+        }
+        if let Some(prefix) = &self.path_prefix {
+            if !url.path().starts_with(prefix.as_str()) {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.path_regex {
+            match Regex::new(pattern) {
+                Ok(regex) if regex.is_match(url.path()) => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(|e| de::Error::custom(format!("invalid path regex: {}", e)))?;
+    Ok(Some(pattern))
+}
+
+/// Picks the first rule matching the url by index, along with its target, or `fallback` when
+/// no rule matches
+pub fn route(rules: &[Rule], url: &str, fallback: Target) -> (Option<usize>, Target) {
+    match rules.iter().position(|rule| rule.matches(url)) {
+        Some(index) => (Some(index), rules[index].target),
+        None => (None, fallback),
+    }
+}
+
+/// Matches text against a glob where `*` matches any run of characters and `?` any one
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // Position to resume from after the last `*` when a match fails
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("example.com", "example.com"));
+        assert!(glob_match("*.example.com", "www.example.com"));
+        assert!(glob_match("*.example.com", "a.b.example.com"));
+        assert!(!glob_match("*.example.com", "example.com"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("a*b*c", "aXXbYY"));
+        assert!(glob_match("exampl?.com", "example.com"));
+        assert!(!glob_match("exampl?.com", "exampl.com"));
+    }
+
+    #[test]
+    fn test_rule_matches() {
+        let rule = Rule {
+            host: Some(String::from("*.SharePoint.com")),
+            path_prefix: Some(String::from("/sites/")),
+            path_regex: None,
+            scheme: Some(String::from("https")),
+            target: Target::Edge,
+            args: vec![],
+        };
+        assert!(rule.matches("https://contoso.sharepoint.com/sites/hr/page.aspx"));
+        assert!(!rule.matches("http://contoso.sharepoint.com/sites/hr/page.aspx"));
+        assert!(!rule.matches("https://contoso.sharepoint.com/teams/hr"));
+        assert!(!rule.matches("https://sharepoint.com.evil.com/sites/"));
+        assert!(!rule.matches("not a url"));
+
+        let rule = Rule {
+            path_regex: Some(String::from(r"^/admin(/|$)")),
+            ..Rule::new(Target::Edge)
+        };
+        assert!(rule.matches("https://admin.teams.microsoft.com/admin"));
+        assert!(rule.matches("https://example.com/admin/users"));
+        assert!(!rule.matches("https://example.com/administrator"));
+
+        let rule = Rule {
+            path_regex: Some(String::from("(")),
+            ..Rule::new(Target::Edge)
+        };
+        assert!(rule.validate().is_err());
+        assert!(!rule.matches("https://example.com/"));
+    }
+
+    #[test]
+    fn test_route() {
+        let rules = vec![
+            Rule {
+                host: Some(String::from("*.sharepoint.com")),
+                ..Rule::new(Target::Edge)
+            },
+            Rule {
+                host: Some(String::from("admin.teams.microsoft.com")),
+                ..Rule::new(Target::Edge)
+            },
+            Rule {
+                scheme: Some(String::from("http")),
+                ..Rule::new(Target::Browser(Browser::Chrome))
+            },
+            Rule::new(Target::Browser(Browser::Firefox)),
+        ];
+        let fallback = Target::DefaultBrowser;
+        assert_eq!(
+            (Some(0), Target::Edge),
+            route(&rules, "https://contoso.sharepoint.com/", fallback)
+        );
+        assert_eq!(
+            (Some(1), Target::Edge),
+            route(
+                &rules,
+                "https://admin.teams.microsoft.com/dashboard",
+                fallback
+            )
+        );
+        assert_eq!(
+            (Some(2), Target::Browser(Browser::Chrome)),
+            route(&rules, "http://example.com/", fallback)
+        );
+        assert_eq!(
+            (Some(3), Target::Browser(Browser::Firefox)),
+            route(&rules, "https://example.com/", fallback)
+        );
+        assert_eq!(
+            (None, Target::Browser(Browser::Brave)),
+            route(&[], "https://example.com/", Target::Browser(Browser::Brave))
+        );
+
+        // A rule picking the default browser beats the fallback
+        let rules = [Rule::new(Target::DefaultBrowser)];
+        assert_eq!(
+            (Some(0), Target::DefaultBrowser),
+            route(&rules, "https://example.com/", Target::Edge)
+        );
+    }
+
+    #[test]
+    fn test_target_from_name() {
+        assert_eq!(Some(Target::DefaultBrowser), Target::from_name("Default"));
+        assert_eq!(Some(Target::Edge), Target::from_name("edge"));
+        assert_eq!(
+            Some(Target::Browser(Browser::Firefox)),
+            Target::from_name("Firefox")
+        );
+        assert_eq!(None, Target::from_name("netscape"));
+    }
+}
@@ -0,0 +1,215 @@
+use url::{form_urlencoded::byte_serialize, Url};
+
+/// Placeholder replaced by the search query in search engine templates
+pub const SEARCH_TERMS: &str = "{searchTerms}";
+
+/// Search engine bing searches are redirected to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchEngine {
+    Google,
+    DuckDuckGo,
+    Startpage,
+    Kagi,
+    /// Url template containing `{searchTerms}`
+    Custom(String),
+}
+
+impl SearchEngine {
+    /// Parses search engine from its name or a custom url template
+    pub fn from_name(name: &str) -> Option<SearchEngine> {
+        let name = name.trim();
+        Some(match name.to_lowercase().as_str() {
+            "google" => SearchEngine::Google,
+            "duckduckgo" | "ddg" => SearchEngine::DuckDuckGo,
+            "startpage" => SearchEngine::Startpage,
+            "kagi" => SearchEngine::Kagi,
+            _ => {
+                // Template must be a valid web url once the placeholder is filled in
+                if !name.contains(SEARCH_TERMS) {
+                    return None;
+                }
+                match Url::parse(&name.replace(SEARCH_TERMS, "test")) {
+                    Ok(ref url) if url.scheme() == "http" || url.scheme() == "https" => {
+                        SearchEngine::Custom(String::from(name))
+                    }
+                    _ => return None,
+                }
+            }
+        })
+    }
+
+    /// Name used in settings, or the template of a custom search engine
+    pub fn name(&self) -> &str {
+        match self {
+            SearchEngine::Google => "google",
+            SearchEngine::DuckDuckGo => "duckduckgo",
+            SearchEngine::Startpage => "startpage",
+            SearchEngine::Kagi => "kagi",
+            SearchEngine::Custom(template) => template,
+        }
+    }
+
+    /// Url template used to build searches
+    pub fn template(&self) -> &str {
+        match self {
+            SearchEngine::Google => "https://www.google.com/search?q={searchTerms}",
+            SearchEngine::DuckDuckGo => "https://duckduckgo.com/?q={searchTerms}",
+            SearchEngine::Startpage => "https://www.startpage.com/sp/search?query={searchTerms}",
+            SearchEngine::Kagi => "https://kagi.com/search?q={searchTerms}",
+            SearchEngine::Custom(template) => template,
+        }
+    }
+
+    /// Builds search url for the given query. Queries are form encoded in the query string of
+    /// the url and percent encoded anywhere else, where `+` isn't read as a space.
+    pub fn search_url(&self, query: &str) -> String {
+        let template = self.template();
+        let in_query_string = match (template.find('?'), template.find(SEARCH_TERMS)) {
+            (Some(question), Some(terms)) => {
+                question < terms && !template[question..terms].contains('#')
+            }
+            _ => false,
+        };
+        // Form encoding escapes `+` itself, so any left are spaces
+        let encoded: String = byte_serialize(query.as_bytes()).collect();
+        let encoded = if in_query_string {
+            encoded
+        } else {
+            encoded.replace('+', "%20")
+        };
+        template.replace(SEARCH_TERMS, &encoded)
+    }
+}
+
+/// Extracts the search query from a bing search url
+///
+/// https://www.bing.com/search?q=wedge+is+pretty+cool&form=WNSGPH
+pub fn parse_bing_query(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?.to_lowercase();
+    if !(host == "bing.com" || host.ends_with(".bing.com")) || url.path() != "/search" {
+        return None;
+    }
+
+    url.query_pairs()
+        .find_map(|pair| {
+            if pair.0 == "q" {
+                Some(pair.1.to_string())
+            } else {
+                None
+            }
+        })
+        .filter(|query| !query.trim().is_empty())
+}
+
+/// Rewrites a bing search url so it searches with another search engine instead
+pub fn rewrite_bing_search(url: &str, engine: &SearchEngine) -> Option<String> {
+    parse_bing_query(url).map(|query| engine.search_url(&query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_engine_from_name() {
+        assert_eq!(
+            Some(SearchEngine::Google),
+            SearchEngine::from_name("Google")
+        );
+        assert_eq!(
+            Some(SearchEngine::DuckDuckGo),
+            SearchEngine::from_name(" duckduckgo ")
+        );
+        assert_eq!(
+            Some(SearchEngine::Startpage),
+            SearchEngine::from_name("STARTPAGE")
+        );
+        assert_eq!(Some(SearchEngine::Kagi), SearchEngine::from_name("kagi"));
+        assert_eq!(
+            Some(SearchEngine::Custom(String::from(
+                "https://search.example.org/?q={searchTerms}"
+            ))),
+            SearchEngine::from_name("https://search.example.org/?q={searchTerms}")
+        );
+        assert_eq!(None, SearchEngine::from_name(""));
+        assert_eq!(None, SearchEngine::from_name("altavista"));
+        assert_eq!(
+            None,
+            SearchEngine::from_name("https://search.example.org/?q=")
+        );
+        assert_eq!(None, SearchEngine::from_name("file:///{searchTerms}"));
+    }
+
+    #[test]
+    fn test_parse_bing_query() {
+        assert_eq!(
+            Some(String::from("wedge is pretty cool")),
+            parse_bing_query("https://www.bing.com/search?q=wedge+is+pretty+cool&form=WNSGPH")
+        );
+        assert_eq!(
+            Some(String::from("a&b=c?")),
+            parse_bing_query("https://bing.com/search?form=X&q=a%26b%3Dc%3F")
+        );
+        assert_eq!(None, parse_bing_query("https://www.bing.com/search?q="));
+        assert_eq!(None, parse_bing_query("https://www.bing.com/images?q=cat"));
+        assert_eq!(None, parse_bing_query("https://www.bing.com/"));
+        assert_eq!(None, parse_bing_query("https://notbing.com/search?q=cat"));
+        assert_eq!(None, parse_bing_query("https://example.com/search?q=cat"));
+        assert_eq!(None, parse_bing_query("microsoft-edge:invalid"));
+    }
+
+    #[test]
+    fn test_rewrite_bing_search() {
+        let url = "https://www.bing.com/search?q=wedge+%26+rust&form=WNSGPH";
+        assert_eq!(
+            Some(String::from(
+                "https://www.google.com/search?q=wedge+%26+rust"
+            )),
+            rewrite_bing_search(url, &SearchEngine::Google)
+        );
+        assert_eq!(
+            Some(String::from("https://duckduckgo.com/?q=wedge+%26+rust")),
+            rewrite_bing_search(url, &SearchEngine::DuckDuckGo)
+        );
+        assert_eq!(
+            Some(String::from(
+                "https://www.startpage.com/sp/search?query=wedge+%26+rust"
+            )),
+            rewrite_bing_search(url, &SearchEngine::Startpage)
+        );
+        assert_eq!(
+            Some(String::from("https://kagi.com/search?q=wedge+%26+rust")),
+            rewrite_bing_search(url, &SearchEngine::Kagi)
+        );
+        assert_eq!(
+            Some(String::from("https://example.org/find/wedge%20%26%20rust")),
+            rewrite_bing_search(
+                url,
+                &SearchEngine::Custom(String::from("https://example.org/find/{searchTerms}"))
+            )
+        );
+        assert_eq!(
+            None,
+            rewrite_bing_search("https://example.com/?q=cat", &SearchEngine::Google)
+        );
+    }
+
+    #[test]
+    fn test_search_url() {
+        let path = SearchEngine::Custom(String::from("https://example.com/search/{searchTerms}"));
+        assert_eq!(
+            "https://example.com/search/a%2Fb%20c%2B%2B",
+            path.search_url("a/b c++")
+        );
+        let query = SearchEngine::Custom(String::from(
+            "https://example.com/search?q={searchTerms}&lang=en",
+        ));
+        assert_eq!(
+            "https://example.com/search?q=a%2Fb+c%2B%2B&lang=en",
+            query.search_url("a/b c++")
+        );
+        let fragment = SearchEngine::Custom(String::from("https://example.com/?s=1#{searchTerms}"));
+        assert_eq!("https://example.com/?s=1#a%20b", fragment.search_url("a b"));
+    }
+}
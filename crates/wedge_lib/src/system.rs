@@ -0,0 +1,285 @@
+use crate::{
+    error::WedgeError,
+    registry::{MemoryRegistry, Registry},
+};
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    io::{Error, ErrorKind},
+};
+
+/// Everything installing touches on a machine. Paths are windows paths separated by `\`.
+pub trait System {
+    fn registry(&self) -> &dyn Registry;
+
+    /// Is there a file or directory at the path?
+    fn path_exists(&self, path: &str) -> bool;
+
+    fn read_file(&self, path: &str) -> Result<Vec<u8>, WedgeError>;
+
+    /// Writes a file, replacing it if it exists
+    fn write_file(&self, path: &str, contents: &[u8]) -> Result<(), WedgeError>;
+
+    /// Removes a file, shortcut or symlink
+    fn remove_file(&self, path: &str) -> Result<(), WedgeError>;
+
+    /// Creates a directory whose parent exists
+    fn create_dir(&self, path: &str) -> Result<(), WedgeError>;
+
+    /// Removes a directory and everything in it
+    fn remove_dir_all(&self, path: &str) -> Result<(), WedgeError>;
+
+    fn create_shortcut(
+        &self,
+        path: &str,
+        target: &str,
+        description: &str,
+    ) -> Result<(), WedgeError>;
+
+    fn create_symlink(&self, path: &str, target: &str) -> Result<(), WedgeError>;
+
+    /// Path a symlink points to
+    fn read_link(&self, path: &str) -> Result<String, WedgeError>;
+
+    /// Opens a url with the default handler of its protocol
+    fn open_url(&self, url: &str);
+}
+
+/// Parent of a windows path, if it has one
+pub fn parent(path: &str) -> Option<&str> { path.rfind('\\').map(|i| &path[..i]) }
+
+/// The machine wedge is running on
+#[cfg(windows)]
+pub struct WindowsSystem;
+
+#[cfg(windows)]
+mod windows {
+    use super::*;
+    use crate::{registry::SystemRegistry, win32};
+    use std::fs;
+
+    impl System for WindowsSystem {
+        fn registry(&self) -> &dyn Registry { &SystemRegistry }
+
+        fn path_exists(&self, path: &str) -> bool { fs::symlink_metadata(path).is_ok() }
+
+        fn read_file(&self, path: &str) -> Result<Vec<u8>, WedgeError> {
+            fs::read(path).map_err(WedgeError::filesystem(path))
+        }
+
+        fn write_file(&self, path: &str, contents: &[u8]) -> Result<(), WedgeError> {
+            fs::write(path, contents).map_err(WedgeError::filesystem(path))
+        }
+
+        fn remove_file(&self, path: &str) -> Result<(), WedgeError> {
+            fs::remove_file(path).map_err(WedgeError::filesystem(path))
+        }
+
+        fn create_dir(&self, path: &str) -> Result<(), WedgeError> {
+            fs::create_dir(path).map_err(WedgeError::filesystem(path))
+        }
+
+        fn remove_dir_all(&self, path: &str) -> Result<(), WedgeError> {
+            fs::remove_dir_all(path).map_err(WedgeError::filesystem(path))
+        }
+
+        fn create_shortcut(
+            &self,
+            path: &str,
+            target: &str,
+            description: &str,
+        ) -> Result<(), WedgeError> {
+            win32::create_link(path, target, description)
+        }
+
+        fn create_symlink(&self, path: &str, target: &str) -> Result<(), WedgeError> {
+            win32::create_symlink(path, target)
+        }
+
+        fn read_link(&self, path: &str) -> Result<String, WedgeError> {
+            let target = fs::read_link(path).map_err(WedgeError::filesystem(path))?;
+            // Targets may come back as NT paths
+            Ok(String::from(
+                target.to_string_lossy().trim_start_matches(r"\??\"),
+            ))
+        }
+
+        fn open_url(&self, url: &str) { let _ = win32::shell_execute(url); }
+    }
+}
+
+/// A file, shortcut or symlink kept by `MemorySystem`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemoryFile {
+    File(Vec<u8>),
+    Shortcut { target: String, description: String },
+    Symlink { target: String },
+}
+
+/// Machine kept in memory, usable on any OS. Paths are case-insensitive like on windows.
+#[derive(Debug, Default)]
+pub struct MemorySystem {
+    pub registry: MemoryRegistry,
+    /// Symlinks can only be created when elevated
+    pub elevated: bool,
+    /// Directories by lowercase path, with their original path
+    dirs: RefCell<BTreeMap<String, String>>,
+    /// Files by lowercase path, with their original path
+    files: RefCell<BTreeMap<String, (String, MemoryFile)>>,
+    opened_urls: RefCell<Vec<String>>,
+}
+
+impl MemorySystem {
+    /// An elevated machine with only the given directories
+    pub fn new(dirs: &[&str]) -> MemorySystem {
+        let system = MemorySystem {
+            elevated: true,
+            ..MemorySystem::default()
+        };
+        for dir in dirs {
+            system
+                .dirs
+                .borrow_mut()
+                .insert(dir.to_lowercase(), String::from(*dir));
+        }
+        system
+    }
+
+    /// Paths of every directory, sorted
+    pub fn dump_dirs(&self) -> Vec<String> { self.dirs.borrow().values().cloned().collect() }
+
+    /// Every file with its path, sorted
+    pub fn dump_files(&self) -> Vec<(String, MemoryFile)> {
+        self.files.borrow().values().cloned().collect()
+    }
+
+    /// Urls opened so far, in order
+    pub fn opened_urls(&self) -> Vec<String> { self.opened_urls.borrow().clone() }
+
+    fn check_parent(&self, path: &str) -> Result<(), WedgeError> {
+        match parent(path) {
+            Some(parent) if self.dirs.borrow().contains_key(&parent.to_lowercase()) => Ok(()),
+            _ => Err(not_found(path)),
+        }
+    }
+
+    fn insert(&self, path: &str, file: MemoryFile) -> Result<(), WedgeError> {
+        self.check_parent(path)?;
+        if self.dirs.borrow().contains_key(&path.to_lowercase()) {
+            return Err(WedgeError::filesystem(path)(Error::new(
+                ErrorKind::AlreadyExists,
+                "Path is a directory",
+            )));
+        }
+        self.files
+            .borrow_mut()
+            .insert(path.to_lowercase(), (String::from(path), file));
+        Ok(())
+    }
+}
+
+fn not_found(path: &str) -> WedgeError {
+    WedgeError::filesystem(path)(Error::new(
+        ErrorKind::NotFound,
+        "The system cannot find the path specified.",
+    ))
+}
+
+impl System for MemorySystem {
+    fn registry(&self) -> &dyn Registry { &self.registry }
+
+    fn path_exists(&self, path: &str) -> bool {
+        let path = path.to_lowercase();
+        self.dirs.borrow().contains_key(&path) || self.files.borrow().contains_key(&path)
+    }
+
+    fn read_file(&self, path: &str) -> Result<Vec<u8>, WedgeError> {
+        match self.files.borrow().get(&path.to_lowercase()) {
+            Some((_, MemoryFile::File(contents))) => Ok(contents.clone()),
+            _ => Err(not_found(path)),
+        }
+    }
+
+    fn write_file(&self, path: &str, contents: &[u8]) -> Result<(), WedgeError> {
+        self.insert(path, MemoryFile::File(contents.to_vec()))
+    }
+
+    fn remove_file(&self, path: &str) -> Result<(), WedgeError> {
+        self.files
+            .borrow_mut()
+            .remove(&path.to_lowercase())
+            .map(|_| ())
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn create_dir(&self, path: &str) -> Result<(), WedgeError> {
+        self.check_parent(path)?;
+        self.dirs
+            .borrow_mut()
+            .insert(path.to_lowercase(), String::from(path));
+        Ok(())
+    }
+
+    fn remove_dir_all(&self, path: &str) -> Result<(), WedgeError> {
+        let lowercase = path.to_lowercase();
+        if !self.dirs.borrow().contains_key(&lowercase) {
+            return Err(not_found(path));
+        }
+        let children = format!(r"{}\", lowercase);
+        let mut dirs = self.dirs.borrow_mut();
+        let doomed: Vec<String> = dirs
+            .keys()
+            .filter(|dir| **dir == lowercase || dir.starts_with(&children))
+            .cloned()
+            .collect();
+        for dir in doomed {
+            dirs.remove(&dir);
+        }
+        let mut files = self.files.borrow_mut();
+        let doomed: Vec<String> = files
+            .keys()
+            .filter(|file| file.starts_with(&children))
+            .cloned()
+            .collect();
+        for file in doomed {
+            files.remove(&file);
+        }
+        Ok(())
+    }
+
+    fn create_shortcut(
+        &self,
+        path: &str,
+        target: &str,
+        description: &str,
+    ) -> Result<(), WedgeError> {
+        self.insert(path, MemoryFile::Shortcut {
+            target: String::from(target),
+            description: String::from(description),
+        })
+    }
+
+    fn create_symlink(&self, path: &str, target: &str) -> Result<(), WedgeError> {
+        if !self.elevated {
+            return Err(WedgeError::Elevation {
+                operation: "create symlinks",
+            });
+        }
+        self.insert(path, MemoryFile::Symlink {
+            target: String::from(target),
+        })
+    }
+
+    fn read_link(&self, path: &str) -> Result<String, WedgeError> {
+        match self.files.borrow().get(&path.to_lowercase()) {
+            Some((_, MemoryFile::Symlink { target })) => Ok(target.clone()),
+            Some(_) => Err(WedgeError::filesystem(path)(Error::new(
+                ErrorKind::InvalidInput,
+                "The file or directory is not a reparse point.",
+            ))),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn open_url(&self, url: &str) { self.opened_urls.borrow_mut().push(String::from(url)) }
+}
@@ -52,6 +52,18 @@ pub fn parse_ms_edge_url(url: &str) -> Option<String> {
     }
 }
 
+/// Tries parsing a web url from an argument edge was launched with. Both plain http(s) urls
+/// and "microsoft-edge:" urls are accepted.
+pub fn parse_edge_arg_url(arg: &str) -> Option<String> {
+    if arg.starts_with("microsoft-edge:") {
+        parse_ms_edge_url(arg)
+    } else if is_http_url(arg) && !arg.contains(' ') {
+        Some(String::from(arg))
+    } else {
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -131,4 +143,21 @@ mod tests {
             parse_ms_edge_url(r"microsoft-edge:C:\Windows\system32\notepad.exe")
         );
     }
+
+    #[test]
+    fn test_edge_arg_url() {
+        assert_eq!(
+            Some(String::from("https://example.com/a?b=c")),
+            parse_edge_arg_url("https://example.com/a?b=c")
+        );
+        assert_eq!(
+            Some(String::from("http://example.com")),
+            parse_edge_arg_url("microsoft-edge:?url=http%3A%2F%2Fexample.com")
+        );
+        assert_eq!(None, parse_edge_arg_url("https://example.com/a b"));
+        assert_eq!(None, parse_edge_arg_url("microsoft-edge:calc.exe"));
+        assert_eq!(None, parse_edge_arg_url("file:///C:/some.pdf"));
+        assert_eq!(None, parse_edge_arg_url(r"C:\some.pdf"));
+        assert_eq!(None, parse_edge_arg_url("--flag"));
+    }
 }
@@ -1,7 +1,7 @@
 #![allow(non_snake_case)]
 #![allow(non_upper_case_globals)]
 
-use super::com::ComPtr;
+use super::{com::ComPtr, error::WedgeError};
 use std::{
     ffi::OsStr,
     io::{Error, ErrorKind},
@@ -15,8 +15,11 @@ use widestring::U16CString;
 use winapi::{
     shared::{
         minwindef::{MAX_PATH, TRUE},
-        ntdef::{HANDLE, NULL},
-        winerror::{SUCCEEDED, S_OK},
+        ntdef::{HANDLE, HRESULT, NULL},
+        winerror::{
+            ERROR_DDE_FAIL, ERROR_DLL_NOT_FOUND, ERROR_NOT_ENOUGH_MEMORY, ERROR_SHARING_VIOLATION,
+            FACILITY_WIN32, HRESULT_CODE, HRESULT_FACILITY, SUCCEEDED, S_OK,
+        },
         wtypesbase::CLSCTX_INPROC_SERVER,
     },
     um::{
@@ -26,20 +29,27 @@ use winapi::{
         ioapiset::DeviceIoControl,
         libloaderapi::{GetModuleFileNameW, GetModuleHandleW},
         objbase::COINIT_MULTITHREADED,
+        processenv::GetCommandLineW,
         processthreadsapi::{GetCurrentProcess, OpenProcessToken},
         securitybaseapi::{AdjustTokenPrivileges, GetTokenInformation},
-        shellapi::ShellExecuteW,
+        shellapi::{
+            ShellExecuteW, SE_ERR_ASSOCINCOMPLETE, SE_ERR_DDEBUSY, SE_ERR_DDEFAIL,
+            SE_ERR_DDETIMEOUT, SE_ERR_DLLNOTFOUND, SE_ERR_NOASSOC, SE_ERR_OOM, SE_ERR_SHARE,
+        },
         shlobj::{SHGetFolderPathW, CSIDL_COMMON_PROGRAMS, CSIDL_LOCAL_APPDATA, CSIDL_PROGRAMS},
         winbase::{
             LookupPrivilegeValueW, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT,
         },
+        wincon::{AttachConsole, ATTACH_PARENT_PROCESS},
         winioctl::FSCTL_SET_REPARSE_POINT,
         winnt::{
             TokenElevation, GENERIC_READ, GENERIC_WRITE, SE_CREATE_SYMBOLIC_LINK_NAME,
             SE_PRIVILEGE_ENABLED, TOKEN_ADJUST_PRIVILEGES, TOKEN_ELEVATION, TOKEN_PRIVILEGES,
             TOKEN_QUERY,
         },
-        winuser::SW_SHOWNORMAL,
+        winuser::{
+            MessageBoxExW, MB_ICONERROR, MB_ICONINFORMATION, MB_OK, MB_TOPMOST, SW_SHOWNORMAL,
+        },
     },
 };
 
@@ -73,21 +83,92 @@ pub fn win32_string(value: &str) -> Vec<u16> {
 }
 
 /// Executes a shell open command
-pub fn shell_execute(command: &str) {
-    unsafe {
+pub fn shell_execute(command: &str) -> Result<(), WedgeError> {
+    shell_execute_verb("open", command, None)
+}
+
+/// Launches a program, such as a browser registered under App Paths, with parameters
+pub fn shell_execute_with(program: &str, parameters: &str) -> Result<(), WedgeError> {
+    shell_execute_verb("open", program, Some(parameters))
+}
+
+/// Launches a program with parameters as administrator, prompting the user through UAC
+pub fn shell_execute_elevated(program: &str, parameters: &str) -> Result<(), WedgeError> {
+    shell_execute_verb("runas", program, Some(parameters))
+}
+
+fn shell_execute_verb(verb: &str, file: &str, parameters: Option<&str>) -> Result<(), WedgeError> {
+    let mut parameters = parameters.map(win32_string);
+    let instance = unsafe {
         ShellExecuteW(
             null_mut(),
-            TEXT!("open"),
-            TEXT!(command),
-            null_mut(),
+            TEXT!(verb),
+            TEXT!(file),
+            parameters
+                .as_mut()
+                .map_or(null_mut(), |parameters| parameters.as_mut_ptr()),
             null_mut(),
             SW_SHOWNORMAL,
+        )
+    };
+    // Anything above 32 means success
+    let code = instance as isize;
+    if code > 32 {
+        Ok(())
+    } else {
+        Err(WedgeError::Os {
+            operation: "run a shell command",
+            source: shell_execute_error(code as u32),
+        })
+    }
+}
+
+/// Converts the error code `ShellExecuteW` returns in place of an instance. It doesn't set the
+/// last error, and some of its codes mean something else as windows errors.
+fn shell_execute_error(code: u32) -> Error {
+    match code {
+        SE_ERR_NOASSOC | SE_ERR_ASSOCINCOMPLETE => {
+            Error::new(ErrorKind::NotFound, "No application is associated with it.")
+        }
+        0 | SE_ERR_OOM => Error::from_raw_os_error(ERROR_NOT_ENOUGH_MEMORY as i32),
+        SE_ERR_SHARE => Error::from_raw_os_error(ERROR_SHARING_VIOLATION as i32),
+        SE_ERR_DDETIMEOUT | SE_ERR_DDEFAIL | SE_ERR_DDEBUSY => {
+            Error::from_raw_os_error(ERROR_DDE_FAIL as i32)
+        }
+        SE_ERR_DLLNOTFOUND => Error::from_raw_os_error(ERROR_DLL_NOT_FOUND as i32),
+        // The rest, like file and path not found, are windows errors already
+        code => Error::from_raw_os_error(code as i32),
+    }
+}
+
+/// Shows an error to the user
+pub fn error_box(title: &str, text: &str) {
+    unsafe {
+        MessageBoxExW(
+            null_mut(),
+            TEXT!(text),
+            TEXT!(title),
+            MB_ICONERROR | MB_OK | MB_TOPMOST,
+            0,
+        );
+    }
+}
+
+/// Shows a message to the user
+pub fn info_box(title: &str, text: &str) {
+    unsafe {
+        MessageBoxExW(
+            null_mut(),
+            TEXT!(text),
+            TEXT!(title),
+            MB_ICONINFORMATION | MB_OK,
+            0,
         );
     }
 }
 
 /// Loads common control classes
-pub fn init_common_controls() -> Result<(), Error> {
+pub fn init_common_controls() -> Result<(), WedgeError> {
     // Disabled as I don't believe this makes a difference for dialogs
 
     // let lp_init_ctrls = INITCOMMONCONTROLSEX {
@@ -95,7 +176,7 @@ pub fn init_common_controls() -> Result<(), Error> {
     //     dwICC:  ICC_PROGRESS_CLASS | ICC_LINK_CLASS | ICC_STANDARD_CLASSES,
     // };
     // if unsafe { InitCommonControlsEx(&lp_init_ctrls) } == 0 {
-    //     Err(Error::last_os_error())
+    //     Err(WedgeError::last_os_error("load common controls"))
     // } else {
     //     Ok(())
     // }
@@ -105,7 +186,7 @@ pub fn init_common_controls() -> Result<(), Error> {
 
 /// Returns location of this running executable
 #[cfg(windows)]
-pub fn get_self_location() -> Result<PathBuf, Error> {
+pub fn get_self_location() -> Result<PathBuf, WedgeError> {
     let mut pf: [u16; MAX_PATH] = [0; MAX_PATH];
     let buffer = pf.as_mut_ptr();
     unsafe {
@@ -113,7 +194,7 @@ pub fn get_self_location() -> Result<PathBuf, Error> {
         let hinstance = GetModuleHandleW(null_mut());
 
         if GetModuleFileNameW(hinstance, buffer, MAX_PATH as _) == 0 {
-            Err(Error::last_os_error())
+            Err(WedgeError::last_os_error("locate this executable"))
         } else {
             Ok(PathBuf::from(
                 U16CString::from_ptr_str(buffer).to_os_string(),
@@ -122,16 +203,27 @@ pub fn get_self_location() -> Result<PathBuf, Error> {
     }
 }
 
+/// Returns the command line this process was started with, exactly as it was written
+#[cfg(windows)]
+pub fn get_command_line() -> String {
+    unsafe {
+        U16CString::from_ptr_str(GetCommandLineW())
+            .to_os_string()
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
 /// Returns location of %temp% folder
 /// "C:\Users\user\AppData\Local\Temp"
 #[cfg(windows)]
-pub fn get_temp_location() -> Result<PathBuf, Error> {
+pub fn get_temp_location() -> Result<PathBuf, WedgeError> {
     let mut pf: [u16; MAX_PATH] = [0; MAX_PATH];
     let buffer = pf.as_mut_ptr();
     unsafe {
         // 0 is error
         if GetTempPathW(MAX_PATH as _, buffer) == 0 {
-            Err(Error::last_os_error())
+            Err(WedgeError::last_os_error("locate the temp folder"))
         } else {
             Ok(PathBuf::from(
                 U16CString::from_ptr_str(buffer).to_os_string(),
@@ -140,7 +232,7 @@ pub fn get_temp_location() -> Result<PathBuf, Error> {
     }
 }
 
-fn shell_get_folder_path(id: i32) -> Result<PathBuf, Error> {
+fn shell_get_folder_path(id: i32, operation: &'static str) -> Result<PathBuf, WedgeError> {
     let mut pf: [u16; MAX_PATH] = [0; MAX_PATH];
     let buffer = pf.as_mut_ptr();
     unsafe {
@@ -150,7 +242,7 @@ fn shell_get_folder_path(id: i32) -> Result<PathBuf, Error> {
                 U16CString::from_ptr_str(buffer).to_os_string(),
             ))
         } else {
-            Err(Error::last_os_error())
+            Err(WedgeError::last_os_error(operation))
         }
     }
 }
@@ -158,27 +250,27 @@ fn shell_get_folder_path(id: i32) -> Result<PathBuf, Error> {
 /// Returns location of local appdata folder
 /// "C:\Users\user\AppData\Local\"
 #[cfg(windows)]
-pub fn get_local_install_location() -> Result<PathBuf, Error> {
-    shell_get_folder_path(CSIDL_LOCAL_APPDATA)
+pub fn get_local_install_location() -> Result<PathBuf, WedgeError> {
+    shell_get_folder_path(CSIDL_LOCAL_APPDATA, "locate the local appdata folder")
 }
 
 /// Returns location of user's program shortcuts
 /// "C:\Users\user\AppData\Roaming\Microsoft\Windows\Start Menu\Programs"
 #[cfg(windows)]
-pub fn get_user_start_menu_location() -> Result<PathBuf, Error> {
-    shell_get_folder_path(CSIDL_PROGRAMS)
+pub fn get_user_start_menu_location() -> Result<PathBuf, WedgeError> {
+    shell_get_folder_path(CSIDL_PROGRAMS, "locate the start menu")
 }
 
 /// Returns location of user's program shortcuts
 #[cfg(windows)]
-pub fn get_global_start_menu_location() -> Result<PathBuf, Error> {
-    shell_get_folder_path(CSIDL_COMMON_PROGRAMS)
+pub fn get_global_start_menu_location() -> Result<PathBuf, WedgeError> {
+    shell_get_folder_path(CSIDL_COMMON_PROGRAMS, "locate the start menu")
 }
 
 /// Creates a link to another file
 /// https://docs.microsoft.com/en-us/windows/win32/shell/links
 #[cfg(windows)]
-pub fn create_link(path: &str, target: &str, desc: &str) -> Result<(), Error> {
+pub fn create_link(path: &str, target: &str, desc: &str) -> Result<(), WedgeError> {
     use com::*;
     let mut hres;
 
@@ -223,7 +315,22 @@ pub fn create_link(path: &str, target: &str, desc: &str) -> Result<(), Error> {
     if SUCCEEDED(hres) {
         Ok(())
     } else {
-        Err(Error::new(ErrorKind::Other, "Error creating link"))
+        Err(WedgeError::Os {
+            operation: "create shortcut",
+            source: hresult_error(hres),
+        })
+    }
+}
+
+/// Converts a failed HRESULT, which wraps a windows error code when its facility is win32
+fn hresult_error(hres: HRESULT) -> Error {
+    if HRESULT_FACILITY(hres) == FACILITY_WIN32 {
+        Error::from_raw_os_error(HRESULT_CODE(hres))
+    } else {
+        Error::new(
+            ErrorKind::Other,
+            format!("Failed with HRESULT {:#010x}", hres as u32),
+        )
     }
 }
 
@@ -345,8 +452,9 @@ mod com {
     }
 }
 
+/// Is this process running with administrator rights?
 #[cfg(windows)]
-fn is_elevated() -> bool {
+pub fn is_elevated() -> bool {
     unsafe {
         let mut handle: HANDLE = std::ptr::null_mut();
         let mut ret = false;
@@ -369,6 +477,11 @@ fn is_elevated() -> bool {
     }
 }
 
+/// Attaches to the console of the process that started us, so a release build without a console
+/// of its own can still print to it. Returns false when there isn't one.
+#[cfg(windows)]
+pub fn attach_console() -> bool { unsafe { AttachConsole(ATTACH_PARENT_PROCESS) != 0 } }
+
 #[cfg(windows)]
 fn get_privilege(name: &str) {
     let mut handle: HANDLE = std::ptr::null_mut();
@@ -397,12 +510,11 @@ fn get_privilege(name: &str) {
 
 /// Creates a symlink to another file
 #[cfg(windows)]
-pub fn create_symlink(path: &str, target: &str) -> Result<(), Error> {
+pub fn create_symlink(path: &str, target: &str) -> Result<(), WedgeError> {
     if !is_elevated() {
-        return Err(Error::new(
-            ErrorKind::Other,
-            "Elevated mode needed to create symlinks",
-        ));
+        return Err(WedgeError::Elevation {
+            operation: "create symlinks",
+        });
     }
 
     get_privilege(SE_CREATE_SYMBOLIC_LINK_NAME);
@@ -414,7 +526,12 @@ pub fn create_symlink(path: &str, target: &str) -> Result<(), Error> {
     // Get print name from pathname
     let print_name: Vec<u16> = PathBuf::from(path)
         .file_name()
-        .ok_or(Error::new(ErrorKind::Other, "Invalid path name"))?
+        .ok_or_else(|| {
+            WedgeError::filesystem(path)(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Invalid path name",
+            ))
+        })?
         .encode_wide()
         .collect();
 
@@ -432,7 +549,7 @@ pub fn create_symlink(path: &str, target: &str) -> Result<(), Error> {
         )
     };
     if symlink_handle == INVALID_HANDLE_VALUE {
-        return Err(Error::last_os_error());
+        return Err(WedgeError::filesystem(path)(Error::last_os_error()));
     }
 
     // Create Symbolic Link Reparse Buffer
@@ -488,7 +605,7 @@ pub fn create_symlink(path: &str, target: &str) -> Result<(), Error> {
             let err = Error::last_os_error();
             CloseHandle(symlink_handle);
             let _ = std::fs::remove_dir(path);
-            return Err(err);
+            return Err(WedgeError::filesystem(path)(err));
         }
 
         CloseHandle(symlink_handle);
@@ -499,7 +616,7 @@ pub fn create_symlink(path: &str, target: &str) -> Result<(), Error> {
 
 /// Creates a directory junction
 #[cfg(windows)]
-pub fn create_directory_junction(path: &str, target: &str) -> Result<(), Error> {
+pub fn create_directory_junction(path: &str, target: &str) -> Result<(), WedgeError> {
     unsafe {
         // Create junction point folder, removing any dir or file already there
         let _ = std::fs::remove_dir(path);
@@ -518,7 +635,7 @@ pub fn create_directory_junction(path: &str, target: &str) -> Result<(), Error>
             0 as _,
         );
         if junction_handle == INVALID_HANDLE_VALUE {
-            return Err(Error::last_os_error());
+            return Err(WedgeError::filesystem(path)(Error::last_os_error()));
         }
 
         // Create mount point reparse buffer
@@ -567,7 +684,7 @@ pub fn create_directory_junction(path: &str, target: &str) -> Result<(), Error>
             let err = Error::last_os_error();
             CloseHandle(junction_handle);
             let _ = std::fs::remove_dir(path);
-            return Err(err);
+            return Err(WedgeError::filesystem(path)(err));
         }
 
         CloseHandle(junction_handle);
@@ -591,6 +708,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_shell_execute_error() {
+        assert_eq!(
+            ErrorKind::NotFound,
+            shell_execute_error(SE_ERR_NOASSOC).kind()
+        );
+        assert_eq!(ErrorKind::NotFound, shell_execute_error(2).kind());
+        assert_eq!(
+            Some(ERROR_SHARING_VIOLATION as i32),
+            shell_execute_error(SE_ERR_SHARE).raw_os_error()
+        );
+    }
+
+    #[test]
+    fn test_hresult_error() {
+        // E_ACCESSDENIED wraps ERROR_ACCESS_DENIED
+        assert_eq!(
+            ErrorKind::PermissionDenied,
+            hresult_error(0x8007_0005_u32 as HRESULT).kind()
+        );
+        // E_NOINTERFACE isn't a windows error
+        let error = hresult_error(0x8000_4002_u32 as HRESULT);
+        assert_eq!(None, error.raw_os_error());
+        assert_eq!("Failed with HRESULT 0x80004002", error.to_string());
+    }
+
     #[test]
     fn test_get_self_location() {
         let path = get_self_location().expect("Should never fail");
@@ -3,6 +3,8 @@ cargo +nightly fmt
 cargo build --release --workspace --exclude installer
 :: Now build installer
 cargo build --release --package installer
+:: Write the group policy templates next to the binaries
+.\target\release\wedge.exe policy .\target\release\policy
 :: Generate checksum file
 @echo off
 for /f "skip=1" %%a in (